@@ -0,0 +1,190 @@
+use crate::{TaskError, TaskResult};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_sdk::native_token::lamports_to_sol;
+use std::fmt;
+use std::time::Duration;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+// Failed transfers spelled out in one alert, the rest are only counted
+const MAX_LISTED_FAILURES: usize = 10;
+
+/// A chat or endpoint alerts are posted to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Webhook {
+    /// Discord channel webhook URL
+    Discord { url: String },
+    /// Slack incoming webhook URL
+    Slack { url: String },
+    /// Telegram bot posting to a chat
+    Telegram { bot_token: String, chat_id: String },
+    /// Any URL, receiving the alert as a JSON object
+    Generic { url: String },
+}
+
+/// The `alerts` section of the config.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AlertConfig {
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    /// Alert when the SOL sent by a single run goes over this many lamports
+    #[serde(default)]
+    pub large_run_lamports: Option<u64>,
+}
+
+/// A transfer an alert reports as failed.
+#[derive(Serialize, Debug, Clone)]
+pub struct FailedTransfer {
+    pub from: String,
+    pub to: String,
+    pub amount: u64,
+    pub error: String,
+}
+
+/// Something worth waking someone up for.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "alert", rename_all = "snake_case")]
+pub enum Alert {
+    /// Transfers that failed, the first few of them listed
+    TransfersFailed {
+        count: usize,
+        transfers: Vec<FailedTransfer>,
+    },
+    /// The Geyser stream dropped, and was given up on when `reconnected` is false
+    GeyserDisconnected { error: String, reconnected: bool },
+    /// A run sent more than the `large_run_lamports` threshold
+    LargeRun { lamports: u64, threshold: u64 },
+}
+
+impl Alert {
+    /// An alert listing `failures`, of which only the first few are kept.
+    pub fn transfers_failed(failures: Vec<FailedTransfer>) -> Self {
+        let count = failures.len();
+        let mut transfers = failures;
+        transfers.truncate(MAX_LISTED_FAILURES);
+        Alert::TransfersFailed { count, transfers }
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Alert::TransfersFailed { count, transfers } => {
+                write!(f, "{count} transfer(s) failed")?;
+                for transfer in transfers {
+                    write!(
+                        f,
+                        "\n- {} -> {}, {}: {}",
+                        transfer.from, transfer.to, transfer.amount, transfer.error
+                    )?;
+                }
+                match count.saturating_sub(transfers.len()) {
+                    0 => Ok(()),
+                    more => write!(f, "\n- and {more} more"),
+                }
+            }
+            Alert::GeyserDisconnected {
+                error,
+                reconnected: true,
+            } => write!(f, "Geyser stream dropped and was reconnected: {error}"),
+            Alert::GeyserDisconnected {
+                error,
+                reconnected: false,
+            } => write!(f, "Geyser stream lost, giving up: {error}"),
+            Alert::LargeRun {
+                lamports,
+                threshold,
+            } => write!(
+                f,
+                "run sent {} SOL, over the {} SOL alert threshold",
+                lamports_to_sol(*lamports),
+                lamports_to_sol(*threshold)
+            ),
+        }
+    }
+}
+
+/// Posts alerts to the webhooks of the config. Delivery failures are logged and never fail
+/// the task, which goes on whether or not anyone was told.
+pub struct Notifier {
+    /// Name of the task, prefixed to every message
+    task: &'static str,
+    webhooks: Vec<Webhook>,
+    large_run_lamports: Option<u64>,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn new(config: &AlertConfig, task: &'static str) -> TaskResult<Self> {
+        let client = reqwest::Client::builder()
+            .use_rustls_tls()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| TaskError::ConfigError(format!("alerts: {e}")))?;
+        Ok(Notifier {
+            task,
+            webhooks: config.webhooks.clone(),
+            large_run_lamports: config.large_run_lamports,
+            client,
+        })
+    }
+
+    /// An alert for a run that sent `lamports`, when that is over the threshold.
+    pub fn large_run(&self, lamports: u64) -> Option<Alert> {
+        let threshold = self.large_run_lamports?;
+        (lamports > threshold).then_some(Alert::LargeRun {
+            lamports,
+            threshold,
+        })
+    }
+
+    /// Posts `alert` to every webhook at once.
+    pub async fn notify(&self, alert: &Alert) {
+        let text = format!("[{}] {alert}", self.task);
+        let posts = self.webhooks.iter().map(|webhook| async {
+            if let Err(e) = self.post(webhook, alert, &text).await {
+                tracing::warn!(error = %e.without_url(), "cannot post alert to {}", kind(webhook));
+            }
+        });
+        join_all(posts).await;
+    }
+
+    async fn post(&self, webhook: &Webhook, alert: &Alert, text: &str) -> reqwest::Result<()> {
+        let (url, body) = match webhook {
+            Webhook::Discord { url } => (url.clone(), json!({ "content": text })),
+            Webhook::Slack { url } => (url.clone(), json!({ "text": text })),
+            Webhook::Telegram { bot_token, chat_id } => (
+                format!("https://api.telegram.org/bot{bot_token}/sendMessage"),
+                json!({ "chat_id": chat_id, "text": text }),
+            ),
+            Webhook::Generic { url } => {
+                let mut body = json!({ "task": self.task, "message": text });
+                if let (Value::Object(body), Ok(Value::Object(fields))) =
+                    (&mut body, serde_json::to_value(alert))
+                {
+                    body.extend(fields);
+                }
+                (url.clone(), body)
+            }
+        };
+        self.client
+            .post(url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+// Names the webhook in logs without its URL, which holds its secret
+fn kind(webhook: &Webhook) -> &'static str {
+    match webhook {
+        Webhook::Discord { .. } => "Discord",
+        Webhook::Slack { .. } => "Slack",
+        Webhook::Telegram { .. } => "Telegram",
+        Webhook::Generic { .. } => "webhook",
+    }
+}
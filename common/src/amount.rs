@@ -0,0 +1,252 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+const SOL_DECIMALS: u8 = 9;
+// Decimals a percentage may have, `12.3456%` at most
+const PERCENT_DECIMALS: u8 = 4;
+
+/// An amount as written in a config file or on the command line.
+///
+/// Integers are base units (lamports, or the smallest unit of a token), so existing configs
+/// keep their meaning. Numbers with a fractional part are whole units (SOL, or tokens when
+/// a mint is configured), and `"0.1 SOL"` / `"5000 lamports"` make the unit explicit.
+/// `"25%"` is a share of a balance that is only known when sending.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Amount {
+    Units(u64),
+    Decimal(String),
+    Sol(String),
+    Percent(String),
+}
+
+impl Amount {
+    /// Converts the amount to base units. `decimals` is the number of decimals of the
+    /// transferred token, or `None` for SOL.
+    pub fn to_units(&self, decimals: Option<u8>) -> Result<u64, String> {
+        match self {
+            Self::Units(units) => Ok(*units),
+            Self::Decimal(value) => parse_decimal(value, decimals.unwrap_or(SOL_DECIMALS)),
+            Self::Sol(value) => match decimals {
+                None => parse_decimal(value, SOL_DECIMALS),
+                Some(_) => Err(format!("{self} is given in SOL but a token is transferred")),
+            },
+            Self::Percent(_) => Err(format!("{self} is a share of a balance, not an amount")),
+        }
+    }
+
+    /// The part of `balance` a percentage stands for, rounded down. `None` for fixed amounts.
+    pub fn percent_of(&self, balance: u64) -> Option<u64> {
+        let Self::Percent(value) = self else {
+            return None;
+        };
+        let percent = parse_decimal(value, PERCENT_DECIMALS).ok()?;
+        let share =
+            u128::from(balance) * u128::from(percent) / (100 * 10u128.pow(PERCENT_DECIMALS.into()));
+        // At most 100% of a u64, checked when parsed
+        Some(share as u64)
+    }
+
+    pub fn to_lamports(&self) -> Result<u64, String> {
+        self.to_units(None)
+    }
+}
+
+/// Converts a decimal string to base units without going through floating point,
+/// rejecting values that would lose precision or overflow.
+fn parse_decimal(value: &str, decimals: u8) -> Result<u64, String> {
+    let (whole, fraction) = split_decimal(value)?;
+    let decimals = decimals as usize;
+    if fraction.len() > decimals && fraction[decimals..].chars().any(|c| c != '0') {
+        return Err(format!(
+            "`{value}` has more than {decimals} decimals and would lose precision"
+        ));
+    }
+    let fraction = &fraction[..fraction.len().min(decimals)];
+    let digits = format!("{whole}{fraction:0<decimals$}");
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    digits
+        .parse::<u64>()
+        .map_err(|_| format!("`{value}` is too large"))
+}
+
+// Splits a decimal string into its whole and fractional digits
+fn split_decimal(value: &str) -> Result<(&str, &str), String> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty()) || !all_digits(whole) || !all_digits(fraction) {
+        return Err(format!("`{value}` is not a valid amount"));
+    }
+    Ok((whole, fraction))
+}
+
+impl FromStr for Amount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let lower = s.to_ascii_lowercase();
+        if let Some(value) = s.strip_suffix('%') {
+            let value = value.trim();
+            let percent = parse_decimal(value, PERCENT_DECIMALS)
+                .map_err(|_| format!("`{s}` is not a valid percentage"))?;
+            if percent > 100 * 10u64.pow(PERCENT_DECIMALS.into()) {
+                return Err(format!("`{s}` is more than 100%"));
+            }
+            return Ok(Self::Percent(value.to_string()));
+        }
+        if let Some(value) = lower.strip_suffix("sol") {
+            let value = value.trim();
+            parse_decimal(value, SOL_DECIMALS)?;
+            return Ok(Self::Sol(value.to_string()));
+        }
+        if let Some(value) = lower.strip_suffix("lamports") {
+            let value = value.trim().replace('_', "");
+            return value
+                .parse()
+                .map(Self::Units)
+                .map_err(|_| format!("`{s}` is not a whole number of lamports"));
+        }
+        let value = s.replace('_', "");
+        if value.contains('.') {
+            split_decimal(&value)?;
+            Ok(Self::Decimal(value))
+        } else {
+            value
+                .parse()
+                .map(Self::Units)
+                .map_err(|_| format!("`{s}` is not a valid amount"))
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Units(units) => write!(f, "{units}"),
+            Self::Decimal(value) => write!(f, "{value}"),
+            Self::Sol(value) => write!(f, "{value} SOL"),
+            Self::Percent(value) => write!(f, "{value}%"),
+        }
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Units(units) => serializer.serialize_u64(*units),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Integer(u64),
+            Float(f64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Integer(units) => Ok(Self::Units(units)),
+            // Display gives the shortest exact representation without an exponent, but drops
+            // the fraction of whole numbers like `1.0`, which must still be read as SOL
+            Raw::Float(value) => {
+                let value = value.to_string();
+                match value.contains('.') {
+                    true => Amount::from_str(&value),
+                    false => Amount::from_str(&format!("{value}.0")),
+                }
+            }
+            Raw::Text(text) => Amount::from_str(&text),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_are_base_units() {
+        assert_eq!(Amount::from_str("5000"), Ok(Amount::Units(5000)));
+        assert_eq!(Amount::from_str("1_000_000"), Ok(Amount::Units(1_000_000)));
+        assert_eq!(Amount::from_str("5000 lamports"), Ok(Amount::Units(5000)));
+        assert_eq!(Amount::Units(42).to_units(Some(6)), Ok(42));
+    }
+
+    #[test]
+    fn decimals_are_whole_units() {
+        let amount = Amount::from_str("0.1").unwrap();
+        assert_eq!(amount, Amount::Decimal("0.1".to_string()));
+        assert_eq!(amount.to_lamports(), Ok(100_000_000));
+        assert_eq!(amount.to_units(Some(6)), Ok(100_000));
+        assert_eq!(
+            Amount::from_str(".5").unwrap().to_lamports(),
+            Ok(500_000_000)
+        );
+        assert_eq!(
+            Amount::from_str("2.").unwrap().to_lamports(),
+            Ok(2_000_000_000)
+        );
+    }
+
+    #[test]
+    fn sol_amounts() {
+        let amount = Amount::from_str("1.5 SOL").unwrap();
+        assert_eq!(amount, Amount::Sol("1.5".to_string()));
+        assert_eq!(amount.to_lamports(), Ok(1_500_000_000));
+        assert_eq!(
+            Amount::from_str("2sol").unwrap().to_lamports(),
+            Ok(2_000_000_000)
+        );
+        assert!(amount.to_units(Some(6)).is_err());
+    }
+
+    #[test]
+    fn percentages() {
+        let amount = Amount::from_str("25%").unwrap();
+        assert_eq!(amount, Amount::Percent("25".to_string()));
+        assert_eq!(amount.percent_of(1_000), Some(250));
+        assert_eq!(
+            Amount::from_str("12.5 %").unwrap().percent_of(1_001),
+            Some(125)
+        );
+        assert_eq!(
+            Amount::from_str("100%").unwrap().percent_of(u64::MAX),
+            Some(u64::MAX)
+        );
+        assert_eq!(Amount::Units(5).percent_of(1_000), None);
+        assert!(amount.to_lamports().is_err());
+    }
+
+    #[test]
+    fn invalid_amounts() {
+        for value in ["", ".", "abc", "1.2.3", "-1", "1e9", "0.1 lamports", "1,5"] {
+            assert!(Amount::from_str(value).is_err(), "{value:?} was accepted");
+        }
+        assert!(Amount::from_str("101%").is_err());
+        assert!(Amount::from_str("1.23456%").is_err());
+        assert!(Amount::from_str("0.0000000001 SOL").is_err());
+        assert!(Amount::from_str("18446744073709551616").is_err());
+        assert!(Amount::from_str("18446744073.709551616 SOL").is_err());
+        assert!(Amount::from_str("0.1234567")
+            .unwrap()
+            .to_units(Some(6))
+            .is_err());
+    }
+
+    #[test]
+    fn trailing_zeros_do_not_lose_precision() {
+        assert_eq!(parse_decimal("1.5000000000000", 9), Ok(1_500_000_000));
+        assert_eq!(parse_decimal("0.000", 0), Ok(0));
+        assert_eq!(split_decimal("12.34"), Ok(("12", "34")));
+    }
+}
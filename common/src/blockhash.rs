@@ -0,0 +1,67 @@
+use crate::TaskResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::hash::Hash;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+// Blocks are produced roughly every 400ms
+const BLOCK_TIME: Duration = Duration::from_millis(400);
+// The cached blockhash is replaced while this many blocks of its validity remain, so
+// transactions built from it still have time to land
+const REFRESH_MARGIN: u64 = 100;
+// How long to wait before trying again when a refresh fails
+const RETRY_BLOCKS: u64 = 5;
+
+/// A recent blockhash shared by every transaction of a run. It is fetched once and
+/// refreshed in the background before its `last_valid_block_height` is reached.
+pub struct BlockhashCache {
+    current: Arc<RwLock<Hash>>,
+    refresher: JoinHandle<()>,
+}
+
+impl BlockhashCache {
+    pub async fn new(client: Arc<RpcClient>) -> TaskResult<Self> {
+        let (blockhash, remaining) = fetch(&client).await?;
+        let current = Arc::new(RwLock::new(blockhash));
+        let refresher = tokio::spawn(refresh(client, current.clone(), remaining));
+
+        Ok(Self { current, refresher })
+    }
+
+    pub fn get(&self) -> Hash {
+        *self.current.read().unwrap()
+    }
+}
+
+impl Drop for BlockhashCache {
+    fn drop(&mut self) {
+        self.refresher.abort();
+    }
+}
+
+// Fetches the latest blockhash with the number of blocks it stays valid for
+async fn fetch(client: &RpcClient) -> TaskResult<(Hash, u64)> {
+    let (blockhash, last_valid_block_height) = client
+        .get_latest_blockhash_with_commitment(client.commitment())
+        .await?;
+    let block_height = client.get_block_height().await?;
+    Ok((
+        blockhash,
+        last_valid_block_height.saturating_sub(block_height),
+    ))
+}
+
+async fn refresh(client: Arc<RpcClient>, current: Arc<RwLock<Hash>>, mut remaining: u64) {
+    loop {
+        let blocks = remaining.saturating_sub(REFRESH_MARGIN).max(1);
+        tokio::time::sleep(BLOCK_TIME * blocks as u32).await;
+        match fetch(&client).await {
+            Ok((blockhash, blocks)) => {
+                *current.write().unwrap() = blockhash;
+                remaining = blocks;
+            }
+            Err(_) => remaining = REFRESH_MARGIN + RETRY_BLOCKS,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{TaskError, TaskResult};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey,
+};
+
+const DEFAULT_PRIORITY_FEE_PERCENTILE: u8 = 75;
+
+/// Compute unit price written either as a number of micro-lamports or as `auto`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputeUnitPrice {
+    MicroLamports(u64),
+    /// Estimated before each send from `getRecentPrioritizationFees`
+    Auto,
+}
+
+impl Serialize for ComputeUnitPrice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::MicroLamports(price) => serializer.serialize_u64(*price),
+            Self::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ComputeUnitPrice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(price) => Ok(Self::MicroLamports(price)),
+            Raw::Text(text) if text == "auto" => Ok(Self::Auto),
+            Raw::Text(text) => Err(serde::de::Error::custom(format!(
+                "invalid compute_unit_price `{text}`, expected a number or `auto`"
+            ))),
+        }
+    }
+}
+
+/// Priority fee settings shared by every task that sends transactions.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ComputeBudgetConfig {
+    /// Price of a compute unit in micro-lamports, or `auto`
+    #[serde(default)]
+    pub compute_unit_price: Option<ComputeUnitPrice>,
+    /// Maximum number of compute units the transaction may consume
+    #[serde(default)]
+    pub compute_unit_limit: Option<u32>,
+    /// Percentile of the recent prioritization fees used by the `auto` price, 75 by default
+    #[serde(default)]
+    pub priority_fee_percentile: Option<u8>,
+}
+
+impl ComputeBudgetConfig {
+    /// Returns `instructions` prefixed with the configured ComputeBudget instructions.
+    /// An `auto` price is estimated from the fees recently paid to lock the writable
+    /// accounts of `instructions`.
+    pub async fn apply(
+        &self,
+        client: &RpcClient,
+        instructions: Vec<Instruction>,
+    ) -> TaskResult<Vec<Instruction>> {
+        let mut budget = Vec::new();
+        if let Some(limit) = self.compute_unit_limit {
+            budget.push(ComputeBudgetInstruction::set_compute_unit_limit(limit));
+        }
+        let price = match self.compute_unit_price {
+            Some(ComputeUnitPrice::MicroLamports(price)) => Some(price),
+            Some(ComputeUnitPrice::Auto) => {
+                let accounts = writable_accounts(&instructions);
+                let percentile = self
+                    .priority_fee_percentile
+                    .unwrap_or(DEFAULT_PRIORITY_FEE_PERCENTILE);
+                Some(estimate_priority_fee(client, &accounts, percentile).await?)
+            }
+            None => None,
+        };
+        if let Some(price) = price {
+            budget.push(ComputeBudgetInstruction::set_compute_unit_price(price));
+        }
+        budget.extend(instructions);
+        Ok(budget)
+    }
+}
+
+fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut accounts: Vec<Pubkey> = Vec::new();
+    for meta in instructions.iter().flat_map(|ix| &ix.accounts) {
+        if meta.is_writable && !accounts.contains(&meta.pubkey) {
+            accounts.push(meta.pubkey);
+        }
+    }
+    accounts
+}
+
+/// Returns the given percentile of the prioritization fees paid in recent slots by
+/// transactions locking any of `accounts`.
+pub async fn estimate_priority_fee(
+    client: &RpcClient,
+    accounts: &[Pubkey],
+    percentile: u8,
+) -> TaskResult<u64> {
+    if percentile > 100 {
+        return Err(TaskError::ConfigError(format!(
+            "priority_fee_percentile must be between 0 and 100, got {percentile}"
+        )));
+    }
+    let mut fees: Vec<u64> = client
+        .get_recent_prioritization_fees(accounts)
+        .await?
+        .into_iter()
+        .map(|fee| fee.prioritization_fee)
+        .collect();
+    if fees.is_empty() {
+        return Ok(0);
+    }
+    fees.sort_unstable();
+    // Nearest-rank percentile
+    let rank = (fees.len() * percentile as usize).div_ceil(100).max(1);
+    Ok(fees[rank - 1])
+}
@@ -0,0 +1,421 @@
+use crate::{TaskError, TaskResult};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_yaml::Value;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Formats a config file can be written in, told apart by its extension, YAML when it has
+/// another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Yaml,
+    Toml,
+    Json,
+}
+
+impl Format {
+    fn of(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("toml") => Format::Toml,
+            Some("json") => Format::Json,
+            _ => Format::Yaml,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Format::Yaml => "YAML",
+            Format::Toml => "TOML",
+            Format::Json => "JSON",
+        }
+    }
+
+    // Function for parsing a document into the YAML value the settings are read from
+    fn parse(self, content: &str) -> Result<Value, String> {
+        match self {
+            Format::Yaml => serde_yaml::from_str(content).map_err(|e| e.to_string()),
+            Format::Toml => toml::from_str::<toml::Value>(content)
+                .map_err(|e| e.to_string())
+                .and_then(|value| serde_yaml::to_value(value).map_err(|e| e.to_string())),
+            Format::Json => serde_json::from_str::<serde_json::Value>(content)
+                .map_err(|e| e.to_string())
+                .and_then(|value| serde_yaml::to_value(value).map_err(|e| e.to_string())),
+        }
+    }
+
+    fn write(self, value: &Value) -> Result<String, String> {
+        match self {
+            Format::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+            Format::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            Format::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Reads and parses a config file, in YAML, TOML or JSON depending on its extension.
+/// `${VAR}` in any string value is replaced with the environment variable `VAR`, and `$$`
+/// stands for a literal `$`.
+///
+/// The file may hold named sets of settings under `profiles:`. The one named `profile` is
+/// laid over the top-level settings, nested maps merged key by key, and the others are
+/// ignored.
+pub fn load<T: DeserializeOwned>(path: &str, profile: Option<&str>) -> TaskResult<T> {
+    let format = Format::of(path);
+    let content = fs::read_to_string(path)
+        .map_err(|e| TaskError::ConfigError(format!("cannot read {path}: {e}")))?;
+    let mut value = format.parse(&content).map_err(|e| {
+        TaskError::ConfigError(format!("incorrect {} format in {path}: {e}", format.name()))
+    })?;
+    apply_profile(&mut value, profile, path)?;
+    let mut missing = Vec::new();
+    interpolate(&mut value, &mut missing)?;
+    if !missing.is_empty() {
+        missing.sort();
+        missing.dedup();
+        return Err(TaskError::ConfigError(format!(
+            "environment variables used in {path} are not set: {}",
+            missing.join(", ")
+        )));
+    }
+    serde_yaml::from_value(value).map_err(|e| {
+        TaskError::ConfigError(format!("incorrect {} format in {path}: {e}", format.name()))
+    })
+}
+
+/// Appends `items` to the top-level list `key` of the config file at `path`, adding the
+/// list when the file has none. YAML files are edited in place so their comments and
+/// layout are kept, TOML and JSON files are written anew.
+pub fn append_to_list(path: &str, key: &str, items: &[String]) -> TaskResult<()> {
+    let format = Format::of(path);
+    let content = fs::read_to_string(path)
+        .map_err(|e| TaskError::ConfigError(format!("cannot read {path}: {e}")))?;
+    let content = match format {
+        Format::Yaml => append_to_yaml_list(&content, key, items),
+        _ => append_to_value_list(format, &content, key, items),
+    }
+    .map_err(|e| TaskError::ConfigError(format!("cannot add to {key} in {path}: {e}")))?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+// Function for adding items to a list of a parsed document and writing it back
+fn append_to_value_list(
+    format: Format,
+    content: &str,
+    key: &str,
+    items: &[String],
+) -> Result<String, String> {
+    let mut value = format.parse(content)?;
+    let Value::Mapping(map) = &mut value else {
+        return Err("the file is not a map of settings".to_string());
+    };
+    let list = map
+        .entry(Value::from(key))
+        .or_insert_with(|| Value::Sequence(Vec::new()));
+    let Value::Sequence(list) = list else {
+        return Err("it is not a list".to_string());
+    };
+    list.extend(items.iter().map(|item| Value::from(item.as_str())));
+    format.write(&value)
+}
+
+// Function for adding items after the last item of a block list of a YAML document
+fn append_to_yaml_list(content: &str, key: &str, items: &[String]) -> Result<String, String> {
+    let quoted = |item: &String| {
+        serde_yaml::to_string(item)
+            .map(|item| item.trim_end().to_string())
+            .map_err(|e| e.to_string())
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let Some(start) = lines
+        .iter()
+        .position(|line| line.split_once(':').map(|(name, _)| name) == Some(key))
+    else {
+        let mut content = format!("{}\n{key}:\n", content.trim_end());
+        for item in items {
+            content.push_str(&format!("  - {}\n", quoted(item)?));
+        }
+        return Ok(content);
+    };
+    if !lines[start][key.len() + 1..].trim().is_empty() {
+        return Err("it is not a block list with one `- item` per line".to_string());
+    }
+    // The list goes on while lines are indented, blank or comments
+    let mut end = start + 1;
+    let mut indent = None;
+    for (index, line) in lines.iter().enumerate().skip(start + 1) {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        if trimmed.len() == line.len() && !trimmed.starts_with('-') {
+            break;
+        }
+        if trimmed.starts_with('-') && indent.is_none() {
+            indent = Some(&line[..line.len() - trimmed.len()]);
+        }
+        end = index + 1;
+    }
+    let indent = indent.unwrap_or("  ");
+    let mut result: Vec<String> = lines[..end].iter().map(|line| line.to_string()).collect();
+    for item in items {
+        result.push(format!("{indent}- {}", quoted(item)?));
+    }
+    result.extend(lines[end..].iter().map(|line| line.to_string()));
+    Ok(result.join("\n") + "\n")
+}
+
+// Function for replacing the `profiles` map of the document with the selected profile
+fn apply_profile(value: &mut Value, profile: Option<&str>, path: &str) -> TaskResult<()> {
+    let profiles = match value {
+        Value::Mapping(map) => map.remove("profiles"),
+        _ => None,
+    };
+    let Some(name) = profile else {
+        return Ok(());
+    };
+    let mut profiles = match profiles {
+        Some(Value::Mapping(profiles)) => profiles,
+        Some(_) => {
+            return Err(TaskError::ConfigError(format!(
+                "profiles in {path} must map profile names to settings"
+            )))
+        }
+        None => {
+            return Err(TaskError::ConfigError(format!(
+                "profile {name} requested but {path} has no profiles"
+            )))
+        }
+    };
+    let Some(overrides) = profiles.remove(name) else {
+        let names: Vec<_> = profiles.keys().filter_map(Value::as_str).collect();
+        return Err(TaskError::ConfigError(format!(
+            "no profile {name} in {path}, available: {}",
+            names.join(", ")
+        )));
+    };
+    merge(value, overrides);
+    Ok(())
+}
+
+// Function for laying `overrides` over `base`, maps merged recursively and anything else
+// replaced
+fn merge(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Mapping(base), Value::Mapping(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+// Function for substituting environment variables in every string of the document
+fn interpolate(value: &mut Value, missing: &mut Vec<String>) -> TaskResult<()> {
+    match value {
+        Value::String(text) => *text = substitute(text, missing)?,
+        Value::Sequence(items) => {
+            for item in items {
+                interpolate(item, missing)?;
+            }
+        }
+        Value::Mapping(map) => {
+            for (_, item) in map.iter_mut() {
+                interpolate(item, missing)?;
+            }
+        }
+        Value::Tagged(tagged) => interpolate(&mut tagged.value, missing)?,
+        Value::Null | Value::Bool(_) | Value::Number(_) => {}
+    }
+    Ok(())
+}
+
+fn substitute(text: &str, missing: &mut Vec<String>) -> TaskResult<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$$") {
+            result.push('$');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = after.find('}').ok_or_else(|| {
+                TaskError::ConfigError(format!("unterminated ${{ in config value {text:?}"))
+            })?;
+            let name = &after[..end];
+            match std::env::var(name) {
+                Ok(value) => result.push_str(&value),
+                Err(_) => missing.push(name.to_string()),
+            }
+            rest = &after[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Deserializes an optional duration written either as seconds or in a human readable
+/// form such as `30s` or `5m`.
+pub fn deserialize_optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Seconds(u64),
+        Text(String),
+    }
+
+    match Option::<Raw>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Raw::Seconds(seconds)) => Ok(Some(Duration::from_secs(seconds))),
+        Some(Raw::Text(text)) => humantime::parse_duration(&text)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// Picks the commitment given on the command line, then the one from the config file, then
+/// the default of the task.
+pub fn commitment(
+    cli: Option<CommitmentConfig>,
+    config: Option<CommitmentLevel>,
+    default: CommitmentLevel,
+) -> CommitmentConfig {
+    cli.unwrap_or(CommitmentConfig {
+        commitment: config.unwrap_or(default),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(text: &str) -> Value {
+        serde_yaml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn substitute_reads_the_environment() {
+        let path = std::env::var("PATH").unwrap();
+        let mut missing = Vec::new();
+        let text = substitute("a ${PATH} b", &mut missing).unwrap();
+        assert_eq!(text, format!("a {path} b"));
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn substitute_escapes_and_plain_dollars() {
+        let mut missing = Vec::new();
+        assert_eq!(substitute("$$5 and $5", &mut missing).unwrap(), "$5 and $5");
+        assert_eq!(
+            substitute("no variables", &mut missing).unwrap(),
+            "no variables"
+        );
+        assert!(missing.is_empty());
+    }
+
+    #[test]
+    fn substitute_reports_missing_and_unterminated() {
+        let mut missing = Vec::new();
+        let text = substitute("x${CONFIG_TEST_UNSET_VARIABLE}y", &mut missing).unwrap();
+        assert_eq!(text, "xy");
+        assert_eq!(missing, vec!["CONFIG_TEST_UNSET_VARIABLE".to_string()]);
+        assert!(substitute("${PATH", &mut missing).is_err());
+    }
+
+    #[test]
+    fn merge_overrides_recursively() {
+        let mut base = yaml("a: 1\nnested:\n  b: 2\n  c: 3\nlist: [1, 2]\n");
+        merge(
+            &mut base,
+            yaml("nested:\n  c: 4\n  d: 5\nlist: [3]\ne: 6\n"),
+        );
+        assert_eq!(
+            base,
+            yaml("a: 1\nnested:\n  b: 2\n  c: 4\n  d: 5\nlist: [3]\ne: 6\n")
+        );
+    }
+
+    #[test]
+    fn profile_is_laid_over_the_top_level() {
+        let mut value = yaml("a: 1\nprofiles:\n  dev:\n    a: 2\n");
+        apply_profile(&mut value, Some("dev"), "config.yaml").unwrap();
+        assert_eq!(value, yaml("a: 2\n"));
+
+        let mut value = yaml("a: 1\nprofiles:\n  dev:\n    a: 2\n");
+        apply_profile(&mut value, None, "config.yaml").unwrap();
+        assert_eq!(value, yaml("a: 1\n"));
+        let mut value = yaml("a: 1\nprofiles:\n  dev:\n    a: 2\n");
+        assert!(apply_profile(&mut value, Some("prod"), "config.yaml").is_err());
+    }
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(Format::of("config.toml"), Format::Toml);
+        assert_eq!(Format::of("config.JSON"), Format::Json);
+        assert_eq!(Format::of("config.yml"), Format::Yaml);
+        assert_eq!(Format::of("config"), Format::Yaml);
+    }
+
+    #[test]
+    fn every_format_parses_to_the_same_settings() {
+        let expected = yaml("amount: 5\nrecipients:\n  - a\n  - b\nsend:\n  jito: true\n");
+        let toml = "amount = 5\nrecipients = [\"a\", \"b\"]\n\n[send]\njito = true\n";
+        let json = r#"{"amount": 5, "recipients": ["a", "b"], "send": {"jito": true}}"#;
+        assert_eq!(Format::Toml.parse(toml).unwrap(), expected);
+        assert_eq!(Format::Json.parse(json).unwrap(), expected);
+        assert!(Format::Toml.parse("amount = ").is_err());
+    }
+
+    #[test]
+    fn append_to_yaml_list_keeps_comments() {
+        let content = "# keys\nlist:\n    - a # first\n\n    - b\nother: 1\n";
+        let items = ["c".to_string()];
+        assert_eq!(
+            append_to_yaml_list(content, "list", &items).unwrap(),
+            "# keys\nlist:\n    - a # first\n\n    - b\n    - c\nother: 1\n"
+        );
+    }
+
+    #[test]
+    fn append_to_yaml_list_adds_a_missing_list() {
+        let items = ["a".to_string(), "1".to_string()];
+        assert_eq!(
+            append_to_yaml_list("other: 1\n", "list", &items).unwrap(),
+            "other: 1\nlist:\n  - a\n  - '1'\n"
+        );
+    }
+
+    #[test]
+    fn append_to_yaml_list_rejects_flow_lists() {
+        let items = ["c".to_string()];
+        assert!(append_to_yaml_list("list: [a, b]\n", "list", &items).is_err());
+    }
+
+    #[test]
+    fn append_to_value_list_rewrites_toml_and_json() {
+        let items = ["c".to_string()];
+        let toml = append_to_value_list(Format::Toml, "list = [\"a\"]\n", "list", &items).unwrap();
+        assert_eq!(Format::Toml.parse(&toml).unwrap(), yaml("list: [a, c]\n"));
+        let json = append_to_value_list(Format::Json, "{}", "list", &items).unwrap();
+        assert_eq!(Format::Json.parse(&json).unwrap(), yaml("list: [c]\n"));
+    }
+}
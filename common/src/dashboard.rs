@@ -0,0 +1,411 @@
+use crate::{TaskError, TaskResult};
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Time between two redraws, also the longest a key press waits to be handled
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+/// Time between two balance lookups of the watched wallets
+const BALANCE_INTERVAL: Duration = Duration::from_secs(5);
+/// Confirmations and failures kept for display
+const HISTORY_LEN: usize = 100;
+/// Seconds without a Geyser update after which the stream is shown as stalled
+const STREAM_STALL_SECS: u64 = 10;
+/// Exit code when the dashboard is quit before the run is over, as for Ctrl+C
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+enum Event {
+    Sending {
+        from: String,
+        to: String,
+        amount: u64,
+    },
+    Confirmed {
+        from: String,
+        to: String,
+        amount: u64,
+        signature: String,
+        latency: Duration,
+    },
+    Failed {
+        from: String,
+        to: String,
+        error: String,
+    },
+    Balance {
+        address: String,
+        lamports: u64,
+    },
+    Stream {
+        slot: u64,
+        reconnects: u64,
+    },
+}
+
+/// Live terminal view of a run. Clones feed the same view; updates sent after the
+/// dashboard was closed are dropped.
+#[derive(Clone, Debug)]
+pub struct Dashboard {
+    events: mpsc::Sender<Event>,
+}
+
+/// Restores the terminal when dropped.
+pub struct DashboardGuard {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for DashboardGuard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Dashboard {
+    /// Takes over the terminal and draws the dashboard on a thread of its own until the
+    /// guard is dropped. `q` or Ctrl+C quit the whole process.
+    pub fn start(title: &str) -> TaskResult<(Self, DashboardGuard)> {
+        let (events, received) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut terminal = ratatui::try_init()
+            .map_err(|e| TaskError::ConfigError(format!("cannot start the dashboard: {e}")))?;
+        let mut state = State::new(title);
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                while !stop.load(Ordering::Relaxed) {
+                    while let Ok(event) = received.try_recv() {
+                        state.apply(event);
+                    }
+                    let _ = terminal.draw(|frame| state.render(frame));
+                    if quit_requested() {
+                        ratatui::restore();
+                        std::process::exit(INTERRUPTED_EXIT_CODE);
+                    }
+                }
+                let _ = terminal.draw(|frame| state.render(frame));
+                ratatui::restore();
+            }
+        });
+        Ok((
+            Self { events },
+            DashboardGuard {
+                stop,
+                thread: Some(thread),
+            },
+        ))
+    }
+
+    fn send(&self, event: Event) -> bool {
+        self.events.send(event).is_ok()
+    }
+
+    /// A transfer was sent and waits for its confirmation.
+    pub fn sending(&self, from: &str, to: &str, amount: u64) {
+        self.send(Event::Sending {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+        });
+    }
+
+    pub fn confirmed(&self, from: &str, to: &str, amount: u64, signature: &str, latency: Duration) {
+        self.send(Event::Confirmed {
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+            signature: signature.to_string(),
+            latency,
+        });
+    }
+
+    pub fn failed(&self, from: &str, to: &str, error: &str) {
+        self.send(Event::Failed {
+            from: from.to_string(),
+            to: to.to_string(),
+            error: error.to_string(),
+        });
+    }
+
+    /// The Geyser stream delivered an update of `slot`, after `reconnects` reconnections.
+    pub fn stream_update(&self, slot: u64, reconnects: u64) {
+        self.send(Event::Stream { slot, reconnects });
+    }
+
+    /// Polls the SOL balances of `accounts` in the background while the dashboard is open.
+    pub fn watch_balances(&self, client: Arc<RpcClient>, accounts: Vec<Pubkey>) {
+        let dashboard = self.clone();
+        tokio::spawn(async move {
+            loop {
+                for account in &accounts {
+                    let Ok(lamports) = client.get_balance(account).await else {
+                        continue;
+                    };
+                    let address = account.to_string();
+                    if !dashboard.send(Event::Balance { address, lamports }) {
+                        return;
+                    }
+                }
+                tokio::time::sleep(BALANCE_INTERVAL).await;
+            }
+        });
+    }
+}
+
+// Waits up to one refresh interval for a key press, true for `q` and Ctrl+C
+fn quit_requested() -> bool {
+    if !event::poll(REFRESH_INTERVAL).unwrap_or(false) {
+        return false;
+    }
+    let Ok(TermEvent::Key(key)) = event::read() else {
+        return false;
+    };
+    key.kind == KeyEventKind::Press
+        && (key.code == KeyCode::Char('q')
+            || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)))
+}
+
+struct InFlight {
+    from: String,
+    to: String,
+    amount: u64,
+    since: Instant,
+}
+
+struct StreamHealth {
+    slot: u64,
+    reconnects: u64,
+    updated: Instant,
+}
+
+struct State {
+    title: String,
+    started: Instant,
+    in_flight: Vec<InFlight>,
+    confirmed: VecDeque<(String, u64, String, Duration)>,
+    failures: VecDeque<(String, String)>,
+    succeeded: u64,
+    failed: u64,
+    balances: BTreeMap<String, u64>,
+    stream: Option<StreamHealth>,
+}
+
+impl State {
+    fn new(title: &str) -> Self {
+        State {
+            title: title.to_string(),
+            started: Instant::now(),
+            in_flight: Vec::new(),
+            confirmed: VecDeque::new(),
+            failures: VecDeque::new(),
+            succeeded: 0,
+            failed: 0,
+            balances: BTreeMap::new(),
+            stream: None,
+        }
+    }
+
+    // Removes the oldest in-flight transfer between `from` and `to`
+    fn land(&mut self, from: &str, to: &str) {
+        if let Some(i) = self
+            .in_flight
+            .iter()
+            .position(|transfer| transfer.from == from && transfer.to == to)
+        {
+            self.in_flight.remove(i);
+        }
+    }
+
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::Sending { from, to, amount } => self.in_flight.push(InFlight {
+                from,
+                to,
+                amount,
+                since: Instant::now(),
+            }),
+            Event::Confirmed {
+                from,
+                to,
+                amount,
+                signature,
+                latency,
+            } => {
+                self.land(&from, &to);
+                self.succeeded += 1;
+                self.confirmed.push_front((to, amount, signature, latency));
+                self.confirmed.truncate(HISTORY_LEN);
+            }
+            Event::Failed { from, to, error } => {
+                self.land(&from, &to);
+                self.failed += 1;
+                self.failures.push_front((to, error));
+                self.failures.truncate(HISTORY_LEN);
+            }
+            Event::Balance { address, lamports } => {
+                self.balances.insert(address, lamports);
+            }
+            Event::Stream { slot, reconnects } => {
+                self.stream = Some(StreamHealth {
+                    slot,
+                    reconnects,
+                    updated: Instant::now(),
+                })
+            }
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [header, body] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)])
+                .areas(body);
+        let [in_flight, confirmed] =
+            Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(left);
+        let stream_height = if self.stream.is_some() { 5 } else { 0 };
+        let [failures, balances, stream] = Layout::vertical([
+            Constraint::Percentage(50),
+            Constraint::Min(0),
+            Constraint::Length(stream_height),
+        ])
+        .areas(right);
+
+        self.render_header(frame, header);
+        self.render_in_flight(frame, in_flight);
+        self.render_confirmed(frame, confirmed);
+        self.render_failures(frame, failures);
+        self.render_balances(frame, balances);
+        if let Some(health) = &self.stream {
+            render_stream(frame, stream, health);
+        }
+    }
+
+    fn render_header(&self, frame: &mut Frame, area: Rect) {
+        let line = Line::from(vec![
+            format!("running {}s   ", self.started.elapsed().as_secs()).into(),
+            format!("{} in flight   ", self.in_flight.len()).yellow(),
+            format!("{} confirmed   ", self.succeeded).green(),
+            format!("{} failed   ", self.failed).red(),
+            "q to quit".dark_gray(),
+        ]);
+        let title = format!(" {} ", self.title);
+        frame.render_widget(
+            Paragraph::new(line).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+
+    fn render_in_flight(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.in_flight.iter().map(|transfer| {
+            Row::new(vec![
+                short(&transfer.from),
+                short(&transfer.to),
+                transfer.amount.to_string(),
+                format!("{}s", transfer.since.elapsed().as_secs()),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Min(10),
+                Constraint::Length(6),
+            ],
+        )
+        .header(Row::new(["From", "To", "Amount", "Age"]).bold())
+        .block(Block::bordered().title(" In flight "));
+        frame.render_widget(table, area);
+    }
+
+    fn render_confirmed(&self, frame: &mut Frame, area: Rect) {
+        let rows = self
+            .confirmed
+            .iter()
+            .map(|(to, amount, signature, latency)| {
+                Row::new(vec![
+                    short(to),
+                    amount.to_string(),
+                    format!("{}ms", latency.as_millis()),
+                    signature.clone(),
+                ])
+            });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(14),
+                Constraint::Length(9),
+                Constraint::Min(20),
+            ],
+        )
+        .header(Row::new(["To", "Amount", "Latency", "Signature"]).bold())
+        .block(Block::bordered().title(" Recent confirmations "));
+        frame.render_widget(table, area);
+    }
+
+    fn render_failures(&self, frame: &mut Frame, area: Rect) {
+        let rows = self
+            .failures
+            .iter()
+            .map(|(to, error)| Row::new(vec![short(to), error.clone()]));
+        let table = Table::new(rows, [Constraint::Length(12), Constraint::Min(20)])
+            .header(Row::new(["To", "Error"]).bold())
+            .style(Style::new().fg(Color::Red))
+            .block(Block::bordered().title(" Failures "));
+        frame.render_widget(table, area);
+    }
+
+    fn render_balances(&self, frame: &mut Frame, area: Rect) {
+        let rows = self.balances.iter().map(|(address, lamports)| {
+            Row::new(vec![
+                address.clone(),
+                format!("{:.9}", lamports_to_sol(*lamports)),
+            ])
+        });
+        let table = Table::new(rows, [Constraint::Min(44), Constraint::Length(16)])
+            .header(Row::new(["Wallet", "SOL"]).bold())
+            .block(Block::bordered().title(" Balances "));
+        frame.render_widget(table, area);
+    }
+}
+
+fn render_stream(frame: &mut Frame, area: Rect, health: &StreamHealth) {
+    let age = health.updated.elapsed().as_secs();
+    let status = if age < STREAM_STALL_SECS {
+        "healthy".green()
+    } else {
+        "stalled".red()
+    };
+    let lines = vec![
+        Line::from(vec!["Status ".into(), status]),
+        Line::from(format!("Last slot {}, {}s ago", health.slot, age)),
+        Line::from(format!("Reconnects {}", health.reconnects)),
+    ];
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(" Geyser stream ")),
+        area,
+    );
+}
+
+// Shortens an address to its first and last characters
+fn short(address: &str) -> String {
+    if address.len() <= 11 {
+        return address.to_string();
+    }
+    format!("{}..{}", &address[..4], &address[address.len() - 4..])
+}
@@ -0,0 +1,205 @@
+use crate::{TaskError, TaskResult};
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS transfers (
+    id INTEGER PRIMARY KEY,
+    recorded_at TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    memo TEXT,
+    signature TEXT,
+    fee_lamports INTEGER,
+    compute_units INTEGER,
+    slot INTEGER,
+    block_time INTEGER,
+    duration_ms INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS balances (
+    id INTEGER PRIMARY KEY,
+    recorded_at TEXT NOT NULL,
+    address TEXT NOT NULL,
+    lamports INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS token_balances (
+    id INTEGER PRIMARY KEY,
+    recorded_at TEXT NOT NULL,
+    address TEXT NOT NULL,
+    token_account TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    ui_amount TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transfers_sender ON transfers (sender, recorded_at);
+CREATE INDEX IF NOT EXISTS balances_address ON balances (address, recorded_at);
+";
+
+/// The outcome of one transfer, stored in the `transfers` table.
+#[derive(Debug, Clone, Copy)]
+pub struct TransferRecord<'a> {
+    pub sender: &'a str,
+    pub recipient: &'a str,
+    pub amount: u64,
+    pub memo: Option<&'a str>,
+    pub signature: Option<&'a str>,
+    pub fee_lamports: Option<u64>,
+    pub compute_units: Option<u64>,
+    pub slot: Option<u64>,
+    /// Unix timestamp of the block the transaction landed in
+    pub block_time: Option<i64>,
+    pub duration_ms: u128,
+    /// `ok`, `proposed`, `failed`, `skipped` or `unknown`
+    pub status: &'a str,
+    pub error: Option<&'a str>,
+}
+
+/// The balance of one wallet, stored in the `balances` table, with its token accounts in
+/// `token_balances`.
+#[derive(Debug, Clone)]
+pub struct BalanceRecord<'a> {
+    pub address: &'a str,
+    pub lamports: u64,
+    pub tokens: Vec<TokenRecord<'a>>,
+}
+
+/// The last recorded balance of a wallet.
+#[derive(Debug, Clone)]
+pub struct BalanceSnapshot {
+    /// RFC 3339 time of the snapshot
+    pub recorded_at: String,
+    pub lamports: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TokenRecord<'a> {
+    pub token_account: &'a str,
+    pub mint: &'a str,
+    pub ui_amount: &'a str,
+}
+
+/// SQLite database keeping the transfers and balance snapshots of every run, for queries
+/// across runs. Rows are stamped with the RFC 3339 time they were inserted at.
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database at `path`, creating it and its tables when missing.
+    pub fn open(path: &str) -> TaskResult<Self> {
+        let connection = Connection::open(path).map_err(|e| database_error(path, e))?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|e| database_error(path, e))?;
+        Ok(Database { connection })
+    }
+
+    /// Inserts the outcomes of a run in a single SQLite transaction.
+    pub fn insert_transfers(&mut self, records: &[TransferRecord]) -> TaskResult<()> {
+        let recorded_at = now();
+        let tx = self.connection.transaction().map_err(sql_error)?;
+        {
+            let mut insert = tx
+                .prepare(
+                    "INSERT INTO transfers (recorded_at, sender, recipient, amount, memo,
+                     signature, fee_lamports, compute_units, slot, block_time, duration_ms,
+                     status, error) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                )
+                .map_err(sql_error)?;
+            for record in records {
+                insert
+                    .execute(params![
+                        recorded_at,
+                        record.sender,
+                        record.recipient,
+                        record.amount,
+                        record.memo,
+                        record.signature,
+                        record.fee_lamports,
+                        record.compute_units,
+                        record.slot,
+                        record.block_time,
+                        record.duration_ms as u64,
+                        record.status,
+                        record.error,
+                    ])
+                    .map_err(sql_error)?;
+            }
+        }
+        tx.commit().map_err(sql_error)
+    }
+
+    /// Inserts a snapshot of wallet balances in a single SQLite transaction.
+    pub fn insert_balances(&mut self, records: &[BalanceRecord]) -> TaskResult<()> {
+        let recorded_at = now();
+        let tx = self.connection.transaction().map_err(sql_error)?;
+        {
+            let mut balance = tx
+                .prepare(
+                    "INSERT INTO balances (recorded_at, address, lamports) VALUES (?1, ?2, ?3)",
+                )
+                .map_err(sql_error)?;
+            let mut token = tx
+                .prepare(
+                    "INSERT INTO token_balances (recorded_at, address, token_account, mint,
+                     ui_amount) VALUES (?1, ?2, ?3, ?4, ?5)",
+                )
+                .map_err(sql_error)?;
+            for record in records {
+                balance
+                    .execute(params![recorded_at, record.address, record.lamports])
+                    .map_err(sql_error)?;
+                for tokens in &record.tokens {
+                    token
+                        .execute(params![
+                            recorded_at,
+                            record.address,
+                            tokens.token_account,
+                            tokens.mint,
+                            tokens.ui_amount,
+                        ])
+                        .map_err(sql_error)?;
+                }
+            }
+        }
+        tx.commit().map_err(sql_error)
+    }
+
+    /// The most recent balance snapshot of every address that has one.
+    pub fn latest_balances(&self) -> TaskResult<HashMap<String, BalanceSnapshot>> {
+        let mut query = self
+            .connection
+            .prepare(
+                "SELECT address, recorded_at, lamports FROM balances
+                 WHERE id IN (SELECT MAX(id) FROM balances GROUP BY address)",
+            )
+            .map_err(sql_error)?;
+        let rows = query
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    BalanceSnapshot {
+                        recorded_at: row.get(1)?,
+                        lamports: row.get(2)?,
+                    },
+                ))
+            })
+            .map_err(sql_error)?;
+        rows.collect::<Result<_, _>>().map_err(sql_error)
+    }
+}
+
+fn now() -> String {
+    humantime::format_rfc3339_seconds(SystemTime::now()).to_string()
+}
+
+fn database_error(path: &str, e: rusqlite::Error) -> TaskError {
+    TaskError::DatabaseError(format!("{path}: {e}"))
+}
+
+fn sql_error(e: rusqlite::Error) -> TaskError {
+    TaskError::DatabaseError(e.to_string())
+}
@@ -0,0 +1,112 @@
+use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
+    rpc_request::RpcError,
+};
+use solana_sdk::{signature::Signature, transaction::TransactionError};
+use std::process::ExitCode;
+use thiserror::Error;
+
+pub type TaskResult<T> = Result<T, TaskError>;
+
+/// Every failure a task can end with. Each variant maps to its own process exit code,
+/// so scripts can tell a broken config apart from a flaky RPC endpoint.
+#[derive(Debug, Error)]
+pub enum TaskError {
+    #[error("config error: {0}")]
+    ConfigError(String),
+    #[error("invalid key: {0}")]
+    KeyError(String),
+    #[error("RPC error: {0}")]
+    RpcError(Box<ClientError>),
+    #[error("transaction {0} was not confirmed in time")]
+    ConfirmationTimeout(Signature),
+    #[error("transaction {0} expired before landing")]
+    Expired(Signature),
+    #[error("cannot build transaction: {0}")]
+    BuildError(String),
+    #[error("transaction failed: {0}")]
+    TransactionError(#[from] TransactionError),
+    #[error("Geyser error: {0}")]
+    GeyserError(String),
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    DatabaseError(String),
+    #[error("interrupted")]
+    Interrupted,
+}
+
+impl TaskError {
+    /// Classifies an error returned by `send_and_confirm_transaction`, telling a
+    /// confirmation timeout apart from other RPC failures.
+    pub fn from_send_error(err: ClientError, signature: Signature) -> Self {
+        match err.kind() {
+            ClientErrorKind::RpcError(RpcError::ForUser(msg))
+                if msg.starts_with("unable to confirm transaction") =>
+            {
+                Self::ConfirmationTimeout(signature)
+            }
+            _ => Self::from(err),
+        }
+    }
+
+    /// A copy of the error for every transfer of a batch that failed as a whole. RPC and
+    /// I/O errors keep their message but not their inner details, which cannot be cloned.
+    pub fn duplicate(&self) -> Self {
+        match self {
+            Self::ConfigError(msg) => Self::ConfigError(msg.clone()),
+            Self::KeyError(msg) => Self::KeyError(msg.clone()),
+            Self::RpcError(err) => {
+                Self::from(ClientError::from(ClientErrorKind::Custom(err.to_string())))
+            }
+            Self::ConfirmationTimeout(signature) => Self::ConfirmationTimeout(*signature),
+            Self::Expired(signature) => Self::Expired(*signature),
+            Self::BuildError(msg) => Self::BuildError(msg.clone()),
+            Self::TransactionError(err) => Self::TransactionError(err.clone()),
+            Self::GeyserError(msg) => Self::GeyserError(msg.clone()),
+            Self::IoError(err) => Self::IoError(std::io::Error::new(err.kind(), err.to_string())),
+            Self::DatabaseError(msg) => Self::DatabaseError(msg.clone()),
+            Self::Interrupted => Self::Interrupted,
+        }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::ConfigError(_) => 2,
+            Self::KeyError(_) => 3,
+            Self::RpcError(_) => 4,
+            Self::ConfirmationTimeout(_) => 5,
+            Self::TransactionError(_) => 6,
+            Self::GeyserError(_) => 7,
+            Self::IoError(_) => 8,
+            Self::BuildError(_) => 9,
+            Self::DatabaseError(_) => 10,
+            Self::Expired(_) => 11,
+            // Like a process killed by SIGINT
+            Self::Interrupted => 130,
+        }
+    }
+}
+
+impl From<ClientError> for TaskError {
+    fn from(err: ClientError) -> Self {
+        Self::RpcError(Box::new(err))
+    }
+}
+
+impl From<TaskError> for ExitCode {
+    fn from(err: TaskError) -> Self {
+        ExitCode::from(err.exit_code())
+    }
+}
+
+/// Prints the error of a finished run to stderr and turns the result into an exit code.
+pub fn exit(result: TaskResult<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            err.into()
+        }
+    }
+}
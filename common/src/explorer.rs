@@ -0,0 +1,71 @@
+use crate::output::percent_encode;
+use crate::rpc::Network;
+use serde::{Deserialize, Serialize};
+
+/// Block explorer the printed signatures and addresses link to.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Explorer {
+    /// solscan.io
+    #[default]
+    Solscan,
+    /// explorer.solana.com
+    SolanaExplorer,
+    /// No links
+    None,
+}
+
+/// Makes explorer URLs for the cluster of an RPC endpoint.
+#[derive(Debug, Clone)]
+pub struct ExplorerLinks {
+    base: &'static str,
+    /// Query string selecting the cluster, empty for mainnet-beta
+    query: String,
+}
+
+impl ExplorerLinks {
+    /// Links to `explorer` for the cluster `rpc_url` belongs to, or `None` when links are
+    /// disabled. Endpoints that are not the public ones of a cluster are taken for
+    /// mainnet-beta unless their URL names devnet, testnet or this machine.
+    pub fn new(explorer: Explorer, rpc_url: &str) -> Option<Self> {
+        let base = match explorer {
+            Explorer::Solscan => "https://solscan.io",
+            Explorer::SolanaExplorer => "https://explorer.solana.com",
+            Explorer::None => return None,
+        };
+        let query = match cluster(rpc_url) {
+            Network::MainnetBeta => String::new(),
+            Network::Devnet => "?cluster=devnet".to_string(),
+            Network::Testnet => "?cluster=testnet".to_string(),
+            Network::Localnet => format!("?cluster=custom&customUrl={}", percent_encode(rpc_url)),
+        };
+        Some(ExplorerLinks { base, query })
+    }
+
+    pub fn transaction(&self, signature: &str) -> String {
+        format!("{}/tx/{signature}{}", self.base, self.query)
+    }
+
+    pub fn account(&self, address: &str) -> String {
+        format!("{}/account/{address}{}", self.base, self.query)
+    }
+}
+
+fn cluster(rpc_url: &str) -> Network {
+    let preset = [Network::Devnet, Network::Testnet, Network::Localnet]
+        .into_iter()
+        .find(|network| network.rpc_url() == rpc_url);
+    if let Some(network) = preset {
+        return network;
+    }
+    let url = rpc_url.to_ascii_lowercase();
+    if url.contains("devnet") {
+        Network::Devnet
+    } else if url.contains("testnet") {
+        Network::Testnet
+    } else if url.contains("localhost") || url.contains("127.0.0.1") {
+        Network::Localnet
+    } else {
+        Network::MainnetBeta
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{TaskError, TaskResult};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_sdk::{
+    instruction::Instruction, pubkey::Pubkey, system_instruction, transaction::VersionedTransaction,
+};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Smallest tip the block engine accepts
+pub const MIN_TIP_LAMPORTS: u64 = 1_000;
+const BLOCK_ENGINE_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn default_block_engine_url() -> String {
+    "https://mainnet.block-engine.jito.wtf".to_string()
+}
+
+/// The `jito` part of the `send` section: transactions go to a Jito block engine as
+/// bundles, each paying a tip to a Jito tip account, instead of to the RPC node.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JitoConfig {
+    #[serde(default = "default_block_engine_url")]
+    pub block_engine_url: String,
+    /// UUID sent as `x-jito-auth`, for block engines that require one
+    #[serde(default)]
+    pub auth_token: Option<String>,
+    /// One of the tip accounts listed by `getTipAccounts`
+    pub tip_account: String,
+    pub tip_lamports: u64,
+}
+
+impl JitoConfig {
+    /// The tip account and amount, checked against what the block engine accepts.
+    pub fn tip(&self) -> TaskResult<(Pubkey, u64)> {
+        let account = Pubkey::from_str(&self.tip_account)
+            .map_err(|e| TaskError::KeyError(format!("Jito tip account: {e}")))?;
+        if self.tip_lamports < MIN_TIP_LAMPORTS {
+            return Err(TaskError::ConfigError(format!(
+                "Jito tip_lamports must be at least {MIN_TIP_LAMPORTS}"
+            )));
+        }
+        Ok((account, self.tip_lamports))
+    }
+
+    /// Submits transactions as a single bundle, landing all of them in order or none, and
+    /// returns the bundle id.
+    #[tracing::instrument(name = "send_bundle", skip_all, fields(url = %self.block_engine_url))]
+    pub async fn send_bundle(&self, txs: &[&VersionedTransaction]) -> TaskResult<String> {
+        let mut encoded = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let wire = bincode::serialize(tx).map_err(|e| TaskError::BuildError(e.to_string()))?;
+            encoded.push(BASE64_STANDARD.encode(wire));
+        }
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sendBundle",
+            "params": [encoded, {"encoding": "base64"}],
+        });
+        let url = format!(
+            "{}/api/v1/bundles",
+            self.block_engine_url.trim_end_matches('/')
+        );
+        let mut post = http_client()?.post(&url).json(&request);
+        if let Some(token) = &self.auth_token {
+            post = post.header("x-jito-auth", token);
+        }
+        let response: Value = post
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| block_engine_error(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| block_engine_error(format!("invalid response: {e}")))?;
+        if let Some(error) = response.get("error") {
+            return Err(block_engine_error(error.to_string()));
+        }
+        response["result"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| block_engine_error(format!("invalid response: {response}")))
+    }
+}
+
+/// Pays `lamports` from `payer` to the Jito tip account.
+pub fn tip_instruction(payer: &Pubkey, (account, lamports): (Pubkey, u64)) -> Instruction {
+    system_instruction::transfer(payer, &account, lamports)
+}
+
+// One HTTP client for every bundle of the run
+fn http_client() -> TaskResult<&'static reqwest::Client> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = reqwest::Client::builder()
+        .use_rustls_tls()
+        .timeout(BLOCK_ENGINE_TIMEOUT)
+        .build()
+        .map_err(|e| block_engine_error(e.to_string()))?;
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+fn block_engine_error(message: String) -> TaskError {
+    ClientError::from(ClientErrorKind::Custom(format!(
+        "Jito block engine: {message}"
+    )))
+    .into()
+}
@@ -0,0 +1,161 @@
+use crate::keystore::{self, Keystore};
+use crate::signer::{LedgerSigner, RemoteSigner, TlsConfig, TxSigner};
+use serde::{Deserialize, Serialize};
+use solana_sdk::derivation_path::DerivationPath;
+use solana_sdk::signature::{
+    generate_seed_from_seed_phrase_and_passphrase, keypair_from_seed_and_derivation_path,
+    keypair_from_seed_phrase_and_passphrase,
+};
+use solana_sdk::{bs58, signature::Keypair};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Where a private key comes from. In a config file it is either the 64 keypair bytes, the
+/// path to a solana-keygen JSON keypair file or an encrypted keystore, a base58 encoded
+/// secret key, or a BIP39 seed phrase with an optional derivation path. A Ledger or a
+/// remote signing service can sign but never hand out the key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum KeySource {
+    Bytes(Vec<u8>),
+    Ledger {
+        /// Device locator such as `usb://ledger`
+        ledger: String,
+        #[serde(default)]
+        derivation_path: Option<String>,
+    },
+    Remote {
+        /// URL of the signing service
+        remote_signer: String,
+        pubkey: String,
+        #[serde(default)]
+        tls: TlsConfig,
+    },
+    Mnemonic {
+        /// The seed phrase, or `prompt` to read it from the terminal
+        mnemonic: String,
+        #[serde(default)]
+        derivation_path: Option<String>,
+        #[serde(default)]
+        passphrase: Option<String>,
+    },
+    Text(String),
+}
+
+impl KeySource {
+    pub fn keypair(&self) -> Result<Keypair, String> {
+        match self {
+            Self::Bytes(bytes) => keypair_from_bytes(bytes),
+            Self::Ledger { .. } | Self::Remote { .. } => {
+                Err("this key cannot be read, only used to sign transfers".to_string())
+            }
+            Self::Mnemonic {
+                mnemonic,
+                derivation_path,
+                passphrase,
+            } => keypair_from_mnemonic(
+                mnemonic,
+                derivation_path.as_deref(),
+                passphrase.as_deref().unwrap_or_default(),
+            ),
+            Self::Text(text) => keypair_from_text(text),
+        }
+    }
+
+    /// Opens the key for signing, connecting to the device if the key is on a Ledger.
+    pub fn signer(&self) -> Result<Arc<dyn TxSigner>, String> {
+        match self {
+            Self::Ledger {
+                ledger,
+                derivation_path,
+            } => Ok(Arc::new(LedgerSigner::connect(
+                ledger,
+                derivation_path.as_deref(),
+            )?)),
+            Self::Remote {
+                remote_signer,
+                pubkey,
+                tls,
+            } => Ok(Arc::new(RemoteSigner::new(remote_signer, pubkey, tls)?)),
+            _ => Ok(Arc::new(self.keypair()?)),
+        }
+    }
+}
+
+/// Derives a keypair from a BIP39 seed phrase the same way solana-keygen does. Without a
+/// derivation path the first 32 bytes of the seed are used, as `solana-keygen recover` does;
+/// with one (e.g. `m/44'/501'/0'/0'`) the key is derived by hardened BIP32 derivation.
+/// A phrase of `prompt` is read from the terminal without echo.
+pub fn keypair_from_mnemonic(
+    phrase: &str,
+    derivation_path: Option<&str>,
+    passphrase: &str,
+) -> Result<Keypair, String> {
+    let phrase = if phrase.trim() == "prompt" {
+        rpassword::prompt_password("Seed phrase: ")
+            .map_err(|e| format!("cannot read seed phrase: {e}"))?
+    } else {
+        phrase.to_string()
+    };
+    let mnemonic = bip39::Mnemonic::parse_normalized(phrase.trim())
+        .map_err(|e| format!("invalid seed phrase: {e}"))?
+        .to_string();
+    match derivation_path {
+        Some(path) => {
+            let path = DerivationPath::from_absolute_path_str(path)
+                .map_err(|e| format!("invalid derivation path {path}: {e}"))?;
+            let seed = generate_seed_from_seed_phrase_and_passphrase(&mnemonic, passphrase);
+            keypair_from_seed_and_derivation_path(&seed, Some(path))
+        }
+        None => keypair_from_seed_phrase_and_passphrase(&mnemonic, passphrase),
+    }
+    .map_err(|e| format!("cannot derive keypair: {e}"))
+}
+
+fn keypair_from_bytes(bytes: &[u8]) -> Result<Keypair, String> {
+    if bytes.len() != 64 {
+        return Err(format!("expected 64 keypair bytes, got {}", bytes.len()));
+    }
+    Keypair::from_bytes(bytes).map_err(|e| format!("invalid keypair: {e}"))
+}
+
+fn keypair_from_json(json: &str) -> Result<Keypair, String> {
+    let bytes: Vec<u8> =
+        serde_json::from_str(json).map_err(|e| format!("invalid byte array: {e}"))?;
+    keypair_from_bytes(&bytes)
+}
+
+// Expands a leading `~/` the way shells do, as in the default `~/.config/solana/id.json`
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Reads a keypair given as a JSON byte array, a keypair or keystore file path or a base58
+/// secret key. The format is detected in this order. Errors never include the key itself.
+pub fn keypair_from_text(text: &str) -> Result<Keypair, String> {
+    let text = text.trim();
+    if text.starts_with('[') {
+        return keypair_from_json(text);
+    }
+    let path = expand_home(text);
+    if path.is_file() {
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        if keystore::is_keystore(&content) {
+            let keystore: Keystore =
+                serde_json::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))?;
+            return keystore
+                .unlock()
+                .map_err(|e| format!("{}: {e}", path.display()));
+        }
+        return keypair_from_json(&content).map_err(|e| format!("{}: {e}", path.display()));
+    }
+    match bs58::decode(text).into_vec() {
+        Ok(bytes) => keypair_from_bytes(&bytes),
+        Err(_) => Err("neither an existing keypair file nor a base58 secret key".to_string()),
+    }
+}
@@ -0,0 +1,184 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    bs58,
+    signature::{Keypair, Signer},
+};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::Mutex;
+
+const VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Environment variable read instead of prompting for the keystore passphrase
+pub const PASSPHRASE_ENV: &str = "KEYSTORE_PASSPHRASE";
+
+// The passphrase entered first is reused for every keystore of the run
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Argon2id parameters the encryption key was derived with
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Kdf {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,
+}
+
+/// A keypair encrypted with XChaCha20-Poly1305 under a key derived from a passphrase
+/// with Argon2id. Binary fields are base58 encoded.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Keystore {
+    pub version: u32,
+    pub pubkey: String,
+    pub kdf: Kdf,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+fn derive_key(passphrase: &str, kdf: &Kdf) -> Result<[u8; 32], String> {
+    let salt = decode(&kdf.salt, "salt")?;
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|e| format!("invalid key derivation parameters: {e}"))?;
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| format!("key derivation failed: {e}"))?;
+    Ok(key)
+}
+
+fn decode(value: &str, field: &str) -> Result<Vec<u8>, String> {
+    bs58::decode(value)
+        .into_vec()
+        .map_err(|e| format!("invalid keystore {field}: {e}"))
+}
+
+impl Keystore {
+    pub fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<Self, String> {
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let kdf = Kdf {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+            salt: bs58::encode(salt).into_string(),
+        };
+        let key = derive_key(passphrase, &kdf)?;
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(XNonce::from_slice(&nonce), keypair.to_bytes().as_slice())
+            .map_err(|e| format!("encryption failed: {e}"))?;
+        Ok(Self {
+            version: VERSION,
+            pubkey: keypair.pubkey().to_string(),
+            kdf,
+            nonce: bs58::encode(nonce).into_string(),
+            ciphertext: bs58::encode(ciphertext).into_string(),
+        })
+    }
+
+    pub fn decrypt(&self, passphrase: &str) -> Result<Keypair, String> {
+        if self.version != VERSION {
+            return Err(format!("unsupported keystore version {}", self.version));
+        }
+        let nonce = decode(&self.nonce, "nonce")?;
+        if nonce.len() != NONCE_LEN {
+            return Err(format!("invalid keystore nonce length {}", nonce.len()));
+        }
+        let ciphertext = decode(&self.ciphertext, "ciphertext")?;
+        let key = derive_key(passphrase, &self.kdf)?;
+        let bytes = XChaCha20Poly1305::new(&key.into())
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "wrong passphrase or corrupted keystore".to_string())?;
+        let keypair =
+            Keypair::from_bytes(&bytes).map_err(|e| format!("invalid keypair in keystore: {e}"))?;
+        if keypair.pubkey().to_string() != self.pubkey {
+            return Err(format!("keystore does not hold the key of {}", self.pubkey));
+        }
+        Ok(keypair)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content =
+            fs::read_to_string(path).map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        serde_json::from_str(&content).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        write_private(path, &json).map_err(|e| format!("cannot write {}: {e}", path.display()))
+    }
+
+    /// Decrypts the keystore with the passphrase of the run, asking for it the first time
+    pub fn unlock(&self) -> Result<Keypair, String> {
+        let mut cached = PASSPHRASE.lock().unwrap_or_else(|e| e.into_inner());
+        let passphrase = match cached.as_ref() {
+            Some(passphrase) => passphrase.clone(),
+            None => match std::env::var(PASSPHRASE_ENV) {
+                Ok(passphrase) => passphrase,
+                Err(_) => {
+                    rpassword::prompt_password(format!("Keystore passphrase for {}: ", self.pubkey))
+                        .map_err(|e| format!("cannot read passphrase: {e}"))?
+                }
+            },
+        };
+        let keypair = self.decrypt(&passphrase)?;
+        *cached = Some(passphrase);
+        Ok(keypair)
+    }
+}
+
+/// Writes a file only its owner can read, as solana-keygen does for keypair files. An
+/// existing file is truncated and restricted as well.
+pub fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to a new file
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+}
+
+/// Tells a keystore file apart from a plain keypair file by its content
+pub fn is_keystore(content: &str) -> bool {
+    content.trim_start().starts_with('{')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let keypair = Keypair::new();
+        let keystore = Keystore::encrypt(&keypair, "correct horse").unwrap();
+        assert_eq!(keystore.pubkey, keypair.pubkey().to_string());
+        assert!(is_keystore(&serde_json::to_string(&keystore).unwrap()));
+
+        let decrypted = keystore.decrypt("correct horse").unwrap();
+        assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+        assert!(keystore.decrypt("wrong horse").is_err());
+    }
+
+    #[test]
+    fn tampered_keystore_is_rejected() {
+        let keypair = Keypair::new();
+        let mut keystore = Keystore::encrypt(&keypair, "passphrase").unwrap();
+        keystore.pubkey = Keypair::new().pubkey().to_string();
+        assert!(keystore.decrypt("passphrase").is_err());
+        keystore.version = VERSION + 1;
+        assert!(keystore.decrypt("passphrase").is_err());
+    }
+}
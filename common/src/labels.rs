@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Readable names of addresses, e.g. `treasury` or `hot-wallet-3`, shown next to them in
+/// output. Read from a `labels` map of address to label in the config, which config
+/// entries with a `label` of their own add to.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(transparent)]
+pub struct Labels {
+    by_address: HashMap<String, String>,
+}
+
+impl Labels {
+    /// Labels `address`, replacing any label it had, unless `label` is `None`.
+    pub fn insert(&mut self, address: &str, label: Option<&str>) {
+        if let Some(label) = label {
+            self.by_address
+                .insert(address.to_string(), label.to_string());
+        }
+    }
+
+    pub fn get(&self, address: &str) -> Option<&str> {
+        self.by_address.get(address).map(String::as_str)
+    }
+
+    /// `address` with its label, see [`labeled`].
+    pub fn display(&self, address: &str) -> String {
+        labeled(address, self.get(address))
+    }
+}
+
+/// Formats an address as `label (address)`, or bare when it has no label.
+pub fn labeled(address: &str, label: Option<&str>) -> String {
+    match label {
+        Some(label) => format!("{label} ({address})"),
+        None => address.to_string(),
+    }
+}
@@ -0,0 +1,26 @@
+//! Code shared by the task binaries.
+
+pub mod alerts;
+pub mod amount;
+pub mod blockhash;
+pub mod compute_budget;
+pub mod config;
+pub mod dashboard;
+pub mod database;
+pub mod error;
+pub mod explorer;
+pub mod jito;
+pub mod keys;
+pub mod keystore;
+pub mod labels;
+pub mod nonce;
+pub mod output;
+pub mod rate_limit;
+pub mod recipients;
+pub mod rpc;
+pub mod signer;
+pub mod stake;
+pub mod telemetry;
+pub mod transaction;
+
+pub use error::{exit, TaskError, TaskResult};
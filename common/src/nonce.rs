@@ -0,0 +1,71 @@
+use crate::keys::KeySource;
+use crate::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::{nonce_utils, rpc_client::RpcClient};
+use solana_sdk::{
+    hash::Hash,
+    instruction::Instruction,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+};
+use std::str::FromStr;
+
+/// Durable nonce account used instead of a recent blockhash.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NonceConfig {
+    pub nonce_account: String,
+    /// Private key of the nonce authority, the sender is the authority when omitted
+    #[serde(default)]
+    pub authority: Option<KeySource>,
+}
+
+#[derive(Debug)]
+pub struct DurableNonce {
+    pub account: Pubkey,
+    /// Signs the nonce advance, the fee payer does when unset
+    pub authority: Option<Keypair>,
+}
+
+impl DurableNonce {
+    pub fn from_config(config: &NonceConfig) -> TaskResult<Self> {
+        let account = Pubkey::from_str(&config.nonce_account).map_err(|e| {
+            TaskError::KeyError(format!("nonce account {}: {e}", config.nonce_account))
+        })?;
+        let authority =
+            match &config.authority {
+                Some(source) => Some(source.keypair().map_err(|e| {
+                    TaskError::KeyError(format!("nonce authority of {account}: {e}"))
+                })?),
+                None => None,
+            };
+
+        Ok(Self { account, authority })
+    }
+
+    /// The nonce authority, falling back to the fee payer.
+    pub fn authority_pubkey(&self, payer: &Pubkey) -> Pubkey {
+        self.authority
+            .as_ref()
+            .map_or(*payer, |authority| authority.pubkey())
+    }
+
+    /// The blockhash currently stored in the nonce account.
+    pub async fn blockhash(&self, client: &RpcClient) -> TaskResult<Hash> {
+        let account = client
+            .get_account_with_commitment(&self.account, client.commitment())
+            .await?
+            .value
+            .ok_or_else(|| {
+                TaskError::ConfigError(format!("nonce account {} does not exist", self.account))
+            })?;
+        let data = nonce_utils::data_from_account(&account)
+            .map_err(|e| TaskError::ConfigError(format!("nonce account {}: {e}", self.account)))?;
+        Ok(data.blockhash())
+    }
+
+    /// Instruction that has to come first in every transaction using this nonce.
+    pub fn advance_instruction(&self, payer: &Pubkey) -> Instruction {
+        system_instruction::advance_nonce_account(&self.account, &self.authority_pubkey(payer))
+    }
+}
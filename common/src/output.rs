@@ -0,0 +1,91 @@
+use crate::TaskResult;
+use serde::{Serialize, Serializer};
+use solana_sdk::transaction;
+use std::fmt::Display;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// How results are printed to stdout.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Prints `value` as a single line of JSON.
+pub fn print_json<T: Serialize>(value: &T) -> TaskResult<()> {
+    let line = serde_json::to_string(value).map_err(std::io::Error::from)?;
+    println!("{line}");
+    Ok(())
+}
+
+/// Prints the end-of-run summary as `{"summary": ...}`.
+pub fn print_summary<T: Serialize>(summary: &T) -> TaskResult<()> {
+    #[derive(Serialize)]
+    struct SummaryLine<'a, T> {
+        summary: &'a T,
+    }
+
+    print_json(&SummaryLine { summary })
+}
+
+/// Serializes a value through its `Display` implementation, e.g. pubkeys and signatures.
+pub fn serialize_display<T: Display, S: Serializer>(
+    value: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Like [`serialize_display`], for optional values which become `null` when absent.
+pub fn serialize_optional_display<T: Display, S: Serializer>(
+    value: &Option<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(value) => serializer.collect_str(value),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Percent-encodes everything but the unreserved characters of RFC 3986, for a value in a
+/// URL.
+pub fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Formats a Unix timestamp, such as the block time of a slot, as an RFC 3339 UTC time.
+pub fn format_unix_time(timestamp: i64) -> String {
+    let time = UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64);
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// Serializes a duration as a number of milliseconds.
+pub fn serialize_duration_ms<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(duration.as_millis())
+}
+
+/// Serializes a signature status as `"ok"`, the error message, or `null` when unknown.
+pub fn serialize_status<S: Serializer>(
+    status: &Option<transaction::Result<()>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match status {
+        Some(Ok(())) => serializer.serialize_str("ok"),
+        Some(Err(err)) => serializer.collect_str(err),
+        None => serializer.serialize_none(),
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Client-side limit on the rate of RPC requests.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Sustained number of requests allowed per second
+    pub requests_per_second: f64,
+    /// Number of requests that may be sent at once after a quiet period
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+/// Token bucket holding up to `burst` tokens and refilled at `requests_per_second`.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> TaskResult<Self> {
+        if !config.requests_per_second.is_finite()
+            || config.requests_per_second <= 0.0
+            || config.burst == 0
+        {
+            return Err(TaskError::ConfigError(
+                "rate_limit needs a positive requests_per_second and burst".to_string(),
+            ));
+        }
+        Ok(Self {
+            rate: config.requests_per_second,
+            burst: config.burst as f64,
+            bucket: Mutex::new(Bucket {
+                tokens: config.burst as f64,
+                updated: Instant::now(),
+            }),
+        })
+    }
+
+    /// Waits until a request may be sent. Tokens are reserved up front, so concurrent
+    /// callers queue up in order instead of racing for the next refill.
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+            bucket.updated = now;
+            bucket.tokens -= 1.0;
+            match bucket.tokens < 0.0 {
+                true => Duration::from_secs_f64(-bucket.tokens / self.rate),
+                false => Duration::ZERO,
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use std::fs;
+use std::str::FromStr;
+
+/// Files of recipients transfers may or may not go to. Each holds one address per line;
+/// blank lines and `#` comments are ignored.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RecipientListsConfig {
+    /// Only these recipients may be paid when set
+    #[serde(default)]
+    pub recipient_allowlist: Option<String>,
+    /// These recipients are never paid
+    #[serde(default)]
+    pub recipient_denylist: Option<String>,
+}
+
+/// Rejects transfers to recipients that are denied or missing from the allowlist, a safety
+/// net against mistyped or tampered configs.
+#[derive(Debug, Default)]
+pub struct RecipientPolicy {
+    allowed: Option<HashSet<Pubkey>>,
+    denied: HashSet<Pubkey>,
+}
+
+impl RecipientPolicy {
+    pub fn load(config: &RecipientListsConfig) -> TaskResult<Self> {
+        Ok(Self {
+            allowed: config
+                .recipient_allowlist
+                .as_deref()
+                .map(read_list)
+                .transpose()?,
+            denied: match &config.recipient_denylist {
+                Some(path) => read_list(path)?,
+                None => HashSet::new(),
+            },
+        })
+    }
+
+    pub fn check(&self, recipient: &Pubkey) -> TaskResult<()> {
+        if self.denied.contains(recipient) {
+            return Err(TaskError::ConfigError(format!(
+                "recipient {recipient} is in recipient_denylist"
+            )));
+        }
+        match &self.allowed {
+            Some(allowed) if !allowed.contains(recipient) => Err(TaskError::ConfigError(format!(
+                "recipient {recipient} is not in recipient_allowlist"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Function for reading the addresses of a list file
+fn read_list(path: &str) -> TaskResult<HashSet<Pubkey>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| TaskError::ConfigError(format!("cannot read {path}: {e}")))?;
+    let mut addresses = HashSet::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let address = Pubkey::from_str(line)
+            .map_err(|e| TaskError::KeyError(format!("{path}:{}: {line}: {e}", i + 1)))?;
+        addresses.insert(address);
+    }
+    Ok(addresses)
+}
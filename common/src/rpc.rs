@@ -0,0 +1,400 @@
+use crate::rate_limit::{RateLimitConfig, RateLimiter};
+use crate::{TaskError, TaskResult};
+use async_trait::async_trait;
+use futures::stream::{FuturesUnordered, StreamExt};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use solana_client::{
+    client_error::{
+        reqwest::{self, StatusCode},
+        ClientError, ClientErrorKind, Result as ClientResult,
+    },
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::RpcClientConfig,
+    rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    rpc_request::{RpcError, RpcRequest},
+    rpc_sender::{RpcSender, RpcTransportStats},
+};
+use solana_rpc_client::http_sender::HttpSender;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// Request timeout of the Solana RPC client
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A public Solana cluster, standing for its RPC and WebSocket endpoints.
+#[derive(Serialize, Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Network {
+    MainnetBeta,
+    Devnet,
+    Testnet,
+    /// A `solana-test-validator` on this machine
+    Localnet,
+}
+
+impl Network {
+    const ALL: [Network; 4] = [
+        Network::MainnetBeta,
+        Network::Devnet,
+        Network::Testnet,
+        Network::Localnet,
+    ];
+
+    pub fn rpc_url(self) -> &'static str {
+        match self {
+            Network::MainnetBeta => "https://api.mainnet-beta.solana.com",
+            Network::Devnet => "https://api.devnet.solana.com",
+            Network::Testnet => "https://api.testnet.solana.com",
+            Network::Localnet => "http://127.0.0.1:8899",
+        }
+    }
+
+    pub fn ws_url(self) -> &'static str {
+        match self {
+            Network::MainnetBeta => "wss://api.mainnet-beta.solana.com",
+            Network::Devnet => "wss://api.devnet.solana.com",
+            Network::Testnet => "wss://api.testnet.solana.com",
+            Network::Localnet => "ws://127.0.0.1:8900",
+        }
+    }
+}
+
+/// RPC endpoints of a config file: `rpc_url`, followed by the fallbacks in `rpc_urls`, or
+/// the public endpoint of `network` when neither is set.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RpcEndpoints {
+    #[serde(default)]
+    pub network: Option<Network>,
+    #[serde(default)]
+    pub rpc_url: Option<String>,
+    /// Endpoints requests fail over to when the current one is unreachable or overloaded
+    #[serde(default)]
+    pub rpc_urls: Vec<String>,
+    /// Send every transaction to all the endpoints at once, and ask all of them for its
+    /// status, to land it through whichever node is fastest
+    #[serde(default)]
+    pub fan_out: bool,
+}
+
+impl RpcEndpoints {
+    /// The endpoints to use in order, or only `cli_url` or the endpoint of `cli_network` when
+    /// one was given on the command line.
+    pub fn urls(
+        &self,
+        cli_url: Option<String>,
+        cli_network: Option<Network>,
+    ) -> TaskResult<Vec<String>> {
+        if let Some(url) = cli_url {
+            return Ok(vec![url]);
+        }
+        if let Some(network) = cli_network {
+            return Ok(vec![network.rpc_url().to_string()]);
+        }
+        let urls: Vec<String> = self
+            .rpc_url
+            .iter()
+            .chain(self.rpc_urls.iter())
+            .cloned()
+            .collect();
+        if urls.is_empty() {
+            return match self.network {
+                Some(network) => Ok(vec![network.rpc_url().to_string()]),
+                None => Err(TaskError::ConfigError(
+                    "either network, rpc_url or rpc_urls must be set".to_string(),
+                )),
+            };
+        }
+        Ok(urls)
+    }
+}
+
+/// Derives the pubsub WebSocket URL from an HTTP RPC URL the way the Solana CLI does:
+/// `http` becomes `ws`, `https` becomes `wss` and an explicit port is incremented. The
+/// endpoint of a [`Network`] gets the WebSocket URL of the network.
+pub fn websocket_url(rpc_url: &str) -> String {
+    let trimmed = rpc_url.trim_end_matches('/');
+    if let Some(network) = Network::ALL.iter().find(|n| n.rpc_url() == trimmed) {
+        return network.ws_url().to_string();
+    }
+    let (scheme, rest) = match rpc_url.split_once("://") {
+        Some(("https", rest)) => ("wss", rest),
+        Some((_, rest)) => ("ws", rest),
+        None => ("ws", rpc_url),
+    };
+    let (host, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+    let host = match host
+        .rsplit_once(':')
+        .map(|(name, port)| (name, port.parse::<u16>()))
+    {
+        Some((name, Ok(port))) => format!("{name}:{}", port.saturating_add(1)),
+        _ => host.to_string(),
+    };
+    format!("{scheme}://{host}{path}")
+}
+
+/// How long to wait on RPC endpoints, so a hanging endpoint fails instead of stalling
+/// the run. Every timeout is a number of seconds or a duration such as `10s`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct RpcTimeouts {
+    /// Longest wait for a connection to an endpoint, only bounded by the request timeout
+    /// when unset
+    #[serde(
+        default,
+        deserialize_with = "crate::config::deserialize_optional_duration"
+    )]
+    pub rpc_connect_timeout: Option<Duration>,
+    /// Longest wait for the response to a single request, 30 seconds by default
+    #[serde(
+        default,
+        deserialize_with = "crate::config::deserialize_optional_duration"
+    )]
+    pub rpc_request_timeout: Option<Duration>,
+    /// Longest wait for a sent transfer to be confirmed, until its blockhash expires when
+    /// unset
+    #[serde(
+        default,
+        deserialize_with = "crate::config::deserialize_optional_duration"
+    )]
+    pub confirmation_timeout: Option<Duration>,
+}
+
+impl RpcTimeouts {
+    // An HTTP transport to `url` with the configured timeouts
+    fn sender(&self, url: String) -> TaskResult<HttpSender> {
+        let timeout = self.rpc_request_timeout.unwrap_or(DEFAULT_REQUEST_TIMEOUT);
+        let mut builder = reqwest::Client::builder()
+            .default_headers(HttpSender::default_headers())
+            .timeout(timeout)
+            .pool_idle_timeout(timeout);
+        if let Some(connect_timeout) = self.rpc_connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| TaskError::ConfigError(format!("RPC client for {url}: {e}")))?;
+        Ok(HttpSender::new_with_client(url, client))
+    }
+}
+
+/// Retry policy applied to every RPC request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RetryConfig {
+    /// Total number of attempts per request, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every failed attempt
+    pub initial_backoff_ms: u64,
+    /// Upper bound for the delay between two attempts
+    pub max_backoff_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 10_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Delay before the given retry (1-based): exponential backoff with "equal jitter",
+    /// i.e. a random value between half and the whole of the exponential delay.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << (retry - 1).min(32))
+            .min(self.max_backoff_ms);
+        let jittered = rand::thread_rng().gen_range(exponential / 2..=exponential);
+        Duration::from_millis(jittered)
+    }
+}
+
+/// Whether an error is worth retrying: transport failures, rate limiting, server errors
+/// and unhealthy nodes. Everything else (invalid params, preflight failures, ...) would
+/// fail the same way again.
+fn is_transient(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(err) => {
+            err.is_timeout()
+                || err.is_connect()
+                || err.is_request()
+                || err.status().is_some_and(|status| {
+                    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                })
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+        }
+        _ => false,
+    }
+}
+
+/// Called with every failed request to an endpoint, including the ones retried.
+pub type ErrorHook = Arc<dyn Fn(&ClientError) + Send + Sync>;
+
+/// HTTP transport that fails over to the next endpoint on transient failures, and backs off
+/// exponentially once every endpoint has been tried. The endpoint that last worked keeps
+/// receiving requests. Every attempt first waits for the rate limiter, if any.
+///
+/// With `fan_out`, transactions and signature status requests go to every endpoint in
+/// parallel instead, falling back to failover when none of them answers.
+pub struct RetrySender {
+    endpoints: Vec<HttpSender>,
+    current: AtomicUsize,
+    retry: RetryConfig,
+    limiter: Option<RateLimiter>,
+    fan_out: bool,
+    on_error: Option<ErrorHook>,
+}
+
+impl RetrySender {
+    pub fn new(
+        urls: Vec<String>,
+        retry: RetryConfig,
+        limiter: Option<RateLimiter>,
+        timeouts: &RpcTimeouts,
+        fan_out: bool,
+        on_error: Option<ErrorHook>,
+    ) -> TaskResult<Self> {
+        assert!(!urls.is_empty(), "at least one RPC endpoint is required");
+        Ok(Self {
+            endpoints: urls
+                .into_iter()
+                .map(|url| timeouts.sender(url))
+                .collect::<TaskResult<_>>()?,
+            current: AtomicUsize::new(0),
+            retry,
+            limiter,
+            fan_out,
+            on_error,
+        })
+    }
+
+    // Function for passing a failed request to the error hook
+    fn failed(&self, err: &ClientError) {
+        if let Some(on_error) = &self.on_error {
+            on_error(err);
+        }
+    }
+
+    // Sends the request to every endpoint and returns the first useful answer. Every node
+    // answers `sendTransaction` with the same signature, so the first one is kept; a
+    // signature status counts once some node knows the transaction
+    async fn send_to_all(
+        &self,
+        request: RpcRequest,
+        params: &serde_json::Value,
+    ) -> Option<serde_json::Value> {
+        let mut answers: FuturesUnordered<_> = self
+            .endpoints
+            .iter()
+            .map(|endpoint| async {
+                if let Some(limiter) = &self.limiter {
+                    limiter.acquire().await;
+                }
+                let answer = endpoint.send(request, params.clone()).await;
+                if let Err(err) = &answer {
+                    self.failed(err);
+                }
+                answer
+            })
+            .collect();
+        let mut fallback = None;
+        while let Some(answer) = answers.next().await {
+            let Ok(answer) = answer else {
+                continue;
+            };
+            let known = match request {
+                RpcRequest::GetSignatureStatuses => answer["value"]
+                    .as_array()
+                    .is_some_and(|statuses| statuses.iter().any(|status| !status.is_null())),
+                _ => true,
+            };
+            if known {
+                return Some(answer);
+            }
+            fallback.get_or_insert(answer);
+        }
+        fallback
+    }
+}
+
+#[async_trait]
+impl RpcSender for RetrySender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let fan_out = matches!(
+            request,
+            RpcRequest::SendTransaction | RpcRequest::GetSignatureStatuses
+        );
+        if self.fan_out && fan_out && self.endpoints.len() > 1 {
+            if let Some(answer) = self.send_to_all(request, &params).await {
+                return Ok(answer);
+            }
+        }
+        let endpoints = self.endpoints.len() as u32;
+        let mut attempt = 1;
+        loop {
+            if let Some(limiter) = &self.limiter {
+                limiter.acquire().await;
+            }
+            let index = self.current.load(Ordering::Relaxed);
+            let result = self.endpoints[index].send(request, params.clone()).await;
+            if let Err(err) = &result {
+                self.failed(err);
+            }
+            match result {
+                Err(err) if attempt < self.retry.max_attempts && is_transient(&err) => {
+                    // Concurrent requests that hit the same failure only move on once
+                    let next = (index + 1) % self.endpoints.len();
+                    let _ = self.current.compare_exchange(
+                        index,
+                        next,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    );
+                    if attempt % endpoints == 0 {
+                        tokio::time::sleep(self.retry.backoff(attempt / endpoints)).await;
+                    }
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.endpoints[self.current.load(Ordering::Relaxed)].get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.endpoints[self.current.load(Ordering::Relaxed)].url()
+    }
+}
+
+/// Creates an RPC client whose requests go through the rate limit, retry and failover layer.
+/// `on_error` sees every failed request, e.g. to count them.
+pub fn new_client(
+    urls: Vec<String>,
+    fan_out: bool,
+    commitment: CommitmentConfig,
+    retry: &RetryConfig,
+    rate_limit: Option<&RateLimitConfig>,
+    timeouts: &RpcTimeouts,
+    on_error: Option<ErrorHook>,
+) -> TaskResult<RpcClient> {
+    let limiter = rate_limit.map(RateLimiter::new).transpose()?;
+    Ok(RpcClient::new_sender(
+        RetrySender::new(urls, retry.clone(), limiter, timeouts, fan_out, on_error)?,
+        RpcClientConfig::with_commitment(commitment),
+    ))
+}
@@ -0,0 +1,229 @@
+use crate::{TaskError, TaskResult};
+use async_trait::async_trait;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use solana_remote_wallet::{
+    locator::Locator,
+    remote_keypair::{generate_remote_keypair, RemoteKeypair},
+    remote_wallet::maybe_wallet_manager,
+};
+use solana_sdk::{
+    derivation_path::DerivationPath,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::thread;
+use std::{fs, time::Duration};
+use tokio::sync::oneshot;
+
+/// Signs transaction messages for one public key. Implemented by local keypairs and by
+/// backends that keep the private key elsewhere, such as a Ledger.
+#[async_trait]
+pub trait TxSigner: Send + Sync + fmt::Debug {
+    fn pubkey(&self) -> Pubkey;
+    async fn sign_message(&self, message: &[u8]) -> TaskResult<Signature>;
+}
+
+#[async_trait]
+impl TxSigner for Keypair {
+    fn pubkey(&self) -> Pubkey {
+        Signer::pubkey(self)
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> TaskResult<Signature> {
+        Ok(Signer::sign_message(self, message))
+    }
+}
+
+type LedgerRequest = (Vec<u8>, oneshot::Sender<Result<Signature, String>>);
+
+/// Signs with a key held on a Ledger device. Every signature has to be approved on the
+/// device. The device handle cannot move between threads, so it lives on a thread of its
+/// own that signs one message at a time.
+#[derive(Debug)]
+pub struct LedgerSigner {
+    pubkey: Pubkey,
+    requests: mpsc::Sender<LedgerRequest>,
+}
+
+impl LedgerSigner {
+    /// Connects to the Ledger at `locator`, such as `usb://ledger`, and reads the public key
+    /// of `derivation_path`, `m/44'/501'` by default as in the Solana CLI.
+    pub fn connect(locator: &str, derivation_path: Option<&str>) -> Result<Self, String> {
+        let locator = Locator::new_from_path(locator)
+            .map_err(|e| format!("invalid Ledger locator {locator}: {e}"))?;
+        let derivation_path = match derivation_path {
+            Some(path) => DerivationPath::from_absolute_path_str(path)
+                .map_err(|e| format!("invalid derivation path {path}: {e}"))?,
+            None => DerivationPath::default(),
+        };
+
+        let (requests, received) = mpsc::channel::<LedgerRequest>();
+        let (ready, connected) = mpsc::channel();
+        thread::spawn(move || {
+            let keypair = match open_ledger(locator, derivation_path) {
+                Ok(keypair) => keypair,
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready.send(Ok(keypair.pubkey));
+            for (message, reply) in received {
+                let _ = reply.send(
+                    keypair
+                        .try_sign_message(&message)
+                        .map_err(|e| e.to_string()),
+                );
+            }
+        });
+        let pubkey = connected
+            .recv()
+            .map_err(|_| "Ledger connection closed".to_string())??;
+        Ok(Self { pubkey, requests })
+    }
+}
+
+fn open_ledger(locator: Locator, derivation_path: DerivationPath) -> Result<RemoteKeypair, String> {
+    if !cfg!(feature = "ledger") {
+        return Err("built without Ledger support, rebuild with `--features ledger`".to_string());
+    }
+    let manager = maybe_wallet_manager()
+        .map_err(|e| format!("cannot access USB devices: {e}"))?
+        .ok_or_else(|| "no Ledger device found".to_string())?;
+    generate_remote_keypair(locator, derivation_path, &manager, false, "ledger")
+        .map_err(|e| format!("cannot open Ledger: {e}"))
+}
+
+#[async_trait]
+impl TxSigner for LedgerSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> TaskResult<Signature> {
+        let (reply, signature) = oneshot::channel();
+        let closed = || TaskError::KeyError("Ledger connection closed".to_string());
+        self.requests
+            .send((message.to_vec(), reply))
+            .map_err(|_| closed())?;
+        signature
+            .await
+            .map_err(|_| closed())?
+            .map_err(|e| TaskError::KeyError(format!("Ledger did not sign: {e}")))
+    }
+}
+
+/// Time a remote signer has to answer one request
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// TLS settings for a remote signer. Setting a client certificate and key enables mutual TLS.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// PEM file of the CA that issued the certificate of the signer, if not a public one
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+    /// PEM file of the client certificate
+    #[serde(default)]
+    pub client_cert: Option<String>,
+    /// PEM file of the private key of the client certificate
+    #[serde(default)]
+    pub client_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RemoteSignRequest {
+    pubkey: String,
+    /// Serialized transaction message, base64 encoded
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct RemoteSignResponse {
+    /// Base58 encoded signature
+    signature: String,
+}
+
+/// Signs through a separate signing service. The serialized message is POSTed to `url` as
+/// `{"pubkey": ..., "message": <base64>}` and the service answers `{"signature": <base58>}`.
+/// Signatures that do not verify against the message are rejected.
+#[derive(Debug)]
+pub struct RemoteSigner {
+    pubkey: Pubkey,
+    url: String,
+    client: reqwest::Client,
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("cannot read {path}: {e}"))
+}
+
+impl RemoteSigner {
+    pub fn new(url: &str, pubkey: &str, tls: &TlsConfig) -> Result<Self, String> {
+        let pubkey =
+            Pubkey::from_str(pubkey).map_err(|e| format!("invalid signer pubkey {pubkey}: {e}"))?;
+        let mut builder = reqwest::Client::builder()
+            .use_rustls_tls()
+            .timeout(REMOTE_SIGNER_TIMEOUT);
+        if let Some(path) = &tls.ca_cert {
+            let cert = reqwest::Certificate::from_pem(&read_pem(path)?)
+                .map_err(|e| format!("invalid CA certificate {path}: {e}"))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        match (&tls.client_cert, &tls.client_key) {
+            (Some(cert), Some(key)) => {
+                let mut pem = read_pem(cert)?;
+                pem.push(b'\n');
+                pem.extend(read_pem(key)?);
+                let identity = reqwest::Identity::from_pem(&pem)
+                    .map_err(|e| format!("invalid client certificate {cert}: {e}"))?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => return Err("client_cert and client_key must be set together".to_string()),
+        }
+        let client = builder
+            .build()
+            .map_err(|e| format!("cannot create remote signer client: {e}"))?;
+        Ok(Self {
+            pubkey,
+            url: url.to_string(),
+            client,
+        })
+    }
+}
+
+#[async_trait]
+impl TxSigner for RemoteSigner {
+    fn pubkey(&self) -> Pubkey {
+        self.pubkey
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> TaskResult<Signature> {
+        let failed = |e: String| TaskError::KeyError(format!("remote signer {}: {e}", self.url));
+        let request = RemoteSignRequest {
+            pubkey: self.pubkey.to_string(),
+            message: BASE64_STANDARD.encode(message),
+        };
+        let response: RemoteSignResponse = self
+            .client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| failed(e.to_string()))?
+            .json()
+            .await
+            .map_err(|e| failed(format!("invalid response: {e}")))?;
+        let signature = Signature::from_str(&response.signature)
+            .map_err(|e| failed(format!("invalid signature: {e}")))?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(failed(format!("signature does not match {}", self.pubkey)));
+        }
+        Ok(signature)
+    }
+}
@@ -0,0 +1,54 @@
+use crate::TaskResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::feature_set::reduce_stake_warmup_cooldown;
+use solana_sdk::stake::state::{StakeActivationStatus, StakeStateV2};
+use solana_sdk::stake_history::StakeHistory;
+use solana_sdk::{account, feature, sysvar};
+
+/// What the activation of stake depends on, read once and used for many stake accounts.
+pub struct StakeContext {
+    pub epoch: u64,
+    history: StakeHistory,
+    /// Epoch the lower warmup and cooldown rate took effect in, if it did
+    new_rate_activation_epoch: Option<u64>,
+}
+
+impl StakeContext {
+    pub async fn fetch(client: &RpcClient) -> TaskResult<Self> {
+        let epoch = client.get_epoch_info().await?.epoch;
+        let history = client.get_account(&sysvar::stake_history::id()).await?;
+        let activated_at = client
+            .get_account(&reduce_stake_warmup_cooldown::id())
+            .await
+            .ok()
+            .and_then(|account| feature::from_account(&account))
+            .and_then(|feature| feature.activated_at);
+        let new_rate_activation_epoch = match activated_at {
+            Some(slot) => Some(client.get_epoch_schedule().await?.get_epoch(slot)),
+            None => None,
+        };
+        Ok(StakeContext {
+            epoch,
+            history: account::from_account(&history).unwrap_or_default(),
+            new_rate_activation_epoch,
+        })
+    }
+
+    /// The effective, activating and deactivating stake of a stake account in the current
+    /// epoch, `None` when it is not delegated.
+    pub fn activation(&self, state: &StakeStateV2) -> Option<StakeActivationStatus> {
+        let StakeStateV2::Stake(_, stake, _) = state else {
+            return None;
+        };
+        Some(stake.delegation.stake_activating_and_deactivating(
+            self.epoch,
+            &self.history,
+            self.new_rate_activation_epoch,
+        ))
+    }
+}
+
+/// Decodes the data of a stake account.
+pub fn state(data: &[u8]) -> Option<StakeStateV2> {
+    bincode::deserialize(data).ok()
+}
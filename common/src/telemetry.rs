@@ -0,0 +1,127 @@
+use crate::{TaskError, TaskResult};
+use clap::ArgAction;
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace::TracerProvider, Resource};
+use serde::{Deserialize, Serialize};
+use std::io::{self, IsTerminal};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Where to export the spans of a run, shared by every task that sends transactions.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TelemetryConfig {
+    /// OTLP gRPC endpoint spans are exported to, e.g. `http://localhost:4317`. Spans are
+    /// not recorded when unset
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// Service name the spans are reported under, the task name by default
+    #[serde(default)]
+    pub service_name: Option<String>,
+}
+
+/// How log lines are written to stderr.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// One JSON object per line, for log aggregators
+    Json,
+}
+
+/// Logging flags shared by the task binaries. `RUST_LOG` takes precedence over the
+/// verbosity flags and can set levels per module, e.g. `RUST_LOG=info,common::rpc=debug`.
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct LogArgs {
+    /// Log more details, `-vv` for everything
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+    /// Only log errors
+    #[arg(short, long)]
+    pub quiet: bool,
+    /// Format of the log lines
+    #[arg(long, value_enum, default_value_t)]
+    pub log_format: LogFormat,
+    /// Log nothing, while the terminal shows a dashboard
+    #[arg(skip)]
+    pub silent: bool,
+}
+
+impl LogArgs {
+    fn filter(&self) -> EnvFilter {
+        if let Ok(filter) = EnvFilter::try_from_default_env() {
+            return filter;
+        }
+        if self.silent {
+            return EnvFilter::new("off");
+        }
+        let level = match (self.quiet, self.verbose) {
+            (true, _) => "error",
+            (false, 0) => "info",
+            (false, 1) => "debug",
+            (false, _) => "trace",
+        };
+        EnvFilter::new(level)
+    }
+}
+
+/// Keeps exporting spans until dropped, then flushes the ones still buffered.
+pub struct Telemetry {
+    provider: Option<TracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = &self.provider {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("cannot flush traces: {e}");
+            }
+        }
+    }
+}
+
+/// Starts logging to stderr, and exporting spans over OTLP when an endpoint is configured.
+/// Must be called from within the Tokio runtime, which runs the exporter.
+pub fn init(log: &LogArgs, config: &TelemetryConfig, task: &str) -> TaskResult<Telemetry> {
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    let fmt = match log.log_format {
+        LogFormat::Text => fmt.boxed(),
+        LogFormat::Json => fmt.json().boxed(),
+    };
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => Some(tracer_provider(endpoint, config, task)?),
+        None => None,
+    };
+    let service_name = config.service_name.as_deref().unwrap_or(task).to_string();
+    let otel = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(service_name)));
+
+    tracing_subscriber::registry()
+        .with(fmt.with_filter(log.filter()))
+        .with(otel)
+        .try_init()
+        .map_err(|e| TaskError::ConfigError(format!("cannot start logging: {e}")))?;
+    Ok(Telemetry { provider })
+}
+
+fn tracer_provider(
+    endpoint: &str,
+    config: &TelemetryConfig,
+    task: &str,
+) -> TaskResult<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .map_err(|e| TaskError::ConfigError(format!("otlp_endpoint {endpoint}: {e}")))?;
+    let service_name = config.service_name.as_deref().unwrap_or(task).to_string();
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+        .build())
+}
@@ -0,0 +1,669 @@
+use crate::blockhash::BlockhashCache;
+use crate::compute_budget::ComputeBudgetConfig;
+use crate::jito::{self, JitoConfig};
+use crate::nonce::DurableNonce;
+use crate::output;
+use crate::rpc;
+use crate::signer::TxSigner;
+use crate::{TaskError, TaskResult};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{
+    RpcSendTransactionConfig, RpcSignatureSubscribeConfig, RpcSimulateTransactionConfig,
+    RpcTransactionConfig,
+};
+use solana_client::rpc_response::{ProcessedSignatureResult, RpcSignatureResult};
+use solana_sdk::{
+    address_lookup_table::{state::AddressLookupTable, AddressLookupTableAccount},
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    hash::Hash,
+    instruction::Instruction,
+    message::{v0, Message, VersionedMessage},
+    pubkey::Pubkey,
+    signature::{Signature, SIGNATURE_BYTES},
+    transaction::{TransactionError, VersionedTransaction},
+};
+use solana_transaction_status::TransactionStatus;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{info_span, warn, Instrument};
+
+/// Time between two signature status checks while waiting for a confirmation
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Time between two blockhash expiry checks while waiting for a signature notification
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// Time between two sends of a transaction with `confirmation: rebroadcast`
+const DEFAULT_REBROADCAST_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Message format settings shared by every task that sends transactions.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TransactionConfig {
+    /// Build v0 transactions instead of legacy ones
+    #[serde(default)]
+    pub versioned: bool,
+    /// Address lookup tables used to compress the account list of v0 transactions
+    #[serde(default)]
+    pub address_lookup_tables: Vec<String>,
+}
+
+/// How transactions are handed to the RPC node, the `send` section of a config.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct SendConfig {
+    /// Send without simulating the transaction first
+    pub skip_preflight: bool,
+    /// How often the RPC node forwards the transaction to leaders until it expires, 0 to
+    /// send it once. Left to the node when unset
+    pub max_retries: Option<usize>,
+    /// Commitment level the preflight simulation runs at, that of the task by default
+    pub preflight_commitment: Option<CommitmentLevel>,
+    /// How to learn that a sent transaction is confirmed
+    pub confirmation: Confirmation,
+    /// Pubsub endpoint for `confirmation: websocket`, derived from the RPC URL when unset
+    pub ws_url: Option<String>,
+    /// How often `confirmation: rebroadcast` sends the transaction again, 2s by default
+    #[serde(deserialize_with = "crate::config::deserialize_optional_duration")]
+    pub rebroadcast_interval: Option<Duration>,
+    /// Submit transactions as Jito bundles instead of through the RPC node
+    pub jito: Option<JitoConfig>,
+}
+
+/// How a sent transaction is waited for.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Confirmation {
+    /// Ask for the signature status every 500ms
+    #[default]
+    Polling,
+    /// Wait for a `signatureSubscribe` notification, polling instead when the subscription
+    /// fails
+    Websocket,
+    /// Send the same transaction again every `rebroadcast_interval` until it lands, failing
+    /// with [`TaskError::Expired`] once its last valid block height is passed
+    Rebroadcast,
+}
+
+/// Turns instructions into signed transactions, adding the configured compute budget and
+/// compiling either a legacy or a v0 message. Transactions are bound to a recent blockhash,
+/// or to a durable nonce when one is given.
+pub struct TransactionBuilder {
+    compute_budget: ComputeBudgetConfig,
+    versioned: bool,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    blockhash_cache: Option<BlockhashCache>,
+    blockhash: Option<Hash>,
+    tip: Option<(Pubkey, u64)>,
+}
+
+impl TransactionBuilder {
+    /// Creates a builder, fetching the configured address lookup tables.
+    pub async fn new(
+        client: &RpcClient,
+        compute_budget: ComputeBudgetConfig,
+        config: &TransactionConfig,
+    ) -> TaskResult<Self> {
+        if !config.versioned && !config.address_lookup_tables.is_empty() {
+            return Err(TaskError::ConfigError(
+                "address_lookup_tables require `versioned: true`".to_string(),
+            ));
+        }
+        let mut lookup_tables = Vec::with_capacity(config.address_lookup_tables.len());
+        for address in &config.address_lookup_tables {
+            lookup_tables.push(fetch_lookup_table(client, address).await?);
+        }
+
+        Ok(Self {
+            compute_budget,
+            versioned: config.versioned,
+            lookup_tables,
+            blockhash_cache: None,
+            blockhash: None,
+            tip: None,
+        })
+    }
+
+    /// Adds the Jito tip to every transaction when `send` submits bundles.
+    pub fn with_send_config(mut self, send: &SendConfig) -> TaskResult<Self> {
+        self.tip = send.jito.as_ref().map(JitoConfig::tip).transpose()?;
+        Ok(self)
+    }
+
+    /// Lamports of the Jito tip added to every transaction, 0 without one.
+    pub fn tip_lamports(&self) -> u64 {
+        self.tip.map_or(0, |(_, lamports)| lamports)
+    }
+
+    /// Takes recent blockhashes from `cache` instead of fetching one per transaction.
+    pub fn with_blockhash_cache(mut self, cache: BlockhashCache) -> Self {
+        self.blockhash_cache = Some(cache);
+        self
+    }
+
+    /// Binds every transaction to `blockhash` without asking the network, for signing
+    /// offline. With a durable nonce it stands for the value stored in the nonce account.
+    pub fn with_blockhash(mut self, blockhash: Hash) -> Self {
+        self.blockhash = Some(blockhash);
+        self
+    }
+
+    #[tracing::instrument(name = "build", skip_all, fields(%payer))]
+    pub async fn build(
+        &self,
+        client: &RpcClient,
+        instructions: Vec<Instruction>,
+        payer: &Pubkey,
+        signers: &[&dyn TxSigner],
+        nonce: Option<&DurableNonce>,
+    ) -> TaskResult<VersionedTransaction> {
+        let message = self.compile(client, instructions, payer, nonce).await?;
+        let mut signers = signers.to_vec();
+        if let Some(authority) = nonce.and_then(|nonce| nonce.authority.as_ref()) {
+            if !signers.iter().any(|s| s.pubkey() == authority.pubkey()) {
+                signers.push(authority);
+            }
+        }
+        sign(message, &signers).await
+    }
+
+    /// Looks up the fee of the transaction `build` would produce, without signing it.
+    pub async fn fee(
+        &self,
+        client: &RpcClient,
+        instructions: Vec<Instruction>,
+        payer: &Pubkey,
+        nonce: Option<&DurableNonce>,
+    ) -> TaskResult<u64> {
+        let message = self.compile(client, instructions, payer, nonce).await?;
+        message_fee(client, message).await
+    }
+
+    /// Size in bytes of the transaction `build` would produce, known before signing so
+    /// oversized transactions can be split up first.
+    pub async fn size(
+        &self,
+        client: &RpcClient,
+        instructions: Vec<Instruction>,
+        payer: &Pubkey,
+        nonce: Option<&DurableNonce>,
+    ) -> TaskResult<usize> {
+        let message = self.compile(client, instructions, payer, nonce).await?;
+        let signatures = usize::from(message.header().num_required_signatures);
+        // The signature count is a compact-u16, one byte below 128
+        let count_len = if signatures < 0x80 { 1 } else { 2 };
+        Ok(count_len + signatures * SIGNATURE_BYTES + message.serialize().len())
+    }
+
+    async fn compile(
+        &self,
+        client: &RpcClient,
+        instructions: Vec<Instruction>,
+        payer: &Pubkey,
+        nonce: Option<&DurableNonce>,
+    ) -> TaskResult<VersionedMessage> {
+        let mut instructions = self.compute_budget.apply(client, instructions).await?;
+        if let Some(nonce) = nonce {
+            // The nonce advance must be the first instruction of the transaction
+            instructions.insert(0, nonce.advance_instruction(payer));
+        }
+        if let Some(tip) = self.tip {
+            instructions.push(jito::tip_instruction(payer, tip));
+        }
+        let blockhash = async {
+            match (nonce, self.blockhash, &self.blockhash_cache) {
+                (_, Some(blockhash), _) => Ok::<_, TaskError>(blockhash),
+                (Some(nonce), None, _) => nonce.blockhash(client).await,
+                (None, None, Some(cache)) => Ok(cache.get()),
+                (None, None, None) => Ok(client.get_latest_blockhash().await?),
+            }
+        }
+        .instrument(info_span!("blockhash"))
+        .await?;
+
+        if !self.versioned {
+            return Ok(VersionedMessage::Legacy(Message::new_with_blockhash(
+                &instructions,
+                Some(payer),
+                &blockhash,
+            )));
+        }
+        let message =
+            v0::Message::try_compile(payer, &instructions, &self.lookup_tables, blockhash)
+                .map_err(|e| TaskError::BuildError(e.to_string()))?;
+        Ok(VersionedMessage::V0(message))
+    }
+}
+
+// Function for collecting the signature of every account the message requires, in order
+#[tracing::instrument(skip_all)]
+async fn sign(
+    message: VersionedMessage,
+    signers: &[&dyn TxSigner],
+) -> TaskResult<VersionedTransaction> {
+    let data = message.serialize();
+    let required = usize::from(message.header().num_required_signatures);
+    let mut signatures = Vec::with_capacity(required);
+    for key in message.static_account_keys().iter().take(required) {
+        let signer = signers
+            .iter()
+            .find(|signer| signer.pubkey() == *key)
+            .ok_or_else(|| TaskError::BuildError(format!("no signer for {key}")))?;
+        signatures.push(signer.sign_message(&data).await?);
+    }
+    Ok(VersionedTransaction {
+        signatures,
+        message,
+    })
+}
+
+/// Sends a signed transaction with the options of `send` and waits until it reaches the
+/// commitment of `client`, like `send_and_confirm_transaction`, with sending and confirming
+/// traced as spans of their own. Gives up with [`TaskError::ConfirmationTimeout`] once the
+/// blockhash expires, [`TaskError::Expired`] with `confirmation: rebroadcast`, or after
+/// `timeout` when one is given.
+#[tracing::instrument(skip_all, fields(signature = %tx.signatures[0]))]
+pub async fn send_and_confirm(
+    client: &RpcClient,
+    tx: &VersionedTransaction,
+    send: &SendConfig,
+    timeout: Option<Duration>,
+) -> TaskResult<Signature> {
+    let confirmed = async {
+        match send.confirmation {
+            Confirmation::Polling => send_and_poll(client, tx, send).await,
+            Confirmation::Websocket => send_and_subscribe(client, tx, send).await,
+            Confirmation::Rebroadcast => send_and_rebroadcast(client, tx, send).await,
+        }
+    };
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, confirmed)
+            .await
+            .unwrap_or(Err(TaskError::ConfirmationTimeout(tx.signatures[0]))),
+        None => confirmed.await,
+    }
+}
+
+async fn send_transaction(
+    client: &RpcClient,
+    tx: &VersionedTransaction,
+    send: &SendConfig,
+) -> TaskResult<Signature> {
+    if let Some(jito) = &send.jito {
+        let bundle = jito.send_bundle(&[tx]).await?;
+        tracing::debug!(%bundle, "bundle submitted");
+        return Ok(tx.signatures[0]);
+    }
+    let config = RpcSendTransactionConfig {
+        skip_preflight: send.skip_preflight,
+        preflight_commitment: Some(
+            send.preflight_commitment
+                .unwrap_or(client.commitment().commitment),
+        ),
+        max_retries: send.max_retries,
+        ..RpcSendTransactionConfig::default()
+    };
+    Ok(client
+        .send_transaction_with_config(tx, config)
+        .instrument(info_span!("send"))
+        .await?)
+}
+
+/// The blockhash whose expiry means the transaction can no longer land. A durable nonce
+/// does not expire, so the latest blockhash bounds the wait instead.
+pub async fn expiry_blockhash(client: &RpcClient, tx: &VersionedTransaction) -> TaskResult<Hash> {
+    if tx.uses_durable_nonce() {
+        Ok(client
+            .get_latest_blockhash_with_commitment(CommitmentConfig::processed())
+            .await?
+            .0)
+    } else {
+        Ok(*tx.message.recent_blockhash())
+    }
+}
+
+async fn send_and_poll(
+    client: &RpcClient,
+    tx: &VersionedTransaction,
+    send: &SendConfig,
+) -> TaskResult<Signature> {
+    let signature = send_transaction(client, tx, send).await?;
+    let blockhash = expiry_blockhash(client, tx).await?;
+    confirm(client, &signature, &blockhash).await
+}
+
+// Subscribes to the signature before sending, so the notification cannot be missed, and
+// waits for it while checking that the blockhash has not expired. Any failure of the
+// WebSocket falls back to polling
+async fn send_and_subscribe(
+    client: &RpcClient,
+    tx: &VersionedTransaction,
+    send: &SendConfig,
+) -> TaskResult<Signature> {
+    let signature = tx.signatures[0];
+    let ws_url = match &send.ws_url {
+        Some(url) => url.clone(),
+        None => rpc::websocket_url(&client.url()),
+    };
+    let pubsub = match PubsubClient::new(&ws_url).await {
+        Ok(pubsub) => pubsub,
+        Err(e) => {
+            warn!("cannot connect to {ws_url}, polling for confirmation: {e}");
+            return send_and_poll(client, tx, send).await;
+        }
+    };
+    let config = RpcSignatureSubscribeConfig {
+        commitment: Some(client.commitment()),
+        enable_received_notification: Some(false),
+    };
+    let (mut notifications, unsubscribe) =
+        match pubsub.signature_subscribe(&signature, Some(config)).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!("cannot subscribe to {signature}, polling for confirmation: {e}");
+                return send_and_poll(client, tx, send).await;
+            }
+        };
+
+    send_transaction(client, tx, send).await?;
+    let blockhash = expiry_blockhash(client, tx).await?;
+    let notified = async {
+        while let Some(notification) = notifications.next().await {
+            if let RpcSignatureResult::ProcessedSignature(ProcessedSignatureResult { err }) =
+                notification.value
+            {
+                return Some(err);
+            }
+        }
+        None
+    };
+    let result = tokio::select! {
+        notified = notified.instrument(info_span!("confirm")) => match notified {
+            Some(None) => Ok(signature),
+            Some(Some(e)) => Err(ClientError::from(e).into()),
+            None => {
+                warn!("subscription to {signature} closed, polling for confirmation");
+                confirm(client, &signature, &blockhash).await
+            }
+        },
+        expired = expired(client, &blockhash) => match expired {
+            // It may have landed without reaching the commitment yet
+            Ok(()) => confirm(client, &signature, &blockhash).await,
+            Err(e) => Err(e),
+        }
+    };
+    drop(notifications);
+    unsubscribe().await;
+    let _ = pubsub.shutdown().await;
+    result
+}
+
+// Sends the transaction until its status shows up, the first time with the configured
+// options and then without preflight nor retries by the RPC node. The latest blockhash is
+// at least as recent as that of the transaction, so its last valid block height bounds
+// the wait until it has landed
+async fn send_and_rebroadcast(
+    client: &RpcClient,
+    tx: &VersionedTransaction,
+    send: &SendConfig,
+) -> TaskResult<Signature> {
+    let signature = send_transaction(client, tx, send).await?;
+    let (_, last_valid_block_height) = client
+        .get_latest_blockhash_with_commitment(CommitmentConfig::processed())
+        .await?;
+    let interval = send
+        .rebroadcast_interval
+        .unwrap_or(DEFAULT_REBROADCAST_INTERVAL);
+    let resend = RpcSendTransactionConfig {
+        skip_preflight: true,
+        max_retries: Some(0),
+        ..RpcSendTransactionConfig::default()
+    };
+    async {
+        loop {
+            tokio::time::sleep(interval).await;
+            let block_height = client
+                .get_block_height_with_commitment(CommitmentConfig::processed())
+                .await?;
+            match signature_status(client, &signature).await? {
+                Some(status) if status.satisfies_commitment(client.commitment()) => {
+                    return committed(status, signature);
+                }
+                // Landed, so it can no longer expire and is not sent again
+                Some(_) => continue,
+                None if block_height > last_valid_block_height => {
+                    return Err(TaskError::Expired(signature));
+                }
+                None => {}
+            }
+            if let Err(e) = client.send_transaction_with_config(tx, resend).await {
+                warn!("cannot rebroadcast {signature}: {e}");
+            }
+        }
+    }
+    .instrument(info_span!("confirm"))
+    .await
+}
+
+// Completes once `blockhash` is no longer valid
+async fn expired(client: &RpcClient, blockhash: &Hash) -> TaskResult<()> {
+    loop {
+        tokio::time::sleep(EXPIRY_CHECK_INTERVAL).await;
+        if !client
+            .is_blockhash_valid(blockhash, CommitmentConfig::processed())
+            .await?
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Looks up a sent transaction at any commitment, so one that landed is seen before it
+/// reaches the commitment of `client`. `None` until it has landed.
+pub async fn signature_status(
+    client: &RpcClient,
+    signature: &Signature,
+) -> TaskResult<Option<TransactionStatus>> {
+    let statuses = client.get_signature_statuses(&[*signature]).await?.value;
+    Ok(statuses.into_iter().next().flatten())
+}
+
+/// The outcome of a transaction whose status reached the commitment.
+pub fn committed(status: TransactionStatus, signature: Signature) -> TaskResult<Signature> {
+    match status.status {
+        Ok(()) => Ok(signature),
+        Err(e) => Err(ClientError::from(e).into()),
+    }
+}
+
+/// Waits until a sent transaction reaches the commitment of `client`, giving up with
+/// [`TaskError::ConfirmationTimeout`] once `blockhash` expires before it has landed. A
+/// transaction that landed can no longer expire, so its wait has no cutoff.
+pub async fn confirm(
+    client: &RpcClient,
+    signature: &Signature,
+    blockhash: &Hash,
+) -> TaskResult<Signature> {
+    async {
+        loop {
+            match signature_status(client, signature).await? {
+                Some(status) if status.satisfies_commitment(client.commitment()) => {
+                    return committed(status, *signature);
+                }
+                // Landed, so it can no longer expire
+                Some(_) => {}
+                None => {
+                    if !client
+                        .is_blockhash_valid(blockhash, CommitmentConfig::processed())
+                        .await?
+                        // It may have landed between the two checks
+                        && signature_status(client, signature).await?.is_none()
+                    {
+                        return Err(TaskError::ConfirmationTimeout(*signature));
+                    }
+                }
+            }
+            tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+        }
+    }
+    .instrument(info_span!("confirm"))
+    .await
+}
+
+/// Returns the fee the network charges for a transaction. The fee is looked up against the
+/// latest blockhash, so transactions bound to a durable nonce are priced as well.
+pub async fn fee_for(client: &RpcClient, tx: &VersionedTransaction) -> TaskResult<u64> {
+    message_fee(client, tx.message.clone()).await
+}
+
+async fn message_fee(client: &RpcClient, mut message: VersionedMessage) -> TaskResult<u64> {
+    message.set_recent_blockhash(client.get_latest_blockhash().await?);
+    let fee = match &message {
+        VersionedMessage::Legacy(message) => client.get_fee_for_message(message).await?,
+        VersionedMessage::V0(message) => client.get_fee_for_message(message).await?,
+    };
+    Ok(fee)
+}
+
+/// Where a confirmed transaction landed and what it cost, as returned by `getTransaction`.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct Landed {
+    pub slot: u64,
+    /// Estimated production time of the block, as a Unix timestamp
+    pub block_time: Option<i64>,
+    /// Fee paid in lamports, unknown when the node has no status metadata
+    pub fee: Option<u64>,
+    pub compute_units_consumed: Option<u64>,
+}
+
+/// Looks up a confirmed transaction with `getTransaction`.
+pub async fn landed(client: &RpcClient, signature: &Signature) -> TaskResult<Landed> {
+    // Transactions cannot be fetched at `processed`
+    let commitment = if client.commitment().is_at_least_confirmed() {
+        client.commitment()
+    } else {
+        CommitmentConfig::confirmed()
+    };
+    let config = RpcTransactionConfig {
+        commitment: Some(commitment),
+        max_supported_transaction_version: Some(0),
+        ..RpcTransactionConfig::default()
+    };
+    let tx = client
+        .get_transaction_with_config(signature, config)
+        .await?;
+    let meta = tx.transaction.meta;
+    Ok(Landed {
+        slot: tx.slot,
+        block_time: tx.block_time,
+        fee: meta.as_ref().map(|meta| meta.fee),
+        compute_units_consumed: meta.and_then(|meta| meta.compute_units_consumed.into()),
+    })
+}
+
+/// What would happen if a transaction was sent, as reported by `simulateTransaction`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Simulation {
+    #[serde(serialize_with = "output::serialize_optional_display")]
+    pub error: Option<TransactionError>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+}
+
+impl Simulation {
+    /// The simulated outcome in the shape of a signature status.
+    pub fn status(&self) -> solana_sdk::transaction::Result<()> {
+        match &self.error {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Simulates a signed transaction without broadcasting it.
+pub async fn simulate(client: &RpcClient, tx: &VersionedTransaction) -> TaskResult<Simulation> {
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: true,
+        commitment: Some(client.commitment()),
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let result = client
+        .simulate_transaction_with_config(tx, config)
+        .await?
+        .value;
+
+    Ok(Simulation {
+        error: result.err,
+        logs: result.logs.unwrap_or_default(),
+        units_consumed: result.units_consumed,
+    })
+}
+
+async fn fetch_lookup_table(
+    client: &RpcClient,
+    address: &str,
+) -> TaskResult<AddressLookupTableAccount> {
+    let key = Pubkey::from_str(address)
+        .map_err(|e| TaskError::KeyError(format!("address lookup table {address}: {e}")))?;
+    let account = client.get_account(&key).await?;
+    let table = AddressLookupTable::deserialize(&account.data).map_err(|e| {
+        TaskError::ConfigError(format!("{address} is not an address lookup table: {e}"))
+    })?;
+
+    Ok(AddressLookupTableAccount {
+        key,
+        addresses: table.addresses.to_vec(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn confirm_waits_for_landed_transaction_past_expiry() {
+        // Landed at confirmed with an expired blockhash, then finalized on the next poll
+        let mocks = HashMap::from([
+            (
+                RpcRequest::GetSignatureStatuses,
+                json!({
+                    "context": {"slot": 1},
+                    "value": [{
+                        "slot": 1,
+                        "confirmations": 0,
+                        "err": null,
+                        "status": {"Ok": null},
+                        "confirmationStatus": "confirmed",
+                    }],
+                }),
+            ),
+            (
+                RpcRequest::IsBlockhashValid,
+                json!({"context": {"slot": 1}, "value": false}),
+            ),
+        ]);
+        let client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        let signature = Signature::from([1; SIGNATURE_BYTES]);
+        let confirmed = confirm(&client, &signature, &Hash::default()).await;
+        assert_eq!(confirmed.unwrap(), signature);
+    }
+
+    #[tokio::test]
+    async fn confirm_times_out_when_never_landed() {
+        let mocks = HashMap::from([(
+            RpcRequest::IsBlockhashValid,
+            json!({"context": {"slot": 1}, "value": false}),
+        )]);
+        let client = RpcClient::new_mock_with_mocks("sig_not_found".to_string(), mocks);
+        let signature = Signature::from([1; SIGNATURE_BYTES]);
+        let confirmed = confirm(&client, &signature, &Hash::default()).await;
+        assert!(matches!(
+            confirmed,
+            Err(TaskError::ConfirmationTimeout(s)) if s == signature
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+use clap::ValueEnum;
+use common::keys::{keypair_from_mnemonic, keypair_from_text};
+use common::keystore::write_private;
+use common::{TaskError, TaskResult};
+use solana_sdk::bs58;
+use solana_sdk::signature::{Keypair, Signer};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Ways a private key is written down.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+    /// 64-byte JSON array, as in solana-keygen keypair files
+    Json,
+    /// Base58 encoded 64-byte secret key, as wallets export it
+    Base58,
+    /// Hex encoded 64-byte secret key
+    Hex,
+    /// BIP39 seed phrase, only as input
+    Mnemonic,
+}
+
+/// Reads the key to convert from the command line, from stdin for `-`, or from the
+/// terminal without echo when it is omitted.
+pub fn read_key(key: Option<&str>) -> TaskResult<String> {
+    match key {
+        Some("-") => {
+            let mut key = String::new();
+            io::stdin().read_to_string(&mut key)?;
+            Ok(key)
+        }
+        Some(key) => Ok(key.to_string()),
+        None => Ok(rpassword::prompt_password("Key to convert: ")?),
+    }
+}
+
+/// Parses `key` in `format`, or in the format it looks like when `format` is `None`.
+pub fn parse(
+    key: &str,
+    format: Option<KeyFormat>,
+    derivation_path: Option<&str>,
+) -> TaskResult<Keypair> {
+    let key = key.trim();
+    let format = format.unwrap_or_else(|| detect(key));
+    match format {
+        KeyFormat::Mnemonic => keypair_from_mnemonic(key, derivation_path, ""),
+        KeyFormat::Hex => decode_hex(key).and_then(|bytes| {
+            Keypair::from_bytes(&bytes).map_err(|e| format!("invalid keypair: {e}"))
+        }),
+        // A keypair file or a keystore can stand for the JSON array
+        KeyFormat::Json | KeyFormat::Base58 => keypair_from_text(key),
+    }
+    .map_err(TaskError::KeyError)
+}
+
+/// Writes the secret key of `keypair` in `format`.
+pub fn encode(keypair: &Keypair, format: KeyFormat) -> TaskResult<String> {
+    let bytes = keypair.to_bytes();
+    match format {
+        KeyFormat::Json => {
+            serde_json::to_string(&bytes.to_vec()).map_err(|e| TaskError::KeyError(e.to_string()))
+        }
+        KeyFormat::Base58 => Ok(bs58::encode(bytes).into_string()),
+        KeyFormat::Hex => Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect()),
+        KeyFormat::Mnemonic => Err(TaskError::ConfigError(
+            "a seed phrase cannot be recovered from a keypair".to_string(),
+        )),
+    }
+}
+
+/// Writes the converted key to `output`, readable only by its owner, and prints the address
+/// it belongs to, printing the key itself only when `unsafe_print` asks for it.
+pub fn write(
+    keypair: &Keypair,
+    secret: &str,
+    output: Option<&Path>,
+    unsafe_print: bool,
+) -> TaskResult<()> {
+    if let Some(path) = output {
+        write_private(path, secret)?;
+        println!("Wrote key of {} to {}", keypair.pubkey(), path.display());
+    } else {
+        println!("Public key: {}", keypair.pubkey());
+    }
+    match (unsafe_print, output) {
+        (true, _) => println!("{secret}"),
+        (false, None) => eprintln!("Pass --output or --unsafe-print to get the converted key"),
+        (false, Some(_)) => {}
+    }
+    Ok(())
+}
+
+fn detect(key: &str) -> KeyFormat {
+    if key.starts_with('[') {
+        KeyFormat::Json
+    } else if key.len() == 128 && key.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        KeyFormat::Hex
+    } else if key.split_whitespace().count() >= 12 {
+        KeyFormat::Mnemonic
+    } else {
+        KeyFormat::Base58
+    }
+}
+
+fn decode_hex(key: &str) -> Result<Vec<u8>, String> {
+    let key = key.strip_prefix("0x").unwrap_or(key);
+    if !key.len().is_multiple_of(2) || !key.is_ascii() {
+        return Err("invalid hex secret key".to_string());
+    }
+    (0..key.len())
+        .step_by(2)
+        .map(|index| {
+            u8::from_str_radix(&key[index..index + 2], 16)
+                .map_err(|_| "invalid hex secret key".to_string())
+        })
+        .collect()
+}
@@ -0,0 +1,271 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use common::keys::keypair_from_text;
+use common::keystore::{write_private, Keystore, PASSPHRASE_ENV};
+use common::{TaskError, TaskResult};
+use solana_sdk::signature::{Keypair, Signer};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+};
+
+mod keyconv;
+mod vanity;
+
+#[derive(Parser, Debug)]
+#[command(about = "Encrypts private keys into passphrase protected keystores and back")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Encrypts a private key into a keystore file
+    Encrypt {
+        /// Private key to encrypt: a keypair file path, a JSON byte array or a base58 secret key
+        #[arg(long, default_value = "~/.config/solana/id.json")]
+        key: String,
+        /// Path of the keystore file to write
+        #[arg(long)]
+        output: PathBuf,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Decrypts a keystore into a solana-keygen JSON keypair
+    Decrypt {
+        /// Path of the keystore file
+        keystore: PathBuf,
+        /// Path of the keypair file to write, readable only by its owner
+        #[arg(long, required_unless_present = "unsafe_print")]
+        output: Option<PathBuf>,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+        /// Print the keypair to stdout instead of writing it to a file
+        #[arg(long, conflicts_with = "output")]
+        unsafe_print: bool,
+    },
+    /// Generates keypairs until their addresses start and end as asked, and encrypts the
+    /// ones found into keystore files named after their addresses
+    Vanity {
+        /// Characters the address starts with
+        #[arg(long, default_value = "")]
+        prefix: String,
+        /// Characters the address ends with
+        #[arg(long, default_value = "")]
+        suffix: String,
+        /// Match the prefix and suffix in any case, which is much faster
+        #[arg(long)]
+        ignore_case: bool,
+        /// Number of addresses to find
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+        /// Threads generating keypairs, one per CPU by default
+        #[arg(long)]
+        threads: Option<usize>,
+        /// Directory to write the keystore files to
+        #[arg(long, default_value = ".")]
+        output_dir: PathBuf,
+    },
+    /// Generates new keypairs into keystore files named after their addresses, and adds
+    /// them to a list of a config file
+    Keygen {
+        /// Number of keypairs to generate
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+        /// Directory to write the keystore files to
+        #[arg(long, default_value = ".")]
+        output_dir: PathBuf,
+        /// Config file to add the new keys to
+        #[arg(long, requires = "list")]
+        config: Option<String>,
+        /// List of the config the new keys are added to
+        #[arg(long, value_enum, requires = "config")]
+        list: Option<ConfigList>,
+    },
+    /// Converts a private key between the formats the tasks read, printing the address it
+    /// belongs to. The converted key is only shown with --unsafe-print
+    Keyconv {
+        /// Key to convert: a keypair or keystore file path, a JSON byte array, a base58 or
+        /// hex secret key or a seed phrase, `-` to read it from stdin. Asked for without
+        /// echo when omitted, which keeps it out of the shell history
+        key: Option<String>,
+        /// Format of the key, detected when omitted
+        #[arg(long, value_enum)]
+        from: Option<keyconv::KeyFormat>,
+        /// Format to convert to
+        #[arg(long, value_enum, default_value = "json")]
+        to: keyconv::KeyFormat,
+        /// Derivation path of a seed phrase, e.g. `m/44'/501'/0'/0'`. Without one the key
+        /// is derived as `solana-keygen recover` does
+        #[arg(long)]
+        derivation_path: Option<String>,
+        /// Path of the file to write the converted key to
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+        /// Print the converted key to stdout
+        #[arg(long)]
+        unsafe_print: bool,
+    },
+}
+
+/// Lists of the task configs `keygen` adds to.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ConfigList {
+    /// The `wallets` of a task1 config, by address
+    Wallets,
+    /// The `recepient_pyblic_keys` of a task2 config, by address
+    Recipients,
+    /// The `sender_private_keys` of a task2 config, by keystore path
+    Senders,
+}
+
+impl ConfigList {
+    fn key(self) -> &'static str {
+        match self {
+            ConfigList::Wallets => "wallets",
+            ConfigList::Recipients => "recepient_pyblic_keys",
+            ConfigList::Senders => "sender_private_keys",
+        }
+    }
+}
+
+// Function for asking the new passphrase twice, unless it is set in the environment
+fn new_passphrase() -> TaskResult<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(TaskError::KeyError(
+            "passphrase must not be empty".to_string(),
+        ));
+    }
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(TaskError::KeyError("passphrases do not match".to_string()));
+    }
+    Ok(passphrase)
+}
+
+// Function for refusing to overwrite a file by accident
+fn check_output(path: &Path, force: bool) -> TaskResult<()> {
+    if path.exists() && !force {
+        return Err(TaskError::ConfigError(format!(
+            "{} already exists, pass --force to overwrite it",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> TaskResult<()> {
+    match args.command {
+        Command::Encrypt { key, output, force } => {
+            check_output(&output, force)?;
+            let keypair = keypair_from_text(&key).map_err(TaskError::KeyError)?;
+            let keystore =
+                Keystore::encrypt(&keypair, &new_passphrase()?).map_err(TaskError::KeyError)?;
+            keystore.save(&output).map_err(TaskError::ConfigError)?;
+            println!(
+                "Wrote keystore of {} to {}",
+                keypair.pubkey(),
+                output.display()
+            );
+        }
+        Command::Decrypt {
+            keystore,
+            output,
+            force,
+            unsafe_print: _,
+        } => {
+            let keystore = Keystore::load(&keystore).map_err(TaskError::ConfigError)?;
+            let keypair = keystore.unlock().map_err(TaskError::KeyError)?;
+            let json = serde_json::to_string(&keypair.to_bytes().to_vec())
+                .map_err(|e| TaskError::KeyError(e.to_string()))?;
+            match output {
+                Some(path) => {
+                    check_output(&path, force)?;
+                    write_private(&path, &json)?;
+                    println!(
+                        "Wrote keypair of {} to {}",
+                        keypair.pubkey(),
+                        path.display()
+                    );
+                }
+                None => println!("{json}"),
+            }
+        }
+        Command::Vanity {
+            prefix,
+            suffix,
+            ignore_case,
+            count,
+            threads,
+            output_dir,
+        } => {
+            let pattern = vanity::Pattern::new(&prefix, &suffix, ignore_case)?;
+            let threads = threads.unwrap_or_else(|| {
+                thread::available_parallelism().map_or(1, |threads| threads.get())
+            });
+            let passphrase = new_passphrase()?;
+            vanity::grind(&pattern, count, threads, &output_dir, &passphrase)?;
+        }
+        Command::Keyconv {
+            key,
+            from,
+            to,
+            derivation_path,
+            output,
+            force,
+            unsafe_print,
+        } => {
+            if let Some(path) = &output {
+                check_output(path, force)?;
+            }
+            let key = keyconv::read_key(key.as_deref())?;
+            let keypair = keyconv::parse(&key, from, derivation_path.as_deref())?;
+            let secret = keyconv::encode(&keypair, to)?;
+            keyconv::write(&keypair, &secret, output.as_deref(), unsafe_print)?;
+        }
+        Command::Keygen {
+            count,
+            output_dir,
+            config,
+            list,
+        } => {
+            let passphrase = new_passphrase()?;
+            std::fs::create_dir_all(&output_dir)?;
+            let mut items = Vec::with_capacity(count);
+            for _ in 0..count {
+                let keypair = Keypair::new();
+                let path = output_dir.join(format!("{}.json", keypair.pubkey()));
+                let keystore =
+                    Keystore::encrypt(&keypair, &passphrase).map_err(TaskError::KeyError)?;
+                keystore.save(&path).map_err(TaskError::ConfigError)?;
+                println!(
+                    "Wrote keystore of {} to {}",
+                    keypair.pubkey(),
+                    path.display()
+                );
+                items.push(match list {
+                    Some(ConfigList::Senders) => path.display().to_string(),
+                    _ => keypair.pubkey().to_string(),
+                });
+            }
+            if let (Some(config), Some(list)) = (config, list) {
+                common::config::append_to_list(&config, list.key(), &items)?;
+                println!("Added {} keys to {} in {config}", items.len(), list.key());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    common::exit(run(Args::parse()))
+}
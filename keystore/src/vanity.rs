@@ -0,0 +1,136 @@
+use common::keystore::Keystore;
+use common::{TaskError, TaskResult};
+use solana_sdk::signature::{Keypair, Signer};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+// Keypairs a thread generates between two updates of the shared counter
+const BATCH: u64 = 1_000;
+// How often the throughput is reported
+const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The addresses searched for.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    prefix: String,
+    suffix: String,
+    ignore_case: bool,
+}
+
+impl Pattern {
+    /// Checks that the prefix and the suffix can appear in a base58 address.
+    pub fn new(prefix: &str, suffix: &str, ignore_case: bool) -> TaskResult<Self> {
+        if prefix.is_empty() && suffix.is_empty() {
+            return Err(TaskError::ConfigError(
+                "give a --prefix, a --suffix or both".to_string(),
+            ));
+        }
+        for c in prefix.chars().chain(suffix.chars()) {
+            let valid = match ignore_case {
+                true => {
+                    BASE58_ALPHABET.contains(c.to_ascii_lowercase())
+                        || BASE58_ALPHABET.contains(c.to_ascii_uppercase())
+                }
+                false => BASE58_ALPHABET.contains(c),
+            };
+            if !valid {
+                return Err(TaskError::ConfigError(format!(
+                    "{c:?} never appears in an address, which only uses {BASE58_ALPHABET}"
+                )));
+            }
+        }
+        let fold = |text: &str| match ignore_case {
+            true => text.to_ascii_lowercase(),
+            false => text.to_string(),
+        };
+        Ok(Pattern {
+            prefix: fold(prefix),
+            suffix: fold(suffix),
+            ignore_case,
+        })
+    }
+
+    fn matches(&self, address: &str) -> bool {
+        let folded;
+        let address = match self.ignore_case {
+            true => {
+                folded = address.to_ascii_lowercase();
+                &folded
+            }
+            false => address,
+        };
+        address.starts_with(&self.prefix) && address.ends_with(&self.suffix)
+    }
+}
+
+/// Generates keypairs on `threads` threads until `count` of them match `pattern`, and
+/// writes each into a keystore named after its address in `output_dir`.
+pub fn grind(
+    pattern: &Pattern,
+    count: usize,
+    threads: usize,
+    output_dir: &Path,
+    passphrase: &str,
+) -> TaskResult<()> {
+    std::fs::create_dir_all(output_dir)?;
+    let tried = Arc::new(AtomicU64::new(0));
+    let done = Arc::new(AtomicBool::new(false));
+    let (found, matches) = mpsc::channel();
+    let workers: Vec<_> = (0..threads.max(1))
+        .map(|_| {
+            let (pattern, tried, done, found) =
+                (pattern.clone(), tried.clone(), done.clone(), found.clone());
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    for _ in 0..BATCH {
+                        let keypair = Keypair::new();
+                        if pattern.matches(&keypair.pubkey().to_string())
+                            && found.send(keypair).is_err()
+                        {
+                            return;
+                        }
+                    }
+                    tried.fetch_add(BATCH, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+    drop(found);
+
+    let start = Instant::now();
+    let mut written = 0;
+    while written < count {
+        let keypair = match matches.recv_timeout(REPORT_INTERVAL) {
+            Ok(keypair) => keypair,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                report(tried.load(Ordering::Relaxed), start.elapsed());
+                continue;
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let path = output_dir.join(format!("{}.json", keypair.pubkey()));
+        let keystore = Keystore::encrypt(&keypair, passphrase).map_err(TaskError::KeyError)?;
+        keystore.save(&path).map_err(TaskError::ConfigError)?;
+        written += 1;
+        println!("Found {}, wrote {}", keypair.pubkey(), path.display());
+    }
+    done.store(true, Ordering::Relaxed);
+    drop(matches);
+    for worker in workers {
+        let _ = worker.join();
+    }
+    report(tried.load(Ordering::Relaxed), start.elapsed());
+    Ok(())
+}
+
+fn report(tried: u64, elapsed: Duration) {
+    eprintln!(
+        "{tried} keypairs tried in {}s, {:.0} per second",
+        elapsed.as_secs(),
+        tried as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+}
@@ -0,0 +1,353 @@
+use clap::{Parser, Subcommand};
+use common::amount::Amount;
+use common::compute_budget::ComputeBudgetConfig;
+use common::keys::KeySource;
+use common::nonce::{DurableNonce, NonceConfig};
+use common::rate_limit::RateLimitConfig;
+use common::rpc::{Network, RetryConfig, RpcEndpoints, RpcTimeouts};
+use common::signer::TxSigner;
+use common::telemetry::{LogArgs, TelemetryConfig};
+use common::transaction::{SendConfig, TransactionBuilder, TransactionConfig};
+use common::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::{nonce_utils, rpc_client::RpcClient};
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    instruction::Instruction,
+    native_token::lamports_to_sol,
+    nonce::State,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    system_instruction,
+};
+use std::{process::ExitCode, str::FromStr, sync::Arc, time::Duration};
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Manages the durable nonce accounts of the senders of a task2 config",
+    long_about = "Manages the durable nonce accounts of the senders of a task2 config.\n\nEvery sender is the authority of the nonce account it creates. Commands other than `create` act on the accounts listed in `nonce_accounts`."
+)]
+struct Args {
+    /// Path to the config file, YAML, TOML or JSON by its extension
+    #[arg(long, default_value = "config.yaml")]
+    config: String,
+    /// Profile from the `profiles` of the config to lay over its top-level settings
+    #[arg(long)]
+    profile: Option<String>,
+    /// RPC endpoint to use instead of `rpc_url` and `rpc_urls` from the config
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// Public cluster to use instead of the endpoints from the config, `--rpc-url` taking
+    /// precedence
+    #[arg(long, value_enum)]
+    network: Option<Network>,
+    /// Commitment level of queries and confirmations, instead of `commitment` from the
+    /// config or `finalized`
+    #[arg(long)]
+    commitment: Option<CommitmentConfig>,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Creates a rent exempt nonce account for every sender without one and prints the
+    /// `nonce_accounts` entries to add to the config
+    Create {
+        /// Lamports (or SOL with a decimal point) deposited on top of the rent exemption
+        #[arg(long)]
+        amount: Option<Amount>,
+    },
+    /// Prints the balance, authority and stored nonce of every nonce account
+    Show,
+    /// Sends lamports (or SOL with a decimal point) from every sender to its nonce account
+    Fund { amount: Amount },
+    /// Advances every nonce account, invalidating transactions signed with its nonce
+    Advance,
+    /// Withdraws from every nonce account back to its sender
+    Withdraw {
+        /// Lamports (or SOL with a decimal point) to withdraw, all of them when omitted,
+        /// which closes the account
+        #[arg(long)]
+        amount: Option<Amount>,
+        /// Address to withdraw to instead of the sender
+        #[arg(long)]
+        to: Option<String>,
+    },
+}
+
+/// The parts of a task2 config the nonce commands use.
+#[derive(Serialize, Deserialize, Debug)]
+struct YamlFile {
+    #[serde(flatten)]
+    rpc: RpcEndpoints,
+    #[serde(default)]
+    commitment: Option<CommitmentLevel>,
+    #[serde(default)]
+    sender_private_keys: Vec<KeySource>,
+    #[serde(default)]
+    nonce_accounts: Vec<SenderNonce>,
+    #[serde(flatten)]
+    compute_budget: ComputeBudgetConfig,
+    #[serde(flatten)]
+    transaction: TransactionConfig,
+    #[serde(default)]
+    retry: RetryConfig,
+    #[serde(flatten)]
+    timeouts: RpcTimeouts,
+    #[serde(default)]
+    send: SendConfig,
+    #[serde(default)]
+    rate_limit: Option<RateLimitConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SenderNonce {
+    /// Public key of the sender this nonce account belongs to
+    sender: String,
+    #[serde(flatten)]
+    nonce: NonceConfig,
+}
+
+struct Sender {
+    signer: Arc<dyn TxSigner>,
+    nonce: Option<DurableNonce>,
+}
+
+/// Builds, signs and sends the transactions of a command.
+struct Sending {
+    client: RpcClient,
+    builder: TransactionBuilder,
+    send: SendConfig,
+    confirmation_timeout: Option<Duration>,
+}
+
+impl Sending {
+    // Function for sending instructions paid by `payer`, signed as well by the nonce
+    // authority or the new nonce account when given
+    async fn send(
+        &self,
+        instructions: Vec<Instruction>,
+        payer: &dyn TxSigner,
+        signer: Option<&Keypair>,
+    ) -> TaskResult<Signature> {
+        let mut signers = vec![payer];
+        if let Some(signer) = signer {
+            signers.push(signer);
+        }
+        let tx = self
+            .builder
+            .build(&self.client, instructions, &payer.pubkey(), &signers, None)
+            .await?;
+        common::transaction::send_and_confirm(
+            &self.client,
+            &tx,
+            &self.send,
+            self.confirmation_timeout,
+        )
+        .await
+    }
+}
+
+// Function for pairing every sender with the nonce account configured for it
+fn senders(config: &YamlFile) -> TaskResult<Vec<Sender>> {
+    let mut senders = Vec::with_capacity(config.sender_private_keys.len());
+    for (i, key) in config.sender_private_keys.iter().enumerate() {
+        let signer = key.signer().map_err(|e| {
+            TaskError::KeyError(format!("sender private key number {}: {e}", i + 1))
+        })?;
+        let pubkey = signer.pubkey().to_string();
+        let nonce = config
+            .nonce_accounts
+            .iter()
+            .find(|entry| entry.sender == pubkey)
+            .map(|entry| DurableNonce::from_config(&entry.nonce))
+            .transpose()?;
+        senders.push(Sender { signer, nonce });
+    }
+    if senders.is_empty() {
+        return Err(TaskError::ConfigError(
+            "sender_private_keys must not be empty".to_string(),
+        ));
+    }
+    Ok(senders)
+}
+
+fn lamports(amount: &Amount) -> TaskResult<u64> {
+    amount.to_lamports().map_err(TaskError::ConfigError)
+}
+
+// Function for creating a nonce account for every sender without one
+async fn create(sending: &Sending, senders: &[Sender], amount: Option<&Amount>) -> TaskResult<()> {
+    let rent = sending
+        .client
+        .get_minimum_balance_for_rent_exemption(State::size())
+        .await?;
+    let deposit = rent + amount.map(lamports).transpose()?.unwrap_or(0);
+    let mut created = Vec::new();
+    for sender in senders {
+        let payer = sender.signer.pubkey();
+        if let Some(nonce) = &sender.nonce {
+            println!("{payer} already has nonce account {}", nonce.account);
+            continue;
+        }
+        let account = Keypair::new();
+        let address = Signer::pubkey(&account);
+        let instructions =
+            system_instruction::create_nonce_account(&payer, &address, &payer, deposit);
+        let signature = sending
+            .send(instructions, sender.signer.as_ref(), Some(&account))
+            .await?;
+        println!(
+            "Created nonce account {address} for {payer} with {} SOL: {signature}",
+            lamports_to_sol(deposit)
+        );
+        created.push((payer, address));
+    }
+    if !created.is_empty() {
+        println!("\nnonce_accounts:");
+        for (sender, account) in created {
+            println!("  - sender: {sender}\n    nonce_account: {account}");
+        }
+    }
+    Ok(())
+}
+
+// Function for printing the state of every configured nonce account
+async fn show(client: &RpcClient, senders: &[Sender]) -> TaskResult<()> {
+    for sender in senders {
+        let payer = sender.signer.pubkey();
+        let Some(nonce) = &sender.nonce else {
+            println!("{payer}: no nonce account");
+            continue;
+        };
+        let account = client
+            .get_account_with_commitment(&nonce.account, client.commitment())
+            .await?
+            .value;
+        let Some(account) = account else {
+            println!("{payer}: nonce account {} does not exist", nonce.account);
+            continue;
+        };
+        let data = nonce_utils::data_from_account(&account)
+            .map_err(|e| TaskError::ConfigError(format!("nonce account {}: {e}", nonce.account)))?;
+        println!(
+            "{payer}: nonce account {}, {} SOL, authority {}, nonce {}",
+            nonce.account,
+            lamports_to_sol(account.lamports),
+            data.authority,
+            data.blockhash()
+        );
+    }
+    Ok(())
+}
+
+// Function for running a command on every sender that has a nonce account, the
+// instructions being made from the sender, its nonce and the balance of the nonce account
+async fn for_each_nonce(
+    sending: &Sending,
+    senders: &[Sender],
+    action: &str,
+    instructions: impl Fn(&Pubkey, &DurableNonce, u64) -> Vec<Instruction>,
+) -> TaskResult<()> {
+    for sender in senders {
+        let payer = sender.signer.pubkey();
+        let Some(nonce) = &sender.nonce else {
+            println!("{payer}: no nonce account");
+            continue;
+        };
+        let balance = sending.client.get_balance(&nonce.account).await?;
+        let signature = sending
+            .send(
+                instructions(&payer, nonce, balance),
+                sender.signer.as_ref(),
+                nonce.authority.as_ref(),
+            )
+            .await?;
+        println!(
+            "{action} nonce account {} of {payer}: {signature}",
+            nonce.account
+        );
+    }
+    Ok(())
+}
+
+async fn run(args: Args) -> TaskResult<()> {
+    let config: YamlFile = common::config::load(&args.config, args.profile.as_deref())?;
+    let _telemetry = common::telemetry::init(&args.log, &TelemetryConfig::default(), "nonce")?;
+    let senders = senders(&config)?;
+    let commitment = common::config::commitment(
+        args.commitment,
+        config.commitment,
+        CommitmentLevel::Finalized,
+    );
+    let client = common::rpc::new_client(
+        config.rpc.urls(args.rpc_url, args.network)?,
+        config.rpc.fan_out,
+        commitment,
+        &config.retry,
+        config.rate_limit.as_ref(),
+        &config.timeouts,
+        None,
+    )?;
+    if let Command::Show = args.command {
+        return show(&client, &senders).await;
+    }
+
+    let builder = TransactionBuilder::new(&client, config.compute_budget, &config.transaction)
+        .await?
+        .with_send_config(&config.send)?;
+    let sending = Sending {
+        client,
+        builder,
+        send: config.send,
+        confirmation_timeout: config.timeouts.confirmation_timeout,
+    };
+    match args.command {
+        Command::Create { amount } => create(&sending, &senders, amount.as_ref()).await,
+        Command::Show => unreachable!("handled above"),
+        Command::Fund { amount } => {
+            let amount = lamports(&amount)?;
+            for_each_nonce(&sending, &senders, "Funded", |payer, nonce, _| {
+                vec![system_instruction::transfer(payer, &nonce.account, amount)]
+            })
+            .await
+        }
+        Command::Advance => {
+            for_each_nonce(&sending, &senders, "Advanced", |payer, nonce, _| {
+                vec![nonce.advance_instruction(payer)]
+            })
+            .await
+        }
+        Command::Withdraw { amount, to } => {
+            let amount = amount.as_ref().map(lamports).transpose()?;
+            let to = to
+                .map(|to| {
+                    Pubkey::from_str(&to)
+                        .map_err(|e| TaskError::KeyError(format!("withdrawal address {to}: {e}")))
+                })
+                .transpose()?;
+            for_each_nonce(
+                &sending,
+                &senders,
+                "Withdrew from",
+                |payer, nonce, balance| {
+                    vec![system_instruction::withdraw_nonce_account(
+                        &nonce.account,
+                        &nonce.authority_pubkey(payer),
+                        &to.unwrap_or(*payer),
+                        amount.unwrap_or(balance),
+                    )]
+                },
+            )
+            .await
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    common::exit(run(Args::parse()).await)
+}
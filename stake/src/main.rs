@@ -0,0 +1,413 @@
+use clap::{Parser, Subcommand};
+use common::amount::Amount;
+use common::compute_budget::ComputeBudgetConfig;
+use common::keys::KeySource;
+use common::rate_limit::RateLimitConfig;
+use common::rpc::{Network, RetryConfig, RpcEndpoints, RpcTimeouts};
+use common::signer::TxSigner;
+use common::stake::StakeContext;
+use common::telemetry::{LogArgs, TelemetryConfig};
+use common::transaction::{SendConfig, TransactionBuilder, TransactionConfig};
+use common::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    instruction::Instruction,
+    native_token::lamports_to_sol,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    stake::{
+        instruction as stake_instruction,
+        state::{Authorized, Lockup, StakeStateV2},
+    },
+};
+use std::{process::ExitCode, str::FromStr, sync::Arc, time::Duration};
+
+mod rewards;
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Manages the stake accounts of the senders of a task2 config",
+    long_about = "Manages the stake accounts of the senders of a task2 config.\n\nEvery sender is the stake and withdraw authority of the stake accounts it creates. Commands other than `create` act on the accounts listed in `stake_accounts`."
+)]
+struct Args {
+    /// Path to the config file, YAML, TOML or JSON by its extension
+    #[arg(long, default_value = "config.yaml")]
+    config: String,
+    /// Profile from the `profiles` of the config to lay over its top-level settings
+    #[arg(long)]
+    profile: Option<String>,
+    /// RPC endpoint to use instead of `rpc_url` and `rpc_urls` from the config
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// Public cluster to use instead of the endpoints from the config, `--rpc-url` taking
+    /// precedence
+    #[arg(long, value_enum)]
+    network: Option<Network>,
+    /// Commitment level of queries and confirmations, instead of `commitment` from the
+    /// config or `finalized`
+    #[arg(long)]
+    commitment: Option<CommitmentConfig>,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Creates a stake account for every sender, delegated to `vote_account` when set, and
+    /// prints the `stake_accounts` entries to add to the config
+    Create {
+        /// Lamports (or SOL with a decimal point) to stake, on top of the rent exemption
+        amount: Amount,
+    },
+    /// Prints the balance, delegation and activation of every stake account
+    Status,
+    /// Prints the inflation rewards of every stake account and of `vote_account` per epoch
+    Rewards {
+        /// Number of completed epochs to report, counting back from the last one
+        #[arg(long, default_value_t = 5)]
+        epochs: u64,
+        /// Also write the rewards to this CSV file
+        #[arg(long)]
+        csv: Option<String>,
+    },
+    /// Delegates every stake account to `vote_account`
+    Delegate,
+    /// Deactivates every stake account, its stake can be withdrawn once it cooled down
+    Deactivate,
+    /// Withdraws from every stake account back to its sender
+    Withdraw {
+        /// Lamports (or SOL with a decimal point) to withdraw, all of them when omitted,
+        /// which closes the account
+        #[arg(long)]
+        amount: Option<Amount>,
+        /// Address to withdraw to instead of the sender
+        #[arg(long)]
+        to: Option<String>,
+    },
+}
+
+/// The parts of a task2 config the stake commands use, with the validator to stake with.
+#[derive(Serialize, Deserialize, Debug)]
+struct YamlFile {
+    #[serde(flatten)]
+    rpc: RpcEndpoints,
+    #[serde(default)]
+    commitment: Option<CommitmentLevel>,
+    #[serde(default)]
+    sender_private_keys: Vec<KeySource>,
+    /// Vote account of the validator stake is delegated to
+    #[serde(default)]
+    vote_account: Option<String>,
+    #[serde(default)]
+    stake_accounts: Vec<SenderStake>,
+    #[serde(flatten)]
+    compute_budget: ComputeBudgetConfig,
+    #[serde(flatten)]
+    transaction: TransactionConfig,
+    #[serde(default)]
+    retry: RetryConfig,
+    #[serde(flatten)]
+    timeouts: RpcTimeouts,
+    #[serde(default)]
+    send: SendConfig,
+    #[serde(default)]
+    rate_limit: Option<RateLimitConfig>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SenderStake {
+    /// Public key of the sender, the authority of the stake account
+    sender: String,
+    stake_account: String,
+}
+
+struct Sender {
+    signer: Arc<dyn TxSigner>,
+    stake_accounts: Vec<Pubkey>,
+}
+
+/// Builds, signs and sends the transactions of a command.
+struct Sending {
+    client: RpcClient,
+    builder: TransactionBuilder,
+    send: SendConfig,
+    confirmation_timeout: Option<Duration>,
+}
+
+impl Sending {
+    // Function for sending instructions paid by `payer`, signed as well by the new stake
+    // account when given
+    async fn send(
+        &self,
+        instructions: Vec<Instruction>,
+        payer: &dyn TxSigner,
+        signer: Option<&Keypair>,
+    ) -> TaskResult<Signature> {
+        let mut signers = vec![payer];
+        if let Some(signer) = signer {
+            signers.push(signer);
+        }
+        let tx = self
+            .builder
+            .build(&self.client, instructions, &payer.pubkey(), &signers, None)
+            .await?;
+        common::transaction::send_and_confirm(
+            &self.client,
+            &tx,
+            &self.send,
+            self.confirmation_timeout,
+        )
+        .await
+    }
+}
+
+// Function for pairing every sender with the stake accounts configured for it
+fn senders(config: &YamlFile) -> TaskResult<Vec<Sender>> {
+    let mut senders = Vec::with_capacity(config.sender_private_keys.len());
+    for (i, key) in config.sender_private_keys.iter().enumerate() {
+        let signer = key.signer().map_err(|e| {
+            TaskError::KeyError(format!("sender private key number {}: {e}", i + 1))
+        })?;
+        let pubkey = signer.pubkey().to_string();
+        let stake_accounts = config
+            .stake_accounts
+            .iter()
+            .filter(|entry| entry.sender == pubkey)
+            .map(|entry| parse_pubkey(&entry.stake_account, "stake account"))
+            .collect::<TaskResult<_>>()?;
+        senders.push(Sender {
+            signer,
+            stake_accounts,
+        });
+    }
+    if senders.is_empty() {
+        return Err(TaskError::ConfigError(
+            "sender_private_keys must not be empty".to_string(),
+        ));
+    }
+    Ok(senders)
+}
+
+fn parse_pubkey(address: &str, name: &str) -> TaskResult<Pubkey> {
+    Pubkey::from_str(address).map_err(|e| TaskError::KeyError(format!("{name} {address}: {e}")))
+}
+
+fn lamports(amount: &Amount) -> TaskResult<u64> {
+    amount.to_lamports().map_err(TaskError::ConfigError)
+}
+
+// Function for creating a stake account for every sender, delegated right away when a
+// vote account is configured
+async fn create(
+    sending: &Sending,
+    senders: &[Sender],
+    amount: &Amount,
+    vote_account: Option<&Pubkey>,
+) -> TaskResult<()> {
+    let rent = sending
+        .client
+        .get_minimum_balance_for_rent_exemption(StakeStateV2::size_of())
+        .await?;
+    let deposit = rent + lamports(amount)?;
+    let mut created = Vec::new();
+    for sender in senders {
+        let payer = sender.signer.pubkey();
+        let account = Keypair::new();
+        let address = Signer::pubkey(&account);
+        let authorized = Authorized::auto(&payer);
+        let instructions = match vote_account {
+            Some(vote_account) => stake_instruction::create_account_and_delegate_stake(
+                &payer,
+                &address,
+                vote_account,
+                &authorized,
+                &Lockup::default(),
+                deposit,
+            ),
+            None => stake_instruction::create_account(
+                &payer,
+                &address,
+                &authorized,
+                &Lockup::default(),
+                deposit,
+            ),
+        };
+        let signature = sending
+            .send(instructions, sender.signer.as_ref(), Some(&account))
+            .await?;
+        println!(
+            "Created stake account {address} for {payer} with {} SOL: {signature}",
+            lamports_to_sol(deposit)
+        );
+        created.push((payer, address));
+    }
+    println!("\nstake_accounts:");
+    for (sender, account) in created {
+        println!("  - sender: {sender}\n    stake_account: {account}");
+    }
+    Ok(())
+}
+
+// Function for printing the state of every configured stake account
+async fn status(client: &RpcClient, senders: &[Sender]) -> TaskResult<()> {
+    let context = StakeContext::fetch(client).await?;
+    for sender in senders {
+        let payer = sender.signer.pubkey();
+        if sender.stake_accounts.is_empty() {
+            println!("{payer}: no stake account");
+        }
+        for address in &sender.stake_accounts {
+            let account = client
+                .get_account_with_commitment(address, client.commitment())
+                .await?
+                .value;
+            let Some(account) = account else {
+                println!("{payer}: stake account {address} does not exist");
+                continue;
+            };
+            let state = common::stake::state(&account.data).ok_or_else(|| {
+                TaskError::ConfigError(format!("{address} is not a stake account"))
+            })?;
+            let balance = lamports_to_sol(account.lamports);
+            let (Some(delegation), Some(activation)) =
+                (state.delegation(), context.activation(&state))
+            else {
+                println!("{payer}: stake account {address}, {balance} SOL, not delegated");
+                continue;
+            };
+            println!(
+                "{payer}: stake account {address}, {balance} SOL, delegated to {}, {} SOL active, {} SOL activating, {} SOL deactivating",
+                delegation.voter_pubkey,
+                lamports_to_sol(activation.effective),
+                lamports_to_sol(activation.activating),
+                lamports_to_sol(activation.deactivating)
+            );
+        }
+    }
+    Ok(())
+}
+
+// Function for running a command on every stake account of every sender, the instruction
+// being made from the sender, the stake account and its balance
+async fn for_each_stake(
+    sending: &Sending,
+    senders: &[Sender],
+    action: &str,
+    instruction: impl Fn(&Pubkey, &Pubkey, u64) -> Instruction,
+) -> TaskResult<()> {
+    for sender in senders {
+        let payer = sender.signer.pubkey();
+        if sender.stake_accounts.is_empty() {
+            println!("{payer}: no stake account");
+        }
+        for address in &sender.stake_accounts {
+            let balance = sending.client.get_balance(address).await?;
+            let signature = sending
+                .send(
+                    vec![instruction(&payer, address, balance)],
+                    sender.signer.as_ref(),
+                    None,
+                )
+                .await?;
+            println!("{action} stake account {address} of {payer}: {signature}");
+        }
+    }
+    Ok(())
+}
+
+async fn run(args: Args) -> TaskResult<()> {
+    let config: YamlFile = common::config::load(&args.config, args.profile.as_deref())?;
+    let _telemetry = common::telemetry::init(&args.log, &TelemetryConfig::default(), "stake")?;
+    let senders = senders(&config)?;
+    let commitment = common::config::commitment(
+        args.commitment,
+        config.commitment,
+        CommitmentLevel::Finalized,
+    );
+    let client = common::rpc::new_client(
+        config.rpc.urls(args.rpc_url, args.network)?,
+        config.rpc.fan_out,
+        commitment,
+        &config.retry,
+        config.rate_limit.as_ref(),
+        &config.timeouts,
+        None,
+    )?;
+    let vote_account = config
+        .vote_account
+        .as_deref()
+        .map(|address| parse_pubkey(address, "vote account"))
+        .transpose()?;
+    match &args.command {
+        Command::Status => return status(&client, &senders).await,
+        Command::Rewards { epochs, csv } => {
+            let accounts: Vec<Pubkey> = senders
+                .iter()
+                .flat_map(|sender| sender.stake_accounts.iter().copied())
+                .chain(vote_account)
+                .collect();
+            return rewards::report(&client, &accounts, *epochs, csv.as_deref()).await;
+        }
+        _ => {}
+    }
+
+    let builder = TransactionBuilder::new(&client, config.compute_budget, &config.transaction)
+        .await?
+        .with_send_config(&config.send)?;
+    let sending = Sending {
+        client,
+        builder,
+        send: config.send,
+        confirmation_timeout: config.timeouts.confirmation_timeout,
+    };
+    match args.command {
+        Command::Create { amount } => {
+            create(&sending, &senders, &amount, vote_account.as_ref()).await
+        }
+        Command::Status | Command::Rewards { .. } => unreachable!("handled above"),
+        Command::Delegate => {
+            let vote_account = vote_account
+                .ok_or_else(|| TaskError::ConfigError("vote_account must be set".to_string()))?;
+            for_each_stake(&sending, &senders, "Delegated", |payer, address, _| {
+                stake_instruction::delegate_stake(address, payer, &vote_account)
+            })
+            .await
+        }
+        Command::Deactivate => {
+            for_each_stake(&sending, &senders, "Deactivated", |payer, address, _| {
+                stake_instruction::deactivate_stake(address, payer)
+            })
+            .await
+        }
+        Command::Withdraw { amount, to } => {
+            let amount = amount.as_ref().map(lamports).transpose()?;
+            let to = to
+                .map(|to| parse_pubkey(&to, "withdrawal address"))
+                .transpose()?;
+            for_each_stake(
+                &sending,
+                &senders,
+                "Withdrew from",
+                |payer, address, balance| {
+                    stake_instruction::withdraw(
+                        address,
+                        payer,
+                        &to.unwrap_or(*payer),
+                        amount.unwrap_or(balance),
+                        None,
+                    )
+                },
+            )
+            .await
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    common::exit(run(Args::parse()).await)
+}
@@ -0,0 +1,93 @@
+use common::{TaskError, TaskResult};
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use std::collections::BTreeMap;
+
+/// The inflation reward one account earned in one epoch, a row of the table and of the
+/// CSV export.
+#[derive(Serialize, Debug)]
+struct RewardRow {
+    epoch: u64,
+    account: String,
+    lamports: u64,
+    post_balance: u64,
+    /// Commission of the vote account when the reward was credited
+    commission: Option<u8>,
+    effective_slot: u64,
+}
+
+/// Prints the inflation rewards of `accounts` in the last `epochs` completed epochs, with
+/// the total of each account, and writes them to `csv` when given.
+pub async fn report(
+    client: &RpcClient,
+    accounts: &[Pubkey],
+    epochs: u64,
+    csv: Option<&str>,
+) -> TaskResult<()> {
+    if accounts.is_empty() {
+        return Err(TaskError::ConfigError(
+            "no stake_accounts or vote_account to report rewards for".to_string(),
+        ));
+    }
+    let current = client.get_epoch_info().await?.epoch;
+    let first = current.saturating_sub(epochs);
+
+    let mut rows = Vec::new();
+    for epoch in first..current {
+        let rewards = client.get_inflation_reward(accounts, Some(epoch)).await?;
+        for (account, reward) in accounts.iter().zip(rewards) {
+            let Some(reward) = reward else {
+                continue;
+            };
+            rows.push(RewardRow {
+                epoch,
+                account: account.to_string(),
+                lamports: reward.amount,
+                post_balance: reward.post_balance,
+                commission: reward.commission,
+                effective_slot: reward.effective_slot,
+            });
+        }
+    }
+
+    println!(
+        "{:>6}  {:<44}  {:>16}  {:>16}  {:>10}",
+        "epoch", "account", "reward SOL", "balance SOL", "commission"
+    );
+    let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+    for row in &rows {
+        *totals.entry(&row.account).or_default() += row.lamports;
+        println!(
+            "{:>6}  {:<44}  {:>16}  {:>16}  {:>10}",
+            row.epoch,
+            row.account,
+            lamports_to_sol(row.lamports),
+            lamports_to_sol(row.post_balance),
+            row.commission
+                .map(|commission| format!("{commission}%"))
+                .unwrap_or_default()
+        );
+    }
+    println!(
+        "\nTotals over epochs {first} to {}:",
+        current.saturating_sub(1)
+    );
+    for (account, total) in &totals {
+        println!("  {account:<44}  {} SOL", lamports_to_sol(*total));
+    }
+    println!(
+        "  {:<44}  {} SOL",
+        "all accounts",
+        lamports_to_sol(totals.values().sum())
+    );
+
+    if let Some(path) = csv {
+        let mut writer = csv::Writer::from_path(path).map_err(std::io::Error::from)?;
+        for row in &rows {
+            writer.serialize(row).map_err(std::io::Error::from)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use common::labels::Labels;
+use common::output::{self, OutputFormat};
+use common::TaskResult;
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{
+    EncodedTransaction, UiInstruction, UiMessage, UiParsedInstruction, UiParsedMessage,
+    UiTransactionEncoding, UiTransactionStatusMeta,
+};
+use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
+
+// Most signatures a single getSignaturesForAddress request returns
+const MAX_SIGNATURES_PER_PAGE: usize = 1000;
+
+/// How far back to list and whether to decode every transaction.
+pub struct HistoryOptions {
+    pub limit: usize,
+    /// Signature to start listing from, exclusive
+    pub before: Option<Signature>,
+    pub decode: bool,
+    pub output: OutputFormat,
+}
+
+/// A transaction that touched a wallet, newest first.
+#[derive(Serialize)]
+struct HistoryEntry<'a> {
+    wallet: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    signature: String,
+    slot: u64,
+    block_time: Option<i64>,
+    /// `None` when the transaction succeeded
+    error: Option<String>,
+    memo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<TransactionSummary>,
+}
+
+/// What a transaction did, from its parsed instructions and status meta.
+#[derive(Serialize)]
+struct TransactionSummary {
+    fee: u64,
+    /// Lamports the wallet gained or lost
+    change: i128,
+    /// `program instruction` for every top-level instruction
+    instructions: Vec<String>,
+}
+
+/// A past point of the chain to reconstruct balances at.
+#[derive(Debug, Clone, Copy)]
+pub enum PastPoint {
+    Slot(u64),
+    /// Unix timestamp, compared with the block times of transactions
+    Time(i64),
+}
+
+impl PastPoint {
+    // Whether a transaction landed after this point, taking those without a block time
+    // for later ones
+    fn is_before(self, slot: u64, block_time: Option<i64>) -> bool {
+        match self {
+            PastPoint::Slot(point) => point < slot,
+            PastPoint::Time(point) => block_time.is_none_or(|time| point < time),
+        }
+    }
+}
+
+/// Lists the transactions of every wallet, newest first, paging through
+/// getSignaturesForAddress until `limit` signatures per wallet are listed.
+pub async fn print_history(
+    wallets: &[String],
+    pubkeys: &[Pubkey],
+    labels: &Labels,
+    client: &RpcClient,
+    options: &HistoryOptions,
+) -> TaskResult<()> {
+    let commitment = history_commitment(client);
+    for (wallet, pubkey) in wallets.iter().zip(pubkeys) {
+        let label = labels.get(wallet);
+        if options.output == OutputFormat::Text {
+            println!("wallet: {}", common::labels::labeled(wallet, label));
+        }
+        let mut before = options.before;
+        let mut remaining = options.limit;
+        while remaining > 0 {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                until: None,
+                limit: Some(remaining.min(MAX_SIGNATURES_PER_PAGE)),
+                commitment: Some(commitment),
+            };
+            let page = client
+                .get_signatures_for_address_with_config(pubkey, config)
+                .await?;
+            let last_page = page.len() < remaining.min(MAX_SIGNATURES_PER_PAGE);
+            remaining -= page.len();
+            for status in page {
+                let signature = Signature::from_str(&status.signature).ok();
+                before = signature.or(before);
+                let summary = match (options.decode, signature) {
+                    (true, Some(signature)) => {
+                        Some(summarize(client, &signature, pubkey, commitment).await?)
+                    }
+                    _ => None,
+                };
+                let entry = HistoryEntry {
+                    wallet,
+                    label,
+                    signature: status.signature,
+                    slot: status.slot,
+                    block_time: status.block_time,
+                    error: status.err.map(|e| e.to_string()),
+                    memo: status.memo,
+                    summary,
+                };
+                print_entry(&entry, options.output)?;
+            }
+            if last_page {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reconstructs the balance `wallet` had at `point` from its transaction history: the
+/// balance after the last transaction up to `point`, or before the first one after it.
+/// Nodes that do not keep the full history can only go back as far as they remember.
+pub async fn balance_at(client: &RpcClient, wallet: &Pubkey, point: PastPoint) -> TaskResult<u64> {
+    let commitment = history_commitment(client);
+    let mut before = None;
+    // The oldest transaction seen that landed after the point
+    let mut after = None;
+    loop {
+        let config = GetConfirmedSignaturesForAddress2Config {
+            before,
+            until: None,
+            limit: Some(MAX_SIGNATURES_PER_PAGE),
+            commitment: Some(commitment),
+        };
+        let page = client
+            .get_signatures_for_address_with_config(wallet, config)
+            .await?;
+        let last_page = page.len() < MAX_SIGNATURES_PER_PAGE;
+        // The next page starts after this one, whatever its entries turn out to hold
+        let cursor = page
+            .last()
+            .and_then(|status| Signature::from_str(&status.signature).ok());
+        if cursor.is_none() || cursor == before {
+            break;
+        }
+        before = cursor;
+        for status in page {
+            let Ok(signature) = Signature::from_str(&status.signature) else {
+                continue;
+            };
+            if point.is_before(status.slot, status.block_time) {
+                after = Some(signature);
+                continue;
+            }
+            let (message, meta) = fetch_parsed(client, &signature, commitment).await?;
+            if let Some((_, post)) = wallet_balances(message.as_ref(), meta.as_ref(), wallet) {
+                return Ok(post);
+            }
+        }
+        if last_page {
+            break;
+        }
+    }
+    // Nothing before the point: the wallet held what it had before the first transaction
+    // after it, or what it holds now when it has no history
+    match after {
+        Some(signature) => {
+            let (message, meta) = fetch_parsed(client, &signature, commitment).await?;
+            Ok(wallet_balances(message.as_ref(), meta.as_ref(), wallet).map_or(0, |(pre, _)| pre))
+        }
+        None => Ok(client.get_balance(wallet).await?),
+    }
+}
+
+// Transaction history is only kept for confirmed blocks
+fn history_commitment(client: &RpcClient) -> CommitmentConfig {
+    match client.commitment() {
+        commitment if commitment.is_at_least_confirmed() => commitment,
+        _ => CommitmentConfig::confirmed(),
+    }
+}
+
+// Function for fetching a transaction with its instructions parsed by the node
+async fn fetch_parsed(
+    client: &RpcClient,
+    signature: &Signature,
+    commitment: CommitmentConfig,
+) -> TaskResult<(Option<UiParsedMessage>, Option<UiTransactionStatusMeta>)> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::JsonParsed),
+        commitment: Some(commitment),
+        max_supported_transaction_version: Some(0),
+    };
+    let transaction = client
+        .get_transaction_with_config(signature, config)
+        .await?
+        .transaction;
+    let message = match transaction.transaction {
+        EncodedTransaction::Json(ui_transaction) => match ui_transaction.message {
+            UiMessage::Parsed(message) => Some(message),
+            UiMessage::Raw(_) => None,
+        },
+        _ => None,
+    };
+    Ok((message, transaction.meta))
+}
+
+// The balances of `wallet` before and after a transaction
+fn wallet_balances(
+    message: Option<&UiParsedMessage>,
+    meta: Option<&UiTransactionStatusMeta>,
+    wallet: &Pubkey,
+) -> Option<(u64, u64)> {
+    let wallet = wallet.to_string();
+    let index = message?
+        .account_keys
+        .iter()
+        .position(|account| account.pubkey == wallet)?;
+    let meta = meta?;
+    Some((
+        *meta.pre_balances.get(index)?,
+        *meta.post_balances.get(index)?,
+    ))
+}
+
+async fn summarize(
+    client: &RpcClient,
+    signature: &Signature,
+    wallet: &Pubkey,
+    commitment: CommitmentConfig,
+) -> TaskResult<TransactionSummary> {
+    let (message, meta) = fetch_parsed(client, signature, commitment).await?;
+    let change = wallet_balances(message.as_ref(), meta.as_ref(), wallet)
+        .map_or(0, |(pre, post)| post as i128 - pre as i128);
+    Ok(TransactionSummary {
+        fee: meta.as_ref().map_or(0, |meta| meta.fee),
+        change,
+        instructions: message.map_or_else(Vec::new, |message| {
+            message.instructions.iter().map(describe).collect()
+        }),
+    })
+}
+
+// Names an instruction by its program and, when the node parsed it, its type
+fn describe(instruction: &UiInstruction) -> String {
+    match instruction {
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => {
+            match parsed.parsed.get("type").and_then(|kind| kind.as_str()) {
+                Some(kind) => format!("{} {kind}", parsed.program),
+                None => parsed.program.clone(),
+            }
+        }
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(decoded)) => {
+            decoded.program_id.clone()
+        }
+        UiInstruction::Compiled(compiled) => {
+            format!("program #{}", compiled.program_id_index)
+        }
+    }
+}
+
+fn print_entry(entry: &HistoryEntry, output: OutputFormat) -> TaskResult<()> {
+    if output == OutputFormat::Json {
+        return output::print_json(entry);
+    }
+    let time = entry.block_time.map_or("-".to_string(), |time| {
+        humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(time as u64)).to_string()
+    });
+    let status = entry.error.as_deref().unwrap_or("ok");
+    let mut line = format!(
+        "    {}  slot {}  {time}  {status}",
+        entry.signature, entry.slot
+    );
+    if let Some(memo) = &entry.memo {
+        line.push_str(&format!("  memo {memo}"));
+    }
+    println!("{line}");
+    if let Some(summary) = &entry.summary {
+        println!(
+            "        fee {} lamports, {:+} lamports, {}",
+            summary.fee,
+            summary.change,
+            summary.instructions.join(", ")
+        );
+    }
+    Ok(())
+}
@@ -1,72 +1,1025 @@
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use clap::Parser;
+use common::database::{BalanceRecord, BalanceSnapshot, Database, TokenRecord};
+use common::labels::Labels;
+use common::output::{self, OutputFormat};
+use common::rate_limit::RateLimitConfig;
+use common::rpc::{Network, RetryConfig, RpcEndpoints, RpcTimeouts};
+use common::telemetry::{LogArgs, TelemetryConfig};
+use common::{TaskError, TaskResult};
+use futures::future::try_join_all;
+use futures::stream::{select_all, StreamExt};
+use futures::TryFutureExt;
 use serde::{Deserialize, Serialize};
+use solana_account_decoder::parse_token::TokenAccountType;
+use solana_account_decoder::parse_token_extension::UiExtension;
+use solana_account_decoder::{UiAccountData, UiAccountEncoding, UiDataSliceConfig};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
 use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_client::rpc_request::TokenAccountsFilter;
 use solana_program::pubkey::Pubkey;
-use solana_sdk::commitment_config::CommitmentConfig;
-use std::fs;
-use std::pin::Pin;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::signature::Signature;
+use stake::StakeBalance;
+use std::collections::HashMap;
+use std::process::ExitCode;
 use std::str::FromStr;
-use std::{error::Error, future::Future};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+use tracing::{error, warn};
 
 const LAPORTS_PER_SOL: f64 = 1_000_000_000.;
+// Most addresses a single getMultipleAccounts request accepts
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+// Slot time assumed when the node has no performance samples
+const DEFAULT_SLOT_TIME: Duration = Duration::from_millis(400);
+// Delay before reconnecting to the pubsub endpoint
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(2);
+// ANSI escapes used by watch mode and --diff
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[H";
+const HIGHLIGHT: &str = "\x1B[1;33m";
+const RESET: &str = "\x1B[0m";
+
+mod history;
+mod pyth;
+mod stake;
+
+#[derive(Parser, Debug)]
+#[command(about = "Prints SOL balances of the wallets listed in the config file")]
+struct Args {
+    /// Path to the config file, YAML, TOML or JSON by its extension
+    #[arg(long, default_value = "config.yaml")]
+    config: String,
+    /// Profile from the `profiles` of the config to lay over its top-level settings
+    #[arg(long)]
+    profile: Option<String>,
+    /// RPC endpoint to use instead of `rpc_url` and `rpc_urls` from the config
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// Public cluster to use instead of the endpoints from the config, `--rpc-url` taking
+    /// precedence
+    #[arg(long, value_enum)]
+    network: Option<Network>,
+    /// Commitment level of balance queries, instead of `commitment` from the config or
+    /// `processed`
+    #[arg(long)]
+    commitment: Option<CommitmentConfig>,
+    /// Read newline-separated wallet addresses from this file instead of `wallets` from the
+    /// config, `-` for stdin
+    #[arg(long)]
+    wallets: Option<String>,
+    /// Also list the non-empty SPL token accounts of every wallet
+    #[arg(long)]
+    token_balances: bool,
+    /// List every token account of every wallet, empty ones included, with the decimals
+    /// and delegate of each, to audit wallet contents
+    #[arg(long)]
+    tokens: bool,
+    /// Start with the current epoch and slot, and the estimated time left until the next
+    /// epoch
+    #[arg(long)]
+    epoch_info: bool,
+    /// Also report the active and inactive stake of the stake accounts each wallet is the
+    /// withdraw authority of
+    #[arg(long)]
+    stake: bool,
+    /// Also value the balances in USD at the SOL/USD price of the Pyth price account
+    /// `usd_price_account`
+    #[arg(long)]
+    usd: bool,
+    /// List the recent transactions of every wallet instead of their balances
+    #[arg(long, conflicts_with_all = ["watch", "subscribe"])]
+    history: bool,
+    /// Transactions listed per wallet by `--history`
+    #[arg(long, default_value_t = 10, requires = "history")]
+    limit: usize,
+    /// List only the transactions older than this signature with `--history`
+    #[arg(long, requires = "history")]
+    before: Option<Signature>,
+    /// Fetch every transaction listed by `--history` to summarize its fee, balance change
+    /// and instructions
+    #[arg(long, requires = "history")]
+    decode: bool,
+    /// Compare the balances with the last snapshot in `database_path` and list the wallets
+    /// that gained or lost since
+    #[arg(long, conflicts_with_all = ["watch", "history"])]
+    diff: bool,
+    /// Reconstruct the SOL balances at this past slot from the transaction history of the
+    /// wallets, instead of reading the current ones
+    #[arg(long, conflicts_with_all = ["at_time", "watch", "subscribe", "history", "diff"])]
+    at_slot: Option<u64>,
+    /// Reconstruct the SOL balances at this past time, e.g. `2025-01-31T00:00:00Z`, from the
+    /// transaction history of the wallets
+    #[arg(long, value_parser = humantime::parse_rfc3339_weak, conflicts_with_all = ["watch", "subscribe", "history", "diff"])]
+    at_time: Option<SystemTime>,
+    /// Read every balance at processed, confirmed and finalized commitment at once and
+    /// highlight the wallets where they disagree, to spot forks or lagging RPC nodes
+    #[arg(long, conflicts_with_all = ["watch", "subscribe", "history", "diff", "at_slot", "at_time"])]
+    compare_commitments: bool,
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    output: OutputFormat,
+    /// Keep polling balances and highlight the wallets that changed
+    #[arg(long)]
+    watch: bool,
+    /// Delay between two polls in watch mode, e.g. `10s` or `1m`
+    #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+    interval: Duration,
+    /// Follow balance changes live through WebSocket account subscriptions
+    #[arg(long, conflicts_with = "watch")]
+    subscribe: bool,
+    #[command(flatten)]
+    log: LogArgs,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 struct YamlFile {
-    rpc_url: String,
-    wallets: Vec<String>,
+    #[serde(flatten)]
+    rpc: RpcEndpoints,
+    /// Commitment level of queries, confirmations and subscriptions
+    #[serde(default)]
+    commitment: Option<CommitmentLevel>,
+    #[serde(default)]
+    wallets: Vec<WalletEntry>,
+    /// Labels of addresses, by address
+    #[serde(default)]
+    labels: Labels,
+    #[serde(default)]
+    retry: RetryConfig,
+    #[serde(flatten)]
+    timeouts: RpcTimeouts,
+    /// Client-side limit on RPC requests per second
+    #[serde(default)]
+    rate_limit: Option<RateLimitConfig>,
+    /// Pubsub endpoint for `--subscribe`, derived from the RPC URL when omitted
+    #[serde(default)]
+    ws_url: Option<String>,
+    /// SQLite database every fetched balance snapshot is added to
+    #[serde(default)]
+    database_path: Option<String>,
+    /// Pyth SOL/USD price account read by `--usd`, the mainnet-beta feed by default
+    #[serde(default)]
+    usd_price_account: Option<String>,
 }
 
+/// A wallet given either as a bare address or with a label shown next to it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum WalletEntry {
+    Address(String),
+    Labeled {
+        address: String,
+        #[serde(default)]
+        label: Option<String>,
+    },
+}
+
+impl WalletEntry {
+    fn address(&self) -> &str {
+        match self {
+            Self::Address(address) | Self::Labeled { address, .. } => address,
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        match self {
+            Self::Address(_) => None,
+            Self::Labeled { label, .. } => label.as_deref(),
+        }
+    }
+}
+
+#[derive(Serialize)]
 struct WalletBalance {
     address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    balance: u64,
+    /// Value of the balance in USD with `--usd`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usd: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stake: Option<StakeBalance>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tokens: Vec<TokenBalance>,
+}
+
+#[derive(Serialize)]
+struct TokenBalance {
+    token_account: String,
+    mint: String,
+    ui_amount: String,
+    /// Set with `--tokens`, like the delegate fields
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decimals: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delegate: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delegated_amount: Option<String>,
+    /// Transfer fees a Token-2022 account holds back for the mint, in base units
+    #[serde(skip_serializing_if = "Option::is_none")]
+    withheld_fee: Option<u64>,
+}
+
+/// A balance change reported by an account subscription.
+#[derive(Serialize)]
+struct BalanceChange<'a> {
+    address: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    balance: u64,
+    change: i128,
+    slot: u64,
+}
+
+/// Where the cluster stands in the current epoch, printed as `{"epoch": ...}`.
+#[derive(Serialize)]
+struct EpochContext {
+    epoch: u64,
+    slot: u64,
+    slot_index: u64,
+    slots_in_epoch: u64,
+    slots_remaining: u64,
+    /// Estimated from the recent slot times of the node
+    seconds_to_next_epoch: u64,
+}
+
+/// How the balance of a wallet moved since its last snapshot, printed as
+/// `{"delta": ...}`.
+#[derive(Serialize)]
+struct BalanceDelta<'a> {
+    address: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    /// `None` for wallets without an earlier snapshot
+    previous: Option<u64>,
     balance: u64,
+    change: i128,
+    since: Option<&'a str>,
+}
+
+/// The balance of a wallet at each commitment level, printed as `{"commitments": ...}`.
+#[derive(Serialize)]
+struct CommitmentBalances<'a> {
+    address: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<&'a str>,
+    processed: u64,
+    confirmed: u64,
+    finalized: u64,
+    /// Whether all three levels agree
+    consistent: bool,
+}
+
+/// The slot the node is at for each commitment level, printed as `{"slots": ...}`.
+#[derive(Serialize)]
+struct CommitmentSlots {
+    processed: u64,
+    confirmed: u64,
+    finalized: u64,
+}
+
+#[derive(Serialize, Default)]
+struct BalanceSummary {
+    wallets: usize,
+    total_balance: u64,
+    /// SOL/USD price the balances are valued at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sol_usd_price: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_usd: Option<f64>,
 }
 
 fn lamport_to_sol(lamports: u64) -> f64 {
-    let sol = lamports as f64 / LAPORTS_PER_SOL;
-    return sol;
+    lamports as f64 / LAPORTS_PER_SOL
 }
 
-// Function for fetching current balance for a given Solana wallet address
-async fn get_balance(
-    wallet_address: &str,
+// Function for fetching the SPL Token and Token-2022 accounts owned by a wallet, the empty
+// ones and the details of each only in audit mode
+async fn get_token_balances(
+    owner: &Pubkey,
     rpc_client: &RpcClient,
-) -> Result<WalletBalance, Box<dyn Error>> {
-    let commitment_config = CommitmentConfig::processed();
-    let balance = rpc_client
-        .get_balance_with_commitment(&Pubkey::from_str(wallet_address)?, commitment_config)
-        .await?;
+    commitment_config: CommitmentConfig,
+    audit: bool,
+) -> TaskResult<Vec<TokenBalance>> {
+    let accounts = try_join_all([spl_token::id(), spl_token_2022::id()].map(|program_id| {
+        rpc_client.get_token_accounts_by_owner_with_commitment(
+            owner,
+            TokenAccountsFilter::ProgramId(program_id),
+            commitment_config,
+        )
+    }))
+    .await?;
+
+    let mut tokens = Vec::new();
+    for keyed_account in accounts.into_iter().flat_map(|accounts| accounts.value) {
+        // The RPC returns jsonParsed data for token accounts, anything else is skipped
+        let UiAccountData::Json(parsed_account) = keyed_account.account.data else {
+            continue;
+        };
+        let Ok(TokenAccountType::Account(token_account)) =
+            serde_json::from_value::<TokenAccountType>(parsed_account.parsed)
+        else {
+            continue;
+        };
+        if token_account.token_amount.amount == "0" && !audit {
+            continue;
+        }
+        let mut token = TokenBalance {
+            token_account: keyed_account.pubkey,
+            mint: token_account.mint,
+            ui_amount: token_account.token_amount.ui_amount_string,
+            decimals: None,
+            delegate: None,
+            delegated_amount: None,
+            withheld_fee: None,
+        };
+        if audit {
+            token.decimals = Some(token_account.token_amount.decimals);
+            token.delegate = token_account.delegate;
+            token.delegated_amount = token_account
+                .delegated_amount
+                .map(|amount| amount.ui_amount_string);
+            token.withheld_fee =
+                token_account
+                    .extensions
+                    .iter()
+                    .find_map(|extension| match extension {
+                        UiExtension::TransferFeeAmount(fee) => Some(fee.withheld_amount),
+                        _ => None,
+                    });
+        }
+        tokens.push(token);
+    }
 
-    Ok(WalletBalance {
-        address: wallet_address.to_string(),
-        balance: balance.value,
+    Ok(tokens)
+}
+
+// Function for fetching the position in the current epoch, with the time left until the
+// next one estimated from the recent performance samples of the node
+async fn get_epoch_context(rpc_client: &RpcClient) -> TaskResult<EpochContext> {
+    let info = rpc_client.get_epoch_info().await?;
+    let samples = rpc_client.get_recent_performance_samples(Some(10)).await?;
+    let (slots, seconds) = samples
+        .iter()
+        .fold((0u64, 0u64), |(slots, seconds), sample| {
+            (
+                slots + sample.num_slots,
+                seconds + u64::from(sample.sample_period_secs),
+            )
+        });
+    let slot_time = match slots {
+        0 => DEFAULT_SLOT_TIME,
+        _ => Duration::from_secs(seconds) / slots as u32,
+    };
+    let slots_remaining = info.slots_in_epoch.saturating_sub(info.slot_index);
+    Ok(EpochContext {
+        epoch: info.epoch,
+        slot: info.absolute_slot,
+        slot_index: info.slot_index,
+        slots_in_epoch: info.slots_in_epoch,
+        slots_remaining,
+        seconds_to_next_epoch: (slot_time * slots_remaining as u32).as_secs(),
     })
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let config = fs::read_to_string("config.yaml").expect("The config YAML file is missing");
-    let config_yaml: YamlFile =
-        serde_yaml::from_str::<YamlFile>(&config).expect("Incorrect YAML format");
-    let rpc_url = config_yaml.rpc_url;
-    let rpc_client = RpcClient::new(rpc_url.to_string());
-    let wallets: Vec<String> = config_yaml.wallets;
-
-    let mut tasks: FuturesUnordered<_> = FuturesUnordered::<
-        Pin<Box<dyn Future<Output = Result<WalletBalance, Box<dyn Error>>>>>,
-    >::new();
-    for wallet in &wallets {
-        tasks.push(Box::pin(get_balance(wallet, &rpc_client)));
-    }
-
-    while let Some(result) = tasks.next().await {
-        let result = result?;
+// Prints the epoch context ahead of the balances
+fn print_epoch_context(context: &EpochContext, output: OutputFormat) -> TaskResult<()> {
+    if output == OutputFormat::Json {
+        #[derive(Serialize)]
+        struct EpochLine<'a> {
+            epoch: &'a EpochContext,
+        }
+        return output::print_json(&EpochLine { epoch: context });
+    }
+    println!(
+        "epoch {}, slot {} ({} of {}), {} slots left, next epoch in about {}",
+        context.epoch,
+        context.slot,
+        context.slot_index,
+        context.slots_in_epoch,
+        context.slots_remaining,
+        humantime::format_duration(Duration::from_secs(context.seconds_to_next_epoch / 60 * 60))
+    );
+    Ok(())
+}
+
+// Function for reading wallet addresses, one per line, from a file or from stdin for `-`.
+// Blank lines and lines starting with `#` are skipped
+fn read_wallets(path: &str) -> TaskResult<Vec<String>> {
+    let content = if path == "-" {
+        io::read_to_string(io::stdin())
+    } else {
+        fs::read_to_string(path)
+    }
+    .map_err(|e| TaskError::ConfigError(format!("cannot read wallets from {path}: {e}")))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+// Function for fetching the SOL balances of many wallets with one getMultipleAccounts
+// request per chunk of addresses. Wallets without an account have a zero balance.
+async fn get_balances(
+    pubkeys: &[Pubkey],
+    rpc_client: &RpcClient,
+    commitment_config: CommitmentConfig,
+) -> TaskResult<Vec<u64>> {
+    // Only lamports are needed, so no account data is transferred
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        data_slice: Some(UiDataSliceConfig {
+            offset: 0,
+            length: 0,
+        }),
+        commitment: Some(commitment_config),
+        min_context_slot: None,
+    };
+    let chunks = try_join_all(
+        pubkeys
+            .chunks(MAX_MULTIPLE_ACCOUNTS)
+            .map(|chunk| rpc_client.get_multiple_accounts_with_config(chunk, config.clone())),
+    )
+    .await?;
+
+    Ok(chunks
+        .into_iter()
+        .flat_map(|chunk| chunk.value)
+        .map(|account| account.map_or(0, |account| account.lamports))
+        .collect())
+}
+
+// Function for reading every balance at the three commitment levels concurrently and
+// printing them side by side, highlighting the wallets where the levels disagree
+async fn compare_commitments(
+    wallets: &[String],
+    pubkeys: &[Pubkey],
+    labels: &Labels,
+    rpc_client: &RpcClient,
+    output: OutputFormat,
+) -> TaskResult<()> {
+    #[derive(Serialize)]
+    struct SlotsLine<'a> {
+        slots: &'a CommitmentSlots,
+    }
+    #[derive(Serialize)]
+    struct CommitmentsLine<'a> {
+        commitments: &'a CommitmentBalances<'a>,
+    }
+
+    let levels = [
+        CommitmentConfig::processed(),
+        CommitmentConfig::confirmed(),
+        CommitmentConfig::finalized(),
+    ];
+    let (balances, slots) = futures::try_join!(
+        try_join_all(
+            levels
+                .iter()
+                .map(|level| get_balances(pubkeys, rpc_client, *level))
+        ),
+        try_join_all(
+            levels
+                .iter()
+                .map(|level| rpc_client.get_slot_with_commitment(*level))
+        )
+        .err_into(),
+    )?;
+    let slots = CommitmentSlots {
+        processed: slots[0],
+        confirmed: slots[1],
+        finalized: slots[2],
+    };
+    if output == OutputFormat::Json {
+        output::print_json(&SlotsLine { slots: &slots })?;
+    } else {
+        println!(
+            "slots: processed {}, confirmed {} ({} behind), finalized {} ({} behind)",
+            slots.processed,
+            slots.confirmed,
+            slots.processed.saturating_sub(slots.confirmed),
+            slots.finalized,
+            slots.processed.saturating_sub(slots.finalized)
+        );
+    }
+
+    let mut discrepancies = 0;
+    for (index, address) in wallets.iter().enumerate() {
+        let (processed, confirmed, finalized) =
+            (balances[0][index], balances[1][index], balances[2][index]);
+        let commitments = CommitmentBalances {
+            address,
+            label: labels.get(address),
+            processed,
+            confirmed,
+            finalized,
+            consistent: processed == confirmed && confirmed == finalized,
+        };
+        if !commitments.consistent {
+            discrepancies += 1;
+        }
+        if output == OutputFormat::Json {
+            output::print_json(&CommitmentsLine {
+                commitments: &commitments,
+            })?;
+            continue;
+        }
+        let line = format!(
+            "wallet: {}, processed {} SOL, confirmed {} SOL, finalized {} SOL",
+            common::labels::labeled(address, commitments.label),
+            lamport_to_sol(processed),
+            lamport_to_sol(confirmed),
+            lamport_to_sol(finalized)
+        );
+        match commitments.consistent {
+            true => println!("{line}"),
+            false => println!("{HIGHLIGHT}{line}{RESET}"),
+        }
+    }
+    if output == OutputFormat::Text {
         println!(
+            "{discrepancies} of {} wallets differ between commitment levels",
+            wallets.len()
+        );
+    }
+    Ok(())
+}
+
+// Function for fetching the balances of all wallets, with their token accounts if asked
+async fn fetch_balances(
+    wallets: &[String],
+    pubkeys: &[Pubkey],
+    labels: &Labels,
+    price_account: Option<&Pubkey>,
+    rpc_client: &RpcClient,
+    args: &Args,
+) -> TaskResult<(Vec<WalletBalance>, Option<f64>)> {
+    let balances = get_balances(pubkeys, rpc_client, rpc_client.commitment()).await?;
+    let price = match price_account {
+        Some(account) => Some(pyth::fetch_price(rpc_client, account).await?.price),
+        None => None,
+    };
+    let mut results: Vec<WalletBalance> = wallets
+        .iter()
+        .zip(balances)
+        .map(|(address, balance)| WalletBalance {
+            address: address.to_string(),
+            label: labels.get(address).map(str::to_string),
+            balance,
+            usd: price.map(|price| lamport_to_sol(balance) * price),
+            stake: None,
+            tokens: Vec::new(),
+        })
+        .collect();
+    if args.token_balances || args.tokens {
+        let tokens = try_join_all(pubkeys.iter().map(|pubkey| {
+            get_token_balances(pubkey, rpc_client, rpc_client.commitment(), args.tokens)
+        }))
+        .await?;
+        for (result, tokens) in results.iter_mut().zip(tokens) {
+            result.tokens = tokens;
+        }
+    }
+    if args.stake {
+        let context = common::stake::StakeContext::fetch(rpc_client).await?;
+        let stakes = try_join_all(
+            pubkeys
+                .iter()
+                .map(|pubkey| stake::fetch_stake(pubkey, rpc_client, &context)),
+        )
+        .await?;
+        for (result, stake) in results.iter_mut().zip(stakes) {
+            result.stake = Some(stake);
+        }
+    }
+
+    Ok((results, price))
+}
+
+// Function for adding a snapshot of the balances to the database
+fn save_balances(database: &mut Database, results: &[WalletBalance]) -> TaskResult<()> {
+    let records: Vec<BalanceRecord> = results
+        .iter()
+        .map(|result| BalanceRecord {
+            address: &result.address,
+            lamports: result.balance,
+            tokens: result
+                .tokens
+                .iter()
+                .map(|token| TokenRecord {
+                    token_account: &token.token_account,
+                    mint: &token.mint,
+                    ui_amount: &token.ui_amount,
+                })
+                .collect(),
+        })
+        .collect();
+    database.insert_balances(&records)
+}
+
+// Prints which wallets gained or lost since their last snapshot, and the net change
+fn print_deltas(
+    results: &[WalletBalance],
+    snapshots: &HashMap<String, BalanceSnapshot>,
+    output: OutputFormat,
+) -> TaskResult<()> {
+    #[derive(Serialize)]
+    struct DeltaLine<'a> {
+        delta: &'a BalanceDelta<'a>,
+    }
+
+    let deltas: Vec<BalanceDelta> = results
+        .iter()
+        .map(|result| {
+            let snapshot = snapshots.get(&result.address);
+            let previous = snapshot.map(|snapshot| snapshot.lamports);
+            BalanceDelta {
+                address: &result.address,
+                label: result.label.as_deref(),
+                previous,
+                balance: result.balance,
+                change: result.balance as i128 - previous.unwrap_or_default() as i128,
+                since: snapshot.map(|snapshot| snapshot.recorded_at.as_str()),
+            }
+        })
+        .filter(|delta| delta.change != 0 || delta.previous.is_none())
+        .collect();
+    if output == OutputFormat::Json {
+        for delta in &deltas {
+            output::print_json(&DeltaLine { delta })?;
+        }
+        return Ok(());
+    }
+
+    println!("\nSince the last snapshot:");
+    for delta in &deltas {
+        let wallet = common::labels::labeled(delta.address, delta.label);
+        match (delta.previous, delta.since) {
+            (Some(previous), Some(since)) => println!(
+                "  {} {wallet}: {:+} SOL ({} -> {} SOL, since {since})",
+                if delta.change > 0 { "gained" } else { "lost" },
+                delta.change as f64 / LAPORTS_PER_SOL,
+                lamport_to_sol(previous),
+                lamport_to_sol(delta.balance)
+            ),
+            _ => println!(
+                "  new {wallet}: {} SOL, no earlier snapshot",
+                lamport_to_sol(delta.balance)
+            ),
+        }
+    }
+    let net: i128 = deltas
+        .iter()
+        .filter(|delta| delta.previous.is_some())
+        .map(|delta| delta.change)
+        .sum();
+    println!(
+        "  {} wallets unchanged, net change {:+} SOL",
+        results.len() - deltas.len(),
+        net as f64 / LAPORTS_PER_SOL
+    );
+    Ok(())
+}
+
+// Prints the balances, marking the ones that differ from `previous`, the last poll in watch
+// mode or the last snapshot with --diff
+fn print_balances(
+    results: &[WalletBalance],
+    price: Option<f64>,
+    previous: &HashMap<String, u64>,
+    output: OutputFormat,
+) -> TaskResult<()> {
+    let mut summary = BalanceSummary {
+        sol_usd_price: price,
+        total_usd: price.map(|_| 0.),
+        ..BalanceSummary::default()
+    };
+    for result in results {
+        summary.wallets += 1;
+        summary.total_balance += result.balance;
+        if let (Some(total), Some(usd)) = (&mut summary.total_usd, result.usd) {
+            *total += usd;
+        }
+        if output == OutputFormat::Json {
+            output::print_json(result)?;
+            continue;
+        }
+        let mut line = format!(
             "wallet: {}, balance {} SOL",
-            result.address,
+            common::labels::labeled(&result.address, result.label.as_deref()),
             lamport_to_sol(result.balance)
         );
+        if let Some(usd) = result.usd {
+            line.push_str(&format!(" (${usd:.2})"));
+        }
+        if let Some(stake) = result.stake.filter(|stake| stake.accounts > 0) {
+            line.push_str(&format!(
+                ", stake {} SOL active and {} SOL inactive in {} accounts",
+                lamport_to_sol(stake.active),
+                lamport_to_sol(stake.inactive),
+                stake.accounts
+            ));
+        }
+        match previous.get(&result.address) {
+            Some(&before) if before != result.balance => {
+                let change = result.balance as i128 - before as i128;
+                println!("{HIGHLIGHT}{line} ({change:+} lamports){RESET}");
+            }
+            _ => println!("{line}"),
+        }
+        for token in &result.tokens {
+            let mut line = format!(
+                "    token account: {}, mint: {}, balance {}",
+                token.token_account, token.mint, token.ui_amount
+            );
+            if let Some(decimals) = token.decimals {
+                line.push_str(&format!(", decimals {decimals}"));
+            }
+            if let Some(delegate) = &token.delegate {
+                let amount = token.delegated_amount.as_deref().unwrap_or("0");
+                line.push_str(&format!(", delegate {delegate} for {amount}"));
+            }
+            if let Some(withheld) = token.withheld_fee.filter(|withheld| *withheld > 0) {
+                line.push_str(&format!(", {withheld} withheld as transfer fees"));
+            }
+            println!("{line}");
+        }
+    }
+
+    if output == OutputFormat::Json {
+        output::print_summary(&summary)?;
+    } else if let (Some(price), Some(total_usd)) = (summary.sol_usd_price, summary.total_usd) {
+        println!(
+            "total: {} SOL (${total_usd:.2} at ${price:.2}/SOL)",
+            lamport_to_sol(summary.total_balance)
+        );
     }
 
     Ok(())
 }
+
+// Function for following the balances of all wallets through accountSubscribe. The
+// subscriptions are set up again whenever the WebSocket connection drops.
+async fn subscribe_balances(
+    ws_url: &str,
+    results: Vec<WalletBalance>,
+    pubkeys: &[Pubkey],
+    labels: &Labels,
+    commitment_config: CommitmentConfig,
+    output: OutputFormat,
+) -> TaskResult<()> {
+    let mut balances: HashMap<String, u64> = results
+        .into_iter()
+        .map(|result| (result.address, result.balance))
+        .collect();
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(commitment_config),
+        ..RpcAccountInfoConfig::default()
+    };
+
+    loop {
+        let pubsub = match PubsubClient::new(ws_url).await {
+            Ok(pubsub) => pubsub,
+            Err(e) => {
+                error!("cannot connect to {ws_url}: {e}");
+                tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+                continue;
+            }
+        };
+        let mut streams = Vec::with_capacity(pubkeys.len());
+        for pubkey in pubkeys {
+            match pubsub.account_subscribe(pubkey, Some(config.clone())).await {
+                Ok((stream, _unsubscribe)) => {
+                    let address = pubkey.to_string();
+                    streams.push(stream.map(move |update| (address.clone(), update)));
+                }
+                Err(e) => error!("cannot subscribe to {pubkey}: {e}"),
+            }
+        }
+
+        let mut updates = select_all(streams);
+        while let Some((address, update)) = updates.next().await {
+            let balance = update.value.lamports;
+            let before = balances
+                .insert(address.clone(), balance)
+                .unwrap_or_default();
+            if before == balance {
+                continue;
+            }
+            let change = BalanceChange {
+                address: &address,
+                label: labels.get(&address),
+                balance,
+                change: balance as i128 - before as i128,
+                slot: update.context.slot,
+            };
+            if output == OutputFormat::Json {
+                output::print_json(&change)?;
+                continue;
+            }
+            println!(
+                "slot {}: wallet: {}, balance {} SOL ({:+} lamports)",
+                change.slot,
+                common::labels::labeled(change.address, change.label),
+                lamport_to_sol(change.balance),
+                change.change
+            );
+        }
+
+        warn!("subscriptions to {ws_url} ended, resubscribing");
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+async fn run(args: Args) -> TaskResult<()> {
+    let config_yaml: YamlFile = common::config::load(&args.config, args.profile.as_deref())?;
+    let _telemetry = common::telemetry::init(&args.log, &TelemetryConfig::default(), "task1")?;
+    let rpc_urls = config_yaml.rpc.urls(args.rpc_url.clone(), args.network)?;
+    let ws_url = config_yaml
+        .ws_url
+        .unwrap_or_else(|| common::rpc::websocket_url(&rpc_urls[0]));
+    let commitment = common::config::commitment(
+        args.commitment,
+        config_yaml.commitment,
+        CommitmentLevel::Processed,
+    );
+    let rpc_client = common::rpc::new_client(
+        rpc_urls,
+        config_yaml.rpc.fan_out,
+        commitment,
+        &config_yaml.retry,
+        config_yaml.rate_limit.as_ref(),
+        &config_yaml.timeouts,
+        None,
+    )?;
+    let mut labels = config_yaml.labels;
+    let wallets: Vec<String> = match &args.wallets {
+        Some(path) => read_wallets(path)?,
+        None => config_yaml
+            .wallets
+            .iter()
+            .map(|entry| {
+                labels.insert(entry.address(), entry.label());
+                entry.address().to_string()
+            })
+            .collect(),
+    };
+    if wallets.is_empty() {
+        return Err(TaskError::ConfigError(
+            "no wallets, list them in the config or pass --wallets".to_string(),
+        ));
+    }
+    let pubkeys = wallets
+        .iter()
+        .map(|wallet| {
+            Pubkey::from_str(wallet)
+                .map_err(|e| TaskError::KeyError(format!("wallet {wallet}: {e}")))
+        })
+        .collect::<TaskResult<Vec<_>>>()?;
+    if args.history {
+        let options = history::HistoryOptions {
+            limit: args.limit,
+            before: args.before,
+            decode: args.decode,
+            output: args.output,
+        };
+        return history::print_history(&wallets, &pubkeys, &labels, &rpc_client, &options).await;
+    }
+    if args.compare_commitments {
+        return compare_commitments(&wallets, &pubkeys, &labels, &rpc_client, args.output).await;
+    }
+    let point = match (args.at_slot, args.at_time) {
+        (Some(slot), _) => Some(history::PastPoint::Slot(slot)),
+        (None, Some(time)) => {
+            let time = time
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs());
+            Some(history::PastPoint::Time(time as i64))
+        }
+        (None, None) => None,
+    };
+    if let Some(point) = point {
+        let balances = try_join_all(
+            pubkeys
+                .iter()
+                .map(|pubkey| history::balance_at(&rpc_client, pubkey, point)),
+        )
+        .await?;
+        let results: Vec<WalletBalance> = wallets
+            .iter()
+            .zip(balances)
+            .map(|(address, balance)| WalletBalance {
+                address: address.to_string(),
+                label: labels.get(address).map(str::to_string),
+                balance,
+                usd: None,
+                stake: None,
+                tokens: Vec::new(),
+            })
+            .collect();
+        return print_balances(&results, None, &HashMap::new(), args.output);
+    }
+    let price_account =
+        match (args.usd, &config_yaml.usd_price_account) {
+            (false, _) => None,
+            (true, account) => {
+                let account = account.as_deref().unwrap_or(pyth::SOL_USD_PRICE_ACCOUNT);
+                Some(Pubkey::from_str(account).map_err(|e| {
+                    TaskError::ConfigError(format!("usd_price_account {account}: {e}"))
+                })?)
+            }
+        };
+    let mut database = config_yaml
+        .database_path
+        .as_deref()
+        .map(Database::open)
+        .transpose()?;
+
+    if !args.watch {
+        let snapshots = match (&database, args.diff) {
+            (Some(database), true) => database.latest_balances()?,
+            (None, true) => {
+                return Err(TaskError::ConfigError(
+                    "--diff compares with the snapshots in database_path, which is not set"
+                        .to_string(),
+                ))
+            }
+            (_, false) => HashMap::new(),
+        };
+        let (results, price) = fetch_balances(
+            &wallets,
+            &pubkeys,
+            &labels,
+            price_account.as_ref(),
+            &rpc_client,
+            &args,
+        )
+        .await?;
+        if let Some(database) = &mut database {
+            save_balances(database, &results)?;
+        }
+        if args.epoch_info {
+            print_epoch_context(&get_epoch_context(&rpc_client).await?, args.output)?;
+        }
+        let previous = snapshots
+            .iter()
+            .map(|(address, snapshot)| (address.clone(), snapshot.lamports))
+            .collect();
+        print_balances(&results, price, &previous, args.output)?;
+        if args.diff {
+            print_deltas(&results, &snapshots, args.output)?;
+        }
+        if args.subscribe {
+            subscribe_balances(&ws_url, results, &pubkeys, &labels, commitment, args.output)
+                .await?;
+        }
+        return Ok(());
+    }
+
+    // In watch mode a failed poll is reported and retried on the next tick
+    let mut previous = HashMap::new();
+    loop {
+        let balances = fetch_balances(
+            &wallets,
+            &pubkeys,
+            &labels,
+            price_account.as_ref(),
+            &rpc_client,
+            &args,
+        );
+        match balances.await {
+            Ok((results, price)) => {
+                if let Some(database) = &mut database {
+                    if let Err(e) = save_balances(database, &results) {
+                        error!("{e}");
+                    }
+                }
+                if args.output == OutputFormat::Text {
+                    print!("{CLEAR_SCREEN}");
+                }
+                if args.epoch_info {
+                    match get_epoch_context(&rpc_client).await {
+                        Ok(context) => print_epoch_context(&context, args.output)?,
+                        Err(e) => error!("{e}"),
+                    }
+                }
+                print_balances(&results, price, &previous, args.output)?;
+                previous = results
+                    .into_iter()
+                    .map(|result| (result.address, result.balance))
+                    .collect();
+            }
+            Err(e) => error!("{e}"),
+        }
+        tokio::time::sleep(args.interval).await;
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    common::exit(run(Args::parse()).await)
+}
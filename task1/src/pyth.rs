@@ -0,0 +1,111 @@
+use common::{TaskError, TaskResult};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::hash;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// SOL/USD price feed account of the Pyth push oracle on mainnet-beta
+pub const SOL_USD_PRICE_ACCOUNT: &str = "7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE";
+// Prices published longer ago than this are reported as stale
+const MAX_PRICE_AGE_SECS: i64 = 60;
+// First bytes of the price accounts of the legacy Pyth oracle program
+const LEGACY_MAGIC: u32 = 0xa1b2_c3d4;
+// Status of a legacy aggregate price that can be used
+const LEGACY_TRADING: u32 = 1;
+
+/// A price read from a Pyth price account.
+#[derive(Debug, Clone, Copy)]
+pub struct Price {
+    pub price: f64,
+    /// Unix timestamp the price was published at
+    pub publish_time: i64,
+}
+
+/// Reads the price of a Pyth price account, either a `PriceUpdateV2` account of the push
+/// oracle or a price account of the legacy oracle program.
+pub async fn fetch_price(client: &RpcClient, account: &Pubkey) -> TaskResult<Price> {
+    let data = client.get_account_data(account).await?;
+    let price = parse(&data)
+        .map_err(|e| TaskError::ConfigError(format!("price account {account}: {e}")))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64);
+    if now - price.publish_time > MAX_PRICE_AGE_SECS {
+        warn!(
+            %account,
+            age_secs = now - price.publish_time,
+            "the Pyth price is stale"
+        );
+    }
+    Ok(price)
+}
+
+fn parse(data: &[u8]) -> Result<Price, String> {
+    if read_u32(data, 0) == Some(LEGACY_MAGIC) {
+        parse_legacy(data)
+    } else {
+        parse_price_update(data)
+    }
+}
+
+// Function for reading the aggregate price of a legacy price account
+fn parse_legacy(data: &[u8]) -> Result<Price, String> {
+    let truncated = || "truncated legacy price account".to_string();
+    let exponent = read_i32(data, 20).ok_or_else(truncated)?;
+    let publish_time = read_i64(data, 96).ok_or_else(truncated)?;
+    let price = read_i64(data, 208).ok_or_else(truncated)?;
+    let status = read_u32(data, 224).ok_or_else(truncated)?;
+    if status != LEGACY_TRADING {
+        return Err("the price is not trading".to_string());
+    }
+    Ok(Price {
+        price: scale(price, exponent),
+        publish_time,
+    })
+}
+
+// Function for reading the price message of a PriceUpdateV2 Anchor account
+fn parse_price_update(data: &[u8]) -> Result<Price, String> {
+    let discriminator = &hash::hash(b"account:PriceUpdateV2").to_bytes()[..8];
+    if data.get(..8) != Some(discriminator) {
+        return Err("not a Pyth price account".to_string());
+    }
+    let truncated = || "truncated price update account".to_string();
+    // The write authority comes first, then the verification level: `Partial` carries
+    // the number of signatures, `Full` nothing
+    let message = match data.get(40) {
+        Some(0) => 42,
+        Some(1) => 41,
+        Some(level) => return Err(format!("unknown verification level {level}")),
+        None => return Err(truncated()),
+    };
+    // The message starts with the 32-byte feed id
+    let price = read_i64(data, message + 32).ok_or_else(truncated)?;
+    let exponent = read_i32(data, message + 48).ok_or_else(truncated)?;
+    let publish_time = read_i64(data, message + 52).ok_or_else(truncated)?;
+    Ok(Price {
+        price: scale(price, exponent),
+        publish_time,
+    })
+}
+
+fn scale(price: i64, exponent: i32) -> f64 {
+    price as f64 * 10f64.powi(exponent)
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset + N)?.try_into().ok()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    read(data, offset).map(u32::from_le_bytes)
+}
+
+fn read_i32(data: &[u8], offset: usize) -> Option<i32> {
+    read(data, offset).map(i32::from_le_bytes)
+}
+
+fn read_i64(data: &[u8], offset: usize) -> Option<i64> {
+    read(data, offset).map(i64::from_le_bytes)
+}
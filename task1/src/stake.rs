@@ -0,0 +1,61 @@
+use common::stake::StakeContext;
+use common::TaskResult;
+use serde::Serialize;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::stake::{self, state::StakeStateV2};
+
+// Offset of the withdraw authority in a stake account: the state tag, the rent exempt
+// reserve and the stake authority come first
+const WITHDRAWER_OFFSET: usize = 4 + 8 + 32;
+
+/// The stake accounts a wallet is the withdraw authority of.
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct StakeBalance {
+    pub accounts: usize,
+    /// Lamports of effective delegated stake
+    pub active: u64,
+    /// Every other lamport of the accounts: activating, deactivating or undelegated
+    /// stake and rent exempt reserves
+    pub inactive: u64,
+}
+
+/// Sums the stake accounts whose withdraw authority is `wallet`.
+pub async fn fetch_stake(
+    wallet: &Pubkey,
+    client: &RpcClient,
+    context: &StakeContext,
+) -> TaskResult<StakeBalance> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(StakeStateV2::size_of() as u64),
+            RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                WITHDRAWER_OFFSET,
+                wallet.as_ref(),
+            )),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(client.commitment()),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = client
+        .get_program_accounts_with_config(&stake::program::id(), config)
+        .await?;
+
+    let mut balance = StakeBalance::default();
+    for (_, account) in accounts {
+        balance.accounts += 1;
+        let active = common::stake::state(&account.data)
+            .and_then(|state| context.activation(&state))
+            .map_or(0, |activation| activation.effective);
+        balance.active += active;
+        balance.inactive += account.lamports.saturating_sub(active);
+    }
+    Ok(balance)
+}
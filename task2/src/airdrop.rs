@@ -0,0 +1,83 @@
+use common::explorer::ExplorerLinks;
+use common::output::{self, OutputFormat};
+use common::{TaskError, TaskResult};
+use serde::Serialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use tracing::{error, info, warn};
+
+/// Outcome of the airdrop to one wallet.
+#[derive(Serialize)]
+struct AirdropResult {
+    #[serde(serialize_with = "output::serialize_display")]
+    address: Pubkey,
+    lamports: u64,
+    #[serde(serialize_with = "output::serialize_optional_display")]
+    signature: Option<Signature>,
+    /// Balance after the airdrop was confirmed
+    balance: Option<u64>,
+    #[serde(serialize_with = "output::serialize_optional_display")]
+    error: Option<TaskError>,
+}
+
+async fn request(client: &RpcClient, address: &Pubkey, lamports: u64) -> TaskResult<Signature> {
+    let signature = client.request_airdrop(address, lamports).await?;
+    client
+        .poll_for_signature_with_commitment(&signature, client.commitment())
+        .await
+        .map_err(|e| TaskError::from_send_error(e, signature))?;
+    Ok(signature)
+}
+
+/// Requests `lamports` from the faucet for every wallet, one at a time since faucets rate
+/// limit, and reports the balances once the airdrops are confirmed. Only works on devnet,
+/// testnet and local validators.
+pub async fn fund(
+    wallets: &[Pubkey],
+    lamports: u64,
+    client: &RpcClient,
+    explorer: Option<&ExplorerLinks>,
+    output: OutputFormat,
+) -> TaskResult<()> {
+    let mut first_error = None;
+    for address in wallets {
+        let mut result = AirdropResult {
+            address: *address,
+            lamports,
+            signature: None,
+            balance: None,
+            error: None,
+        };
+        match request(client, address, lamports).await {
+            Ok(signature) => {
+                result.signature = Some(signature);
+                match client.get_balance(address).await {
+                    Ok(balance) => result.balance = Some(balance),
+                    Err(e) => result.error = Some(e.into()),
+                }
+            }
+            Err(e) => result.error = Some(e),
+        }
+
+        if output == OutputFormat::Json {
+            output::print_json(&result)?;
+        } else {
+            match (&result.signature, result.balance, &result.error) {
+                (_, _, Some(e)) => error!(%address, error = %e, "airdrop failed"),
+                (Some(signature), Some(balance), None) => {
+                    let url = explorer.map(|links| links.transaction(&signature.to_string()));
+                    info!(%address, lamports, balance, %signature, url, "airdrop received")
+                }
+                _ => warn!(%address, "airdrop status unknown"),
+            }
+        }
+        if first_error.is_none() {
+            first_error = result.error.take();
+        }
+    }
+
+    match first_error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,465 @@
+use crate::idempotency::{AmountMode, IdempotencyStore};
+use crate::shutdown::Shutdown;
+use crate::{
+    alert_results, floor, make_transfers, rent, report, sender_multisig, sender_nonce, shares,
+    transfer_amount, Printer, SendOptions, SkipReason, TokenMint, Transfer, TransferResult,
+    YamlFile, DEFAULT_IDEMPOTENCY_STORE, DEFAULT_MAX_REBUILDS, FAN_OUT_BATCH_SIZE,
+};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use common::alerts::Notifier;
+use common::amount::Amount;
+use common::recipients::RecipientPolicy;
+use common::signer::TxSigner;
+use common::transaction::TransactionBuilder;
+use common::{TaskError, TaskResult};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::hash::{self, Hash};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+/// Environment variable holding the bearer token requests must carry, taking precedence
+/// over `api_token` in the config
+pub const API_TOKEN_ENV: &str = "TASK2_API_TOKEN";
+/// Most jobs kept for `GET /jobs/:id`. The oldest finished ones are dropped to make room,
+/// and transfers are refused while that many are unfinished
+const MAX_JOBS: usize = 1000;
+
+/// What the `serve` subcommand sends transfers with, set up from the config like a run.
+pub struct Api {
+    pub config: YamlFile,
+    /// Amount of the requested transfers that give none
+    pub amount: Amount,
+    pub mint: Option<TokenMint>,
+    pub client: Arc<RpcClient>,
+    pub builder: TransactionBuilder,
+    pub printer: Printer,
+    pub notifier: Notifier,
+    pub policy: RecipientPolicy,
+}
+
+/// The body of `POST /transfers`.
+#[derive(Deserialize)]
+struct TransferRequest {
+    /// Public key of the sender of the config paying, the first one when omitted
+    #[serde(default)]
+    sender: Option<String>,
+    /// Name the transfers are remembered under in the idempotency store. Posting the same
+    /// transfers again with the same batch id skips the ones already sent
+    #[serde(default)]
+    batch_id: Option<String>,
+    transfers: Vec<RequestedTransfer>,
+}
+
+#[derive(Deserialize)]
+struct RequestedTransfer {
+    to: String,
+    #[serde(default)]
+    amount: Option<Amount>,
+    #[serde(default)]
+    memo: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum JobState {
+    /// Waiting for the jobs posted before to finish
+    Queued,
+    Running,
+    Finished,
+    /// The transfers could not be sent at all, see `error`
+    Failed,
+}
+
+impl JobState {
+    fn done(self) -> bool {
+        matches!(self, JobState::Finished | JobState::Failed)
+    }
+}
+
+/// The transfers of one `POST /transfers`, polled with `GET /jobs/:id`.
+#[derive(Serialize)]
+struct Job {
+    id: u64,
+    state: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    results: Vec<TransferResult>,
+}
+
+#[derive(Deserialize)]
+struct BalancesQuery {
+    /// Comma-separated addresses, the senders and recipients of the config by default
+    #[serde(default)]
+    addresses: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Balance {
+    address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    lamports: u64,
+    /// UI amount of `mint` in the associated token account, `None` without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token_amount: Option<String>,
+}
+
+/// Transfers of a job waiting for their turn, with the batch id they were posted with.
+type QueuedJob = (u64, Vec<Transfer>, Option<String>);
+
+struct Server {
+    api: Api,
+    senders: Vec<Arc<dyn TxSigner>>,
+    jobs: Mutex<HashMap<u64, Job>>,
+    /// Id of the last job posted
+    last_job: AtomicU64,
+    /// Hash of the bearer token requests must carry, any request is served without one
+    token: Option<Hash>,
+    /// Jobs run one after the other, so they never race for the funds or the nonce of a
+    /// sender
+    queue: mpsc::UnboundedSender<QueuedJob>,
+    shutdown: Shutdown,
+}
+
+/// Serves `GET /balances`, `POST /transfers` and `GET /jobs/:id` at `addr` until Ctrl+C,
+/// after which the transfers in flight get the usual drain time. Without a bearer token
+/// only a loopback address is served.
+pub async fn serve(addr: &str, api: Api, shutdown: Shutdown) -> TaskResult<()> {
+    let token = std::env::var(API_TOKEN_ENV)
+        .ok()
+        .or_else(|| api.config.api_token.clone())
+        .filter(|token| !token.is_empty());
+    let mut senders = Vec::with_capacity(api.config.sender_private_keys.len());
+    for (i, key) in api.config.sender_private_keys.iter().enumerate() {
+        senders.push(key.signer().map_err(|e| {
+            TaskError::KeyError(format!("sender private key number {}: {e}", i + 1))
+        })?);
+    }
+    if senders.is_empty() {
+        return Err(TaskError::ConfigError(
+            "no sender_private_keys to send transfers from".to_string(),
+        ));
+    }
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| TaskError::ConfigError(format!("cannot listen on {addr}: {e}")))?;
+    if token.is_none() && !listener.local_addr()?.ip().is_loopback() {
+        return Err(TaskError::ConfigError(format!(
+            "{addr} is reachable from other hosts, set api_token or {API_TOKEN_ENV} to serve on it"
+        )));
+    }
+    let (queue, mut queued) = mpsc::unbounded_channel();
+    let server = Arc::new(Server {
+        api,
+        senders,
+        jobs: Mutex::new(HashMap::new()),
+        last_job: AtomicU64::new(0),
+        token: token.map(|token| hash::hash(token.as_bytes())),
+        queue,
+        shutdown: shutdown.clone(),
+    });
+    let app = Router::new()
+        .route("/balances", get(balances_handler))
+        .route("/transfers", post(transfers_handler))
+        .route("/jobs/:id", get(job_handler))
+        .layer(middleware::from_fn_with_state(server.clone(), authorize))
+        .with_state(server.clone());
+    info!(addr, "serving the transfer API");
+    let serve =
+        axum::serve(listener, app).with_graceful_shutdown(async move { shutdown.deadline().await });
+    // Jobs run here rather than in spawned tasks, and stop with the server
+    let worker = async {
+        while let Some((id, transfers, batch_id)) = queued.recv().await {
+            server.set_job(id, JobState::Running, None);
+            match server.run_job(transfers, batch_id.as_deref()).await {
+                Ok(results) => server.set_job(id, JobState::Finished, Some(Ok(results))),
+                Err(e) => {
+                    error!(job = id, error = %e, "job failed");
+                    server.set_job(id, JobState::Failed, Some(Err(e)));
+                }
+            }
+        }
+    };
+    tokio::select! {
+        result = serve => result?,
+        () = worker => {}
+    }
+    Ok(())
+}
+
+impl Server {
+    // The jobs stay readable even if a holder of the lock panicked
+    fn jobs(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn set_job(&self, id: u64, state: JobState, outcome: Option<TaskResult<Vec<TransferResult>>>) {
+        let mut jobs = self.jobs();
+        let Some(job) = jobs.get_mut(&id) else {
+            return;
+        };
+        job.state = state;
+        match outcome {
+            Some(Ok(results)) => job.results = results,
+            Some(Err(e)) => job.error = Some(e.to_string()),
+            None => {}
+        }
+    }
+
+    // Function for turning a request into transfers, rejecting it before a job is created
+    fn form_transfers(&self, request: TransferRequest) -> TaskResult<Vec<Transfer>> {
+        let config = &self.api.config;
+        let sender = match &request.sender {
+            Some(sender) => self
+                .senders
+                .iter()
+                .find(|signer| signer.pubkey().to_string() == *sender)
+                .ok_or_else(|| TaskError::KeyError(format!("{sender} is not a sender")))?,
+            None => &self.senders[0],
+        };
+        if request.transfers.is_empty() {
+            return Err(TaskError::ConfigError("no transfers".to_string()));
+        }
+        let multisig = sender_multisig(config, &sender.pubkey())?;
+        let mut transfers = Vec::with_capacity(request.transfers.len());
+        for (id, requested) in request.transfers.into_iter().enumerate() {
+            let recepient_public_key = Pubkey::from_str(&requested.to)
+                .map_err(|e| TaskError::KeyError(format!("recipient {}: {e}", requested.to)))?;
+            self.api.policy.check(&recepient_public_key)?;
+            let amount = requested.amount.as_ref().unwrap_or(&self.api.amount);
+            let (amount, percent) = transfer_amount(amount, self.api.mint)
+                .map_err(|e| TaskError::ConfigError(format!("amount for {}: {e}", requested.to)))?;
+            transfers.push(Transfer {
+                id,
+                amount,
+                sender: sender.clone(),
+                recepient_public_key,
+                mint: self.api.mint,
+                nonce: sender_nonce(config, &sender.pubkey())?,
+                multisig,
+                memo: requested.memo.or(config.memo.clone()),
+                reference: None,
+                percent,
+                skip: None,
+            });
+        }
+        Ok(transfers)
+    }
+
+    // Function for sending the transfers of a job the way a run of the config would
+    async fn run_job(
+        &self,
+        mut transfers: Vec<Transfer>,
+        batch_id: Option<&str>,
+    ) -> TaskResult<Vec<TransferResult>> {
+        let Api {
+            config,
+            client,
+            builder,
+            ..
+        } = &self.api;
+        if transfers.iter().any(|transfer| transfer.percent.is_some()) {
+            shares::set_amounts(&mut transfers, client, builder, false).await?;
+        }
+        let path = config
+            .idempotency_store
+            .as_deref()
+            .unwrap_or(DEFAULT_IDEMPOTENCY_STORE);
+        // Only transfers with a batch id are remembered, and checked against the store
+        let mut idempotency = match batch_id {
+            Some(batch_id) => Some(IdempotencyStore::open(path, batch_id)?),
+            None => None,
+        };
+        if let Some(idempotency) = &mut idempotency {
+            let completed = idempotency
+                .check(&transfers, AmountMode::Configured, client)
+                .await?;
+            for transfer in &mut transfers {
+                if let Some(signature) = completed.get(&transfer.id) {
+                    let signature = signature.clone();
+                    transfer.skip = Some(SkipReason::AlreadySent { signature });
+                }
+            }
+        }
+        rent::check_recipients(&mut transfers, client, false).await?;
+        if let Some(floor) = config.keep_minimum_lamports {
+            floor::protect(&mut transfers, client, builder, floor).await?;
+        }
+        let results = make_transfers(
+            &transfers,
+            client,
+            builder,
+            &self.api.printer,
+            &SendOptions {
+                dry_run: false,
+                send: &config.send,
+                confirmation_timeout: config.timeouts.confirmation_timeout,
+                max_concurrency: config.max_concurrency.unwrap_or(transfers.len()).max(1),
+                batch_size: config
+                    .transfers_per_transaction
+                    .unwrap_or(FAN_OUT_BATCH_SIZE)
+                    .max(1),
+                max_rebuilds: config.max_rebuilds.unwrap_or(DEFAULT_MAX_REBUILDS),
+                checkpoint: None,
+                idempotency: idempotency.as_ref(),
+                shutdown: &self.shutdown,
+            },
+        )
+        .await?;
+        if let Some(path) = &config.database_path {
+            report::save(path, &results)?;
+        }
+        alert_results(&self.api.notifier, &results, self.api.mint.is_none()).await;
+        Ok(results)
+    }
+}
+
+fn error_response(status: StatusCode, e: impl std::fmt::Display) -> Response {
+    (status, Json(json!({ "error": e.to_string() }))).into_response()
+}
+
+// Rejects requests without the bearer token of the server, when it has one
+async fn authorize(State(server): State<Arc<Server>>, request: Request, next: Next) -> Response {
+    if let Some(token) = server.token {
+        let given = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        // Compared by hash, so the time taken does not tell how much of the token matched
+        if given.map(|given| hash::hash(given.as_bytes())) != Some(token) {
+            return error_response(StatusCode::UNAUTHORIZED, "missing or wrong bearer token");
+        }
+    }
+    next.run(request).await
+}
+
+async fn balances_handler(
+    State(server): State<Arc<Server>>,
+    Query(query): Query<BalancesQuery>,
+) -> Response {
+    let api = &server.api;
+    let addresses: Vec<String> = match &query.addresses {
+        Some(addresses) => addresses
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => {
+            let senders = server
+                .senders
+                .iter()
+                .map(|sender| sender.pubkey().to_string());
+            let recipients = api
+                .config
+                .recepient_pyblic_keys
+                .iter()
+                .map(|recipient| recipient.address().to_string());
+            let mut addresses: Vec<String> = Vec::new();
+            for address in senders.chain(recipients) {
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+            addresses
+        }
+    };
+    let mut pubkeys = Vec::with_capacity(addresses.len());
+    for address in &addresses {
+        match Pubkey::from_str(address) {
+            Ok(pubkey) => pubkeys.push(pubkey),
+            Err(e) => return error_response(StatusCode::BAD_REQUEST, format!("{address}: {e}")),
+        }
+    }
+    let balances = join_all(pubkeys.iter().map(|pubkey| async {
+        let lamports = api.client.get_balance(pubkey).await?;
+        let token_amount = match api.mint {
+            // An owner without a token account holds none of the mint
+            Some(mint) => api
+                .client
+                .get_token_account_balance(&mint.token_account(pubkey))
+                .await
+                .ok()
+                .map(|balance| balance.ui_amount_string),
+            None => None,
+        };
+        Ok::<_, TaskError>(Balance {
+            address: pubkey.to_string(),
+            label: api
+                .printer
+                .labels
+                .get(&pubkey.to_string())
+                .map(str::to_string),
+            lamports,
+            token_amount,
+        })
+    }))
+    .await;
+    match balances.into_iter().collect::<TaskResult<Vec<_>>>() {
+        Ok(balances) => Json(json!({ "balances": balances })).into_response(),
+        Err(e) => error_response(StatusCode::BAD_GATEWAY, e),
+    }
+}
+
+async fn transfers_handler(
+    State(server): State<Arc<Server>>,
+    Json(request): Json<TransferRequest>,
+) -> Response {
+    let batch_id = request.batch_id.clone();
+    let transfers = match server.form_transfers(request) {
+        Ok(transfers) => transfers,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+    let id = {
+        let mut jobs = server.jobs();
+        if jobs.len() >= MAX_JOBS {
+            let oldest = jobs
+                .values()
+                .filter(|job| job.state.done())
+                .map(|job| job.id)
+                .min();
+            let Some(oldest) = oldest else {
+                let e = format!("{MAX_JOBS} jobs are unfinished, try again later");
+                return error_response(StatusCode::SERVICE_UNAVAILABLE, e);
+            };
+            jobs.remove(&oldest);
+        }
+        let id = server.last_job.fetch_add(1, Ordering::Relaxed) + 1;
+        let job = Job {
+            id,
+            state: JobState::Queued,
+            error: None,
+            results: Vec::new(),
+        };
+        jobs.insert(id, job);
+        id
+    };
+    info!(job = id, transfers = transfers.len(), "transfers queued");
+    if server.queue.send((id, transfers, batch_id)).is_err() {
+        server.set_job(id, JobState::Failed, Some(Err(TaskError::Interrupted)));
+    }
+    let body = json!({ "id": id, "status_url": format!("/jobs/{id}") });
+    (StatusCode::ACCEPTED, Json(body)).into_response()
+}
+
+async fn job_handler(State(server): State<Arc<Server>>, Path(id): Path<u64>) -> Response {
+    match server.jobs().get(&id) {
+        Some(job) => Json(job).into_response(),
+        None => error_response(StatusCode::NOT_FOUND, format!("no job {id}")),
+    }
+}
@@ -0,0 +1,188 @@
+use crate::Transfer;
+use common::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{commitment_config::CommitmentConfig, hash::Hash, signature::Signature};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::Mutex,
+};
+
+/// Where a transfer stands, as recorded in a checkpoint.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferState {
+    Pending,
+    /// Handed to the RPC node, not known to be confirmed yet
+    Sent,
+    Confirmed,
+    Failed,
+}
+
+/// One line of a checkpoint file. The file is only ever appended to, so a run killed
+/// halfway loses at most the line it was writing, and the last line of a transfer wins.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    id: usize,
+    from: String,
+    to: String,
+    pub state: TransferState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    /// Blockhash of the sent transaction, to tell whether it can still land
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blockhash: Option<String>,
+}
+
+impl Record {
+    pub fn signature(&self) -> Option<Signature> {
+        self.signature
+            .as_deref()
+            .and_then(|s| Signature::from_str(s).ok())
+    }
+}
+
+/// Records the state of every transfer of a run in a file, so an interrupted run can be
+/// resumed without sending confirmed transfers again.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: String,
+    file: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// Starts a new checkpoint at `path`, replacing any previous one, with every transfer
+    /// pending.
+    pub fn create(path: &str, transfers: &[Transfer]) -> TaskResult<Self> {
+        let checkpoint = Checkpoint {
+            path: path.to_string(),
+            file: Mutex::new(File::create(path)?),
+        };
+        for transfer in transfers {
+            checkpoint.record(transfer, TransferState::Pending, None, None)?;
+        }
+        Ok(checkpoint)
+    }
+
+    /// Opens the checkpoint of an interrupted run and returns the last recorded state of
+    /// each transfer, keyed by its id. The checkpoint must have been written for the same
+    /// transfers.
+    pub fn resume(
+        path: &str,
+        transfers: &[Transfer],
+    ) -> TaskResult<(Self, HashMap<usize, Record>)> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| TaskError::ConfigError(format!("cannot read {path}: {e}")))?;
+        let mut records = HashMap::new();
+        let last = content.lines().count();
+        for (i, line) in content.lines().enumerate() {
+            let record: Record = match serde_json::from_str(line) {
+                Ok(record) => record,
+                // The run was killed while writing its last line
+                Err(_) if i + 1 == last => break,
+                Err(e) => return Err(TaskError::ConfigError(format!("{path}:{}: {e}", i + 1))),
+            };
+            let matches = transfers.get(record.id).is_some_and(|transfer| {
+                record.from == transfer.source().to_string()
+                    && record.to == transfer.recepient_public_key.to_string()
+            });
+            if !matches {
+                return Err(TaskError::ConfigError(format!(
+                    "{path} was written for other transfers, transfer {} from {} to {} is not in this run",
+                    record.id, record.from, record.to
+                )));
+            }
+            // A transfer that failed after sending keeps the signature it was sent with,
+            // its transaction may still have landed
+            let previous = records.insert(record.id, record);
+            if let Some(previous) = previous {
+                let record = records.get_mut(&previous.id).expect("just inserted");
+                if record.signature.is_none() {
+                    record.signature = previous.signature;
+                    record.blockhash = previous.blockhash;
+                }
+            }
+        }
+
+        let file = OpenOptions::new().append(true).open(path)?;
+        let checkpoint = Checkpoint {
+            path: path.to_string(),
+            file: Mutex::new(file),
+        };
+        Ok((checkpoint, records))
+    }
+
+    /// Appends the new state of a transfer, flushed before returning.
+    pub fn record(
+        &self,
+        transfer: &Transfer,
+        state: TransferState,
+        signature: Option<&Signature>,
+        blockhash: Option<&Hash>,
+    ) -> TaskResult<()> {
+        let record = Record {
+            id: transfer.id,
+            from: transfer.source().to_string(),
+            to: transfer.recepient_public_key.to_string(),
+            state,
+            signature: signature.map(|signature| signature.to_string()),
+            blockhash: blockhash.map(|blockhash| blockhash.to_string()),
+        };
+        let mut line = serde_json::to_string(&record)
+            .map_err(|e| TaskError::BuildError(format!("checkpoint {}: {e}", self.path)))?;
+        line.push('\n');
+        let mut file = self.file.lock().expect("checkpoint lock poisoned");
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Whether a transfer recorded as sent has landed. Its transaction is looked up in the
+/// whole history, and waited for while its blockhash is still valid, so a transfer is
+/// only sent again once the first transaction can no longer land.
+pub async fn landed(record: &Record, client: &RpcClient) -> TaskResult<bool> {
+    let Some(signature) = record.signature() else {
+        return Ok(false);
+    };
+    let blockhash = record
+        .blockhash
+        .as_deref()
+        .and_then(|s| Hash::from_str(s).ok());
+    signature_landed(client, &signature, blockhash.as_ref()).await
+}
+
+/// Whether the transaction of `signature`, sent with `blockhash`, has landed without
+/// error, waiting for it while the blockhash is still valid.
+pub async fn signature_landed(
+    client: &RpcClient,
+    signature: &Signature,
+    blockhash: Option<&Hash>,
+) -> TaskResult<bool> {
+    let status = client
+        .get_signature_statuses_with_history(&[*signature])
+        .await?
+        .value
+        .remove(0);
+    if let Some(status) = status {
+        return Ok(status.err.is_none());
+    }
+    let Some(blockhash) = blockhash else {
+        return Ok(false);
+    };
+    if !client
+        .is_blockhash_valid(blockhash, CommitmentConfig::processed())
+        .await?
+    {
+        return Ok(false);
+    }
+    match common::transaction::confirm(client, signature, blockhash).await {
+        Ok(_) => Ok(true),
+        Err(TaskError::ConfirmationTimeout(_)) => Ok(false),
+        Err(TaskError::RpcError(e)) if e.get_transaction_error().is_some() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{sender_multisig, sender_nonce, transfer_amount, TokenMint, Transfer, YamlFile};
+use common::{amount::Amount, keys, signer::TxSigner, TaskError, TaskResult};
+use serde::Deserialize;
+use solana_sdk::pubkey::Pubkey;
+use std::{
+    io::{self, Read},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// One row of a `sender_key,recipient,amount,memo` transfer file.
+#[derive(Deserialize)]
+struct CsvRow {
+    sender_key: String,
+    recipient: String,
+    #[serde(default)]
+    amount: Option<Amount>,
+    #[serde(default)]
+    memo: Option<String>,
+}
+
+fn parse_row(
+    id: usize,
+    row: CsvRow,
+    config_yaml: &YamlFile,
+    default_amount: &Amount,
+    mint: Option<TokenMint>,
+) -> Result<Transfer, String> {
+    let sender: Arc<dyn TxSigner> = Arc::new(keys::keypair_from_text(&row.sender_key)?);
+    let recepient_public_key = Pubkey::from_str(row.recipient.trim())
+        .map_err(|e| format!("recipient {}: {e}", row.recipient))?;
+    let nonce = sender_nonce(config_yaml, &sender.pubkey()).map_err(|e| e.to_string())?;
+    let multisig = sender_multisig(config_yaml, &sender.pubkey()).map_err(|e| e.to_string())?;
+
+    let (amount, percent) = transfer_amount(row.amount.as_ref().unwrap_or(default_amount), mint)
+        .map_err(|e| format!("amount: {e}"))?;
+
+    Ok(Transfer {
+        id,
+        amount,
+        sender,
+        recepient_public_key,
+        mint,
+        nonce,
+        multisig,
+        memo: row
+            .memo
+            .filter(|memo| !memo.is_empty())
+            .or_else(|| config_yaml.memo.clone()),
+        reference: None,
+        percent,
+        skip: None,
+    })
+}
+
+/// Reads transfers from a CSV file with a `sender_key,recipient,amount,memo` header, or
+/// from stdin when `path` is `-`. Every invalid row is reported with its line number, not
+/// just the first one.
+pub fn read_transfers(
+    path: &str,
+    config_yaml: &YamlFile,
+    default_amount: &Amount,
+    mint: Option<TokenMint>,
+) -> TaskResult<Vec<Transfer>> {
+    let (path, input): (&str, Box<dyn Read>) = if path == "-" {
+        ("stdin", Box::new(io::stdin()))
+    } else {
+        let file = std::fs::File::open(path)
+            .map_err(|e| TaskError::ConfigError(format!("cannot read {path}: {e}")))?;
+        (path, Box::new(file))
+    };
+    let read_error = |e: csv::Error| TaskError::ConfigError(format!("cannot read {path}: {e}"));
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(input);
+    let headers = reader.headers().map_err(read_error)?.clone();
+
+    let mut transfers = Vec::new();
+    let mut errors = Vec::new();
+    let mut record = csv::StringRecord::new();
+    let mut id = 0;
+    while reader.read_record(&mut record).map_err(read_error)? {
+        let line = record.position().map_or(0, |position| position.line());
+        let transfer = record
+            .deserialize::<CsvRow>(Some(&headers))
+            .map_err(|e| e.to_string())
+            .and_then(|row| parse_row(id, row, config_yaml, default_amount, mint));
+        id += 1;
+        match transfer {
+            Ok(transfer) => transfers.push(transfer),
+            Err(e) => errors.push(format!("{path}:{line}: {e}")),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(TaskError::ConfigError(format!(
+            "{} invalid row(s) in {path}:\n{}",
+            errors.len(),
+            errors.join("\n")
+        )));
+    }
+    Ok(transfers)
+}
@@ -0,0 +1,61 @@
+use crate::{sweep, SkipReason, Transfer};
+use common::transaction::TransactionBuilder;
+use common::TaskResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Makes sure no source ends up with less than `floor` lamports once its transfers and
+/// their fees are paid. Transfers are served in order: the first one that does not fit is
+/// lowered to what is left above the floor, the ones after it are skipped.
+pub async fn protect(
+    transfers: &mut [Transfer],
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+    floor: u64,
+) -> TaskResult<()> {
+    // Balance of each source once the transfers before are paid
+    let mut remaining: HashMap<Pubkey, u64> = HashMap::new();
+    for transfer in transfers.iter_mut() {
+        if transfer.amount == 0 || transfer.skip.is_some() {
+            continue;
+        }
+        let source = transfer.source();
+        let balance = match remaining.get(&source) {
+            Some(balance) => *balance,
+            None => client.get_balance(&source).await?,
+        };
+        let fee = sweep::source_fee(transfer, client, builder).await?;
+        // Token transfers only take the fee from the SOL balance
+        let amount = if transfer.mint.is_some() {
+            0
+        } else {
+            transfer.amount
+        };
+        let needed = amount.saturating_add(fee);
+        let available = balance.saturating_sub(floor);
+        if needed <= available {
+            remaining.insert(source, balance - needed);
+            continue;
+        }
+        // Only a SOL amount can be lowered, the fee stays the same
+        if transfer.mint.is_none() && available > fee {
+            let lowered = available - fee;
+            warn!(
+                %source,
+                to = %transfer.recepient_public_key,
+                amount = transfer.amount,
+                lowered,
+                floor,
+                "transfer lowered to keep the minimum balance"
+            );
+            transfer.amount = lowered;
+            remaining.insert(source, floor);
+            continue;
+        }
+        transfer.skip = Some(SkipReason::FloorProtected { balance, floor });
+        remaining.insert(source, balance);
+    }
+    Ok(())
+}
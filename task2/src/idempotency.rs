@@ -0,0 +1,342 @@
+use crate::checkpoint::{self, TransferState};
+use crate::Transfer;
+use common::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    hash::{self, Hash},
+    signature::Signature,
+};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    str::FromStr,
+    sync::Mutex,
+};
+
+fn confirmed() -> TransferState {
+    TransferState::Confirmed
+}
+
+/// One sent or completed transfer, a line of the store.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Entry {
+    key: String,
+    batch_id: String,
+    from: String,
+    to: String,
+    amount: u64,
+    signature: String,
+    /// Entries written before sent transfers were recorded are all confirmed
+    #[serde(default = "confirmed")]
+    state: TransferState,
+    /// Blockhash of a sent transaction, to tell whether it can still land
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    blockhash: Option<String>,
+}
+
+/// How the amounts of a run are worked out. Amounts read from balances change from one run
+/// to the next, so those transfers are keyed by how their amount is configured instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountMode {
+    /// Fixed amounts and percentages, as given in the config
+    Configured,
+    /// `--split` of every source balance
+    Split,
+    /// `--sweep` of every source balance
+    Sweep,
+}
+
+/// Remembers every sent and completed transfer across runs, keyed by a hash of its
+/// sender, recipient, configured amount, mint and batch id, so running the same config
+/// twice does not pay anyone twice.
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    path: String,
+    file: Mutex<File>,
+    /// Signatures of the completed transfers by key
+    completed: HashMap<String, String>,
+    /// Signatures and blockhashes of the transactions sent for each key, not known to
+    /// have landed
+    sent: HashMap<String, Vec<(Signature, Option<Hash>)>>,
+    batch_id: String,
+    /// Keys of the transfers of this run by transfer id
+    keys: HashMap<usize, String>,
+}
+
+impl IdempotencyStore {
+    /// Opens the store at `path`, creating it when missing.
+    pub fn open(path: &str, batch_id: &str) -> TaskResult<Self> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(TaskError::ConfigError(format!("cannot read {path}: {e}"))),
+        };
+        let mut completed = HashMap::new();
+        let mut sent: HashMap<String, Vec<_>> = HashMap::new();
+        for (i, line) in content.lines().enumerate() {
+            // A line cut short by a killed run is ignored
+            let Ok(entry) = serde_json::from_str::<Entry>(line) else {
+                tracing::warn!("{path}:{}: unreadable entry ignored", i + 1);
+                continue;
+            };
+            match entry.state {
+                TransferState::Confirmed => {
+                    completed.insert(entry.key, entry.signature);
+                }
+                TransferState::Sent => {
+                    let Ok(signature) = Signature::from_str(&entry.signature) else {
+                        continue;
+                    };
+                    let blockhash = entry
+                        .blockhash
+                        .as_deref()
+                        .and_then(|s| Hash::from_str(s).ok());
+                    sent.entry(entry.key)
+                        .or_default()
+                        .push((signature, blockhash));
+                }
+                TransferState::Pending | TransferState::Failed => {}
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(IdempotencyStore {
+            path: path.to_string(),
+            file: Mutex::new(file),
+            completed,
+            sent,
+            batch_id: batch_id.to_string(),
+            keys: HashMap::new(),
+        })
+    }
+
+    /// Works out the key of every transfer, and returns the signatures of the ones
+    /// completed before by transfer id. Identical transfers of one run are told apart by
+    /// how many came before them. A transfer sent before but not known to be completed
+    /// counts as completed once one of its transactions is found to have landed.
+    pub async fn check(
+        &mut self,
+        transfers: &[Transfer],
+        mode: AmountMode,
+        client: &RpcClient,
+    ) -> TaskResult<HashMap<usize, String>> {
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
+        let mut completed = HashMap::new();
+        for transfer in transfers {
+            let amount = match (mode, &transfer.percent) {
+                (AmountMode::Split, _) => "split".to_string(),
+                (AmountMode::Sweep, _) => "sweep".to_string(),
+                (AmountMode::Configured, Some(percent)) => percent.to_string(),
+                (AmountMode::Configured, None) => transfer.amount.to_string(),
+            };
+            let fields = format!(
+                "{}:{}:{}:{}:{}",
+                transfer.source(),
+                transfer.recepient_public_key,
+                amount,
+                transfer
+                    .mint
+                    .map(|mint| mint.address.to_string())
+                    .unwrap_or_default(),
+                self.batch_id
+            );
+            let occurrence = occurrences.entry(fields.clone()).or_default();
+            let key =
+                hash::hashv(&[fields.as_bytes(), &(*occurrence as u64).to_le_bytes()]).to_string();
+            *occurrence += 1;
+            self.keys.insert(transfer.id, key.clone());
+            if let Some(signature) = self.completed.get(&key) {
+                completed.insert(transfer.id, signature.clone());
+                continue;
+            }
+            for (signature, blockhash) in self.sent.remove(&key).unwrap_or_default() {
+                if checkpoint::signature_landed(client, &signature, blockhash.as_ref()).await? {
+                    self.record(transfer, TransferState::Confirmed, &signature, None)?;
+                    self.completed.insert(key, signature.to_string());
+                    completed.insert(transfer.id, signature.to_string());
+                    break;
+                }
+            }
+        }
+        Ok(completed)
+    }
+
+    /// Adds a sent or completed transfer to the store, flushed before returning. A
+    /// transfer is recorded as sent before its transaction is, with its blockhash.
+    pub fn record(
+        &self,
+        transfer: &Transfer,
+        state: TransferState,
+        signature: &Signature,
+        blockhash: Option<&Hash>,
+    ) -> TaskResult<()> {
+        let Some(key) = self.keys.get(&transfer.id) else {
+            return Ok(());
+        };
+        let entry = Entry {
+            key: key.clone(),
+            batch_id: self.batch_id.clone(),
+            from: transfer.source().to_string(),
+            to: transfer.recepient_public_key.to_string(),
+            amount: transfer.amount,
+            signature: signature.to_string(),
+            state,
+            blockhash: blockhash.map(|blockhash| blockhash.to_string()),
+        };
+        let mut line = serde_json::to_string(&entry)
+            .map_err(|e| TaskError::BuildError(format!("{}: {e}", self.path)))?;
+        line.push('\n');
+        let mut file = self.file.lock().expect("idempotency store lock poisoned");
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::signer::TxSigner;
+    use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+    use std::sync::Arc;
+
+    // A store file of its own for each test, removed when dropped
+    struct StorePath(String);
+
+    impl StorePath {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "task2-idempotency-{name}-{}.jsonl",
+                std::process::id()
+            ));
+            let _ = fs::remove_file(&path);
+            StorePath(path.to_string_lossy().into_owned())
+        }
+    }
+
+    impl Drop for StorePath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn transfers(count: usize) -> Vec<Transfer> {
+        let sender: Arc<dyn TxSigner> = Arc::new(Keypair::new());
+        let recipient = Pubkey::new_unique();
+        (0..count)
+            .map(|id| Transfer {
+                id,
+                amount: 100,
+                sender: sender.clone(),
+                recepient_public_key: recipient,
+                mint: None,
+                nonce: None,
+                multisig: None,
+                memo: None,
+                reference: None,
+                percent: None,
+                skip: None,
+            })
+            .collect()
+    }
+
+    fn client(url: &str) -> RpcClient {
+        RpcClient::new_mock(url.to_string())
+    }
+
+    #[tokio::test]
+    async fn identical_transfers_get_their_own_keys() {
+        let path = StorePath::new("keys");
+        let transfers = transfers(2);
+        let mut store = IdempotencyStore::open(&path.0, "payout").unwrap();
+        let client = client("sig_not_found");
+        let completed = store
+            .check(&transfers, AmountMode::Configured, &client)
+            .await
+            .unwrap();
+        assert!(completed.is_empty());
+        assert_ne!(store.keys[&0], store.keys[&1]);
+
+        let first = store.keys[&0].clone();
+        let mut split = IdempotencyStore::open(&path.0, "payout").unwrap();
+        split
+            .check(&transfers, AmountMode::Split, &client)
+            .await
+            .unwrap();
+        assert_ne!(split.keys[&0], first);
+        let mut other = IdempotencyStore::open(&path.0, "other").unwrap();
+        other
+            .check(&transfers, AmountMode::Configured, &client)
+            .await
+            .unwrap();
+        assert_ne!(other.keys[&0], first);
+    }
+
+    #[tokio::test]
+    async fn completed_transfers_are_skipped_by_the_same_batch_only() {
+        let path = StorePath::new("completed");
+        let transfers = transfers(2);
+        let client = client("sig_not_found");
+        let signature = Signature::from([7; 64]);
+        let mut store = IdempotencyStore::open(&path.0, "payout").unwrap();
+        store
+            .check(&transfers, AmountMode::Configured, &client)
+            .await
+            .unwrap();
+        store
+            .record(&transfers[0], TransferState::Confirmed, &signature, None)
+            .unwrap();
+
+        let mut store = IdempotencyStore::open(&path.0, "payout").unwrap();
+        let completed = store
+            .check(&transfers, AmountMode::Configured, &client)
+            .await
+            .unwrap();
+        assert_eq!(completed, HashMap::from([(0, signature.to_string())]));
+
+        let mut store = IdempotencyStore::open(&path.0, "next").unwrap();
+        let completed = store
+            .check(&transfers, AmountMode::Configured, &client)
+            .await
+            .unwrap();
+        assert!(completed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sent_transfers_are_skipped_once_landed() {
+        let path = StorePath::new("sent");
+        let transfers = transfers(1);
+        let signature = Signature::from([7; 64]);
+        let mut store = IdempotencyStore::open(&path.0, "payout").unwrap();
+        store
+            .check(&transfers, AmountMode::Configured, &client("sig_not_found"))
+            .await
+            .unwrap();
+        store
+            .record(&transfers[0], TransferState::Sent, &signature, None)
+            .unwrap();
+
+        // Never landed, and its blockhash is unknown, so it can be sent again
+        let mut store = IdempotencyStore::open(&path.0, "payout").unwrap();
+        let completed = store
+            .check(&transfers, AmountMode::Configured, &client("sig_not_found"))
+            .await
+            .unwrap();
+        assert!(completed.is_empty());
+
+        // Landed, so it is skipped and recorded as completed
+        let mut store = IdempotencyStore::open(&path.0, "payout").unwrap();
+        let completed = store
+            .check(&transfers, AmountMode::Configured, &client("succeeds"))
+            .await
+            .unwrap();
+        assert_eq!(completed, HashMap::from([(0, signature.to_string())]));
+        let store = IdempotencyStore::open(&path.0, "payout").unwrap();
+        assert_eq!(
+            store.completed.values().collect::<Vec<_>>(),
+            [&signature.to_string()]
+        );
+    }
+}
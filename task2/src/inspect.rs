@@ -0,0 +1,325 @@
+use common::explorer::ExplorerLinks;
+use common::labels::Labels;
+use common::output::{self, OutputFormat};
+use common::{TaskError, TaskResult};
+use serde::Serialize;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcTransactionConfig;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, compute_budget, native_token::lamports_to_sol,
+    pubkey::Pubkey, signature::Signature, system_instruction::SystemInstruction, system_program,
+};
+use solana_transaction_status::{
+    UiLoadedAddresses, UiTransactionEncoding, UiTransactionTokenBalance,
+};
+use spl_token_2022::instruction::TokenInstruction;
+use std::str::FromStr;
+
+/// A transaction decoded for reading, printed by the `inspect` subcommand.
+#[derive(Serialize)]
+struct Inspection {
+    signature: String,
+    slot: u64,
+    block_time: Option<i64>,
+    /// `None` when the transaction succeeded
+    error: Option<String>,
+    fee: u64,
+    compute_units_consumed: Option<u64>,
+    instructions: Vec<DecodedInstruction>,
+    balance_changes: Vec<BalanceChange>,
+    token_balance_changes: Vec<TokenBalanceChange>,
+    logs: Vec<String>,
+}
+
+/// A top-level instruction of the transaction.
+#[derive(Serialize)]
+struct DecodedInstruction {
+    program: String,
+    description: String,
+    accounts: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct BalanceChange {
+    account: String,
+    pre: u64,
+    post: u64,
+}
+
+#[derive(Serialize)]
+struct TokenBalanceChange {
+    account: String,
+    mint: String,
+    /// UI amounts, `0` when the account did not exist before or after
+    pre: String,
+    post: String,
+}
+
+/// Fetches the transaction `signature` and prints its instructions decoded where the
+/// program is known (System, SPL Token, Token-2022, Memo, Associated Token Account and
+/// Compute Budget), the balances it changed, its fee and its logs.
+pub async fn inspect(
+    client: &RpcClient,
+    signature: &Signature,
+    labels: &Labels,
+    explorer: Option<&ExplorerLinks>,
+    output: OutputFormat,
+) -> TaskResult<()> {
+    // Transactions can only be fetched once confirmed
+    let commitment = match client.commitment() {
+        commitment if commitment.is_at_least_confirmed() => commitment,
+        _ => CommitmentConfig::confirmed(),
+    };
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(commitment),
+        max_supported_transaction_version: Some(0),
+    };
+    let confirmed = client
+        .get_transaction_with_config(signature, config)
+        .await?;
+    let transaction = confirmed
+        .transaction
+        .transaction
+        .decode()
+        .ok_or_else(|| rpc_error(format!("undecodable transaction {signature}")))?;
+    let meta = confirmed
+        .transaction
+        .meta
+        .ok_or_else(|| rpc_error(format!("no status meta for {signature}")))?;
+
+    // Accounts loaded from lookup tables follow the static keys, writable ones first
+    let mut account_keys: Vec<String> = transaction
+        .message
+        .static_account_keys()
+        .iter()
+        .map(Pubkey::to_string)
+        .collect();
+    if let Some(loaded) = Option::<UiLoadedAddresses>::from(meta.loaded_addresses) {
+        account_keys.extend(loaded.writable);
+        account_keys.extend(loaded.readonly);
+    }
+    let key = |index: usize| account_keys.get(index).cloned().unwrap_or_default();
+
+    let instructions = transaction
+        .message
+        .instructions()
+        .iter()
+        .map(|instruction| {
+            let program_id = key(instruction.program_id_index as usize);
+            let (program, description) = decode(&program_id, &instruction.data);
+            DecodedInstruction {
+                program,
+                description,
+                accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|index| labels.display(&key(*index as usize)))
+                    .collect(),
+            }
+        })
+        .collect();
+    let balance_changes = meta
+        .pre_balances
+        .iter()
+        .zip(&meta.post_balances)
+        .enumerate()
+        .filter(|(_, (pre, post))| pre != post)
+        .map(|(index, (pre, post))| BalanceChange {
+            account: labels.display(&key(index)),
+            pre: *pre,
+            post: *post,
+        })
+        .collect();
+    let pre_tokens: Vec<UiTransactionTokenBalance> =
+        Option::from(meta.pre_token_balances).unwrap_or_default();
+    let post_tokens: Vec<UiTransactionTokenBalance> =
+        Option::from(meta.post_token_balances).unwrap_or_default();
+    let token_balance_changes = token_changes(&pre_tokens, &post_tokens)
+        .map(|(index, mint, pre, post)| TokenBalanceChange {
+            account: labels.display(&key(index)),
+            mint,
+            pre,
+            post,
+        })
+        .collect();
+
+    let inspection = Inspection {
+        signature: signature.to_string(),
+        slot: confirmed.slot,
+        block_time: confirmed.block_time,
+        error: meta.err.map(|e| e.to_string()),
+        fee: meta.fee,
+        compute_units_consumed: meta.compute_units_consumed.into(),
+        instructions,
+        balance_changes,
+        token_balance_changes,
+        logs: Option::from(meta.log_messages).unwrap_or_default(),
+    };
+    if output == OutputFormat::Json {
+        return output::print_json(&inspection);
+    }
+    print_inspection(&inspection);
+    if let Some(links) = explorer {
+        println!("explorer: {}", links.transaction(&inspection.signature));
+    }
+    Ok(())
+}
+
+fn rpc_error(message: String) -> TaskError {
+    ClientError::from(ClientErrorKind::Custom(message)).into()
+}
+
+// Function for pairing the token balances before and after by account, keeping the
+// changed ones
+fn token_changes<'a>(
+    pre: &'a [UiTransactionTokenBalance],
+    post: &'a [UiTransactionTokenBalance],
+) -> impl Iterator<Item = (usize, String, String, String)> + 'a {
+    let mut indexes: Vec<u8> = pre
+        .iter()
+        .chain(post)
+        .map(|balance| balance.account_index)
+        .collect();
+    indexes.sort_unstable();
+    indexes.dedup();
+    indexes.into_iter().filter_map(move |index| {
+        let find = |balances: &'a [UiTransactionTokenBalance]| {
+            balances
+                .iter()
+                .find(|balance| balance.account_index == index)
+        };
+        let (before, after) = (find(pre), find(post));
+        let mint = before.or(after)?.mint.clone();
+        let amount = |balance: Option<&UiTransactionTokenBalance>| {
+            balance.map_or("0".to_string(), |balance| {
+                balance.ui_token_amount.ui_amount_string.clone()
+            })
+        };
+        let (before, after) = (amount(before), amount(after));
+        (before != after).then_some((index as usize, mint, before, after))
+    })
+}
+
+// Names the program of an instruction and describes what the instruction does
+fn decode(program_id: &str, data: &[u8]) -> (String, String) {
+    let Ok(program) = Pubkey::from_str(program_id) else {
+        return (
+            program_id.to_string(),
+            format!("{} bytes of data", data.len()),
+        );
+    };
+    if program == system_program::id() {
+        let description = match bincode::deserialize::<SystemInstruction>(data) {
+            Ok(SystemInstruction::Transfer { lamports }) => {
+                format!("Transfer {} SOL", lamports_to_sol(lamports))
+            }
+            Ok(instruction) => format!("{instruction:?}"),
+            Err(e) => format!("undecodable: {e}"),
+        };
+        ("System".to_string(), description)
+    } else if program == spl_token::id() || program == spl_token_2022::id() {
+        let name = match program == spl_token::id() {
+            true => "SPL Token",
+            false => "Token-2022",
+        };
+        let description = match TokenInstruction::unpack(data) {
+            Ok(instruction) => format!("{instruction:?}"),
+            Err(e) => format!("undecodable: {e}"),
+        };
+        (name.to_string(), description)
+    } else if program == spl_memo::id() || program == spl_memo::v1::id() {
+        (
+            "Memo".to_string(),
+            String::from_utf8_lossy(data).into_owned(),
+        )
+    } else if program == spl_associated_token_account::id() {
+        let description = match data.first() {
+            None | Some(0) => "Create",
+            Some(1) => "CreateIdempotent",
+            Some(2) => "RecoverNested",
+            Some(_) => "unknown instruction",
+        };
+        (
+            "Associated Token Account".to_string(),
+            description.to_string(),
+        )
+    } else if program == compute_budget::id() {
+        ("Compute Budget".to_string(), decode_compute_budget(data))
+    } else {
+        (
+            program_id.to_string(),
+            format!("{} bytes of data", data.len()),
+        )
+    }
+}
+
+// Compute budget instructions are a tag byte followed by a little-endian integer
+fn decode_compute_budget(data: &[u8]) -> String {
+    let u32_at = || data.get(1..5)?.try_into().ok().map(u32::from_le_bytes);
+    let u64_at = || data.get(1..9)?.try_into().ok().map(u64::from_le_bytes);
+    let description = match data.first() {
+        Some(1) => u32_at().map(|bytes| format!("RequestHeapFrame {bytes} bytes")),
+        Some(2) => u32_at().map(|units| format!("SetComputeUnitLimit {units} units")),
+        Some(3) => u64_at().map(|price| format!("SetComputeUnitPrice {price} micro-lamports")),
+        Some(4) => u32_at().map(|bytes| format!("SetLoadedAccountsDataSizeLimit {bytes} bytes")),
+        _ => None,
+    };
+    description.unwrap_or_else(|| "unknown instruction".to_string())
+}
+
+fn print_inspection(inspection: &Inspection) {
+    println!("signature: {}", inspection.signature);
+    println!("slot: {}", inspection.slot);
+    if let Some(time) = inspection.block_time {
+        println!("block time: {time}");
+    }
+    match &inspection.error {
+        Some(e) => println!("status: failed, {e}"),
+        None => println!("status: succeeded"),
+    }
+    println!("fee: {} lamports", inspection.fee);
+    if let Some(units) = inspection.compute_units_consumed {
+        println!("compute units consumed: {units}");
+    }
+    println!("instructions:");
+    for (index, instruction) in inspection.instructions.iter().enumerate() {
+        println!(
+            "  #{} {}: {}",
+            index + 1,
+            instruction.program,
+            instruction.description
+        );
+        for account in &instruction.accounts {
+            println!("      {account}");
+        }
+    }
+    if !inspection.balance_changes.is_empty() {
+        println!("balance changes:");
+    }
+    for change in &inspection.balance_changes {
+        println!(
+            "  {}: {} -> {} SOL ({:+} lamports)",
+            change.account,
+            lamports_to_sol(change.pre),
+            lamports_to_sol(change.post),
+            change.post as i128 - change.pre as i128
+        );
+    }
+    if !inspection.token_balance_changes.is_empty() {
+        println!("token balance changes:");
+    }
+    for change in &inspection.token_balance_changes {
+        println!(
+            "  {} (mint {}): {} -> {}",
+            change.account, change.mint, change.pre, change.post
+        );
+    }
+    if !inspection.logs.is_empty() {
+        println!("logs:");
+    }
+    for log in &inspection.logs {
+        println!("  {log}");
+    }
+}
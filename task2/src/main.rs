@@ -1,170 +1,2157 @@
-use futures::stream::FuturesUnordered;
-use futures::StreamExt;
+use checkpoint::{Checkpoint, TransferState};
+use clap::{Parser, Subcommand};
+use common::alerts::{Alert, AlertConfig, FailedTransfer, Notifier};
+use common::amount::Amount;
+use common::blockhash::BlockhashCache;
+use common::compute_budget::ComputeBudgetConfig;
+use common::dashboard::Dashboard;
+use common::explorer::{Explorer, ExplorerLinks};
+use common::keys::KeySource;
+use common::labels::{self, Labels};
+use common::nonce::{DurableNonce, NonceConfig};
+use common::output::{self, OutputFormat};
+use common::rate_limit::RateLimitConfig;
+use common::recipients::{RecipientListsConfig, RecipientPolicy};
+use common::rpc::{Network, RetryConfig, RpcEndpoints, RpcTimeouts};
+use common::signer::TxSigner;
+use common::telemetry::{LogArgs, LogFormat, TelemetryConfig};
+use common::transaction::{SendConfig, Simulation, TransactionBuilder, TransactionConfig};
+use common::{TaskError, TaskResult};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use idempotency::{AmountMode, IdempotencyStore};
+use indicatif::{ProgressBar, ProgressStyle};
+use preview::Preview;
 use serde::{Deserialize, Serialize};
+use shutdown::Shutdown;
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
-    commitment_config::CommitmentConfig,
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    hash::Hash,
+    instruction::{AccountMeta, Instruction},
+    packet::PACKET_DATA_SIZE,
+    program_pack::Pack,
     pubkey::Pubkey,
-    signature::{Keypair, Signature, Signer},
-    system_transaction,
-    transaction::{self, Transaction},
+    signature::{Keypair, Signature},
+    system_instruction,
+    transaction::{self, VersionedTransaction},
 };
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::extension::{
+    transfer_fee::{TransferFee, TransferFeeConfig},
+    BaseStateWithExtensions, ExtensionType, StateWithExtensions,
+};
+use squads::{Multisig, MultisigConfig, Proposal};
 use std::{
-    error::Error,
-    fs,
-    future::Future,
-    pin::Pin,
+    collections::HashMap,
+    fmt,
+    io::{self, IsTerminal},
+    process::ExitCode,
     str::FromStr,
+    sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
+use tracing::{debug, error, info, warn};
+
+const DEFAULT_AMOUNT: u64 = 100_000_000; // 0.1 SOL in lamports
+const DEFAULT_AIRDROP: u64 = 1_000_000_000; // 1 SOL in lamports
+
+// Transfers packed into one transaction when a single sender pays many recipients
+const FAN_OUT_BATCH_SIZE: usize = 10;
+// Placeholder endpoint for sign-only runs, which never contact the network
+const OFFLINE_RPC_URL: &str = "http://localhost:8899";
+// How long transfers in flight are waited for after Ctrl+C
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(60);
+// Where completed transfers are remembered unless `idempotency_store` says otherwise
+const DEFAULT_IDEMPOTENCY_STORE: &str = "sent_transfers.jsonl";
+// Times a transaction whose blockhash expired is rebuilt unless `max_rebuilds` says otherwise
+const DEFAULT_MAX_REBUILDS: u32 = 2;
+
+mod airdrop;
+#[cfg(feature = "api")]
+mod api;
+mod checkpoint;
+mod csv_input;
+mod floor;
+mod idempotency;
+mod inspect;
+mod offline;
+mod pay;
+mod preview;
+mod rent;
+mod report;
+mod shares;
+mod shutdown;
+mod squads;
+mod sweep;
+
+#[derive(Parser, Debug)]
+#[command(about = "Sends SOL or SPL tokens from every sender wallet to its paired recipient")]
+struct Args {
+    /// Path to the config file, YAML, TOML or JSON by its extension
+    #[arg(long, default_value = "config.yaml")]
+    config: String,
+    /// Profile from the `profiles` of the config to lay over its top-level settings
+    #[arg(long)]
+    profile: Option<String>,
+    /// Default amount to send per transfer, overrides `amount` from the config but not the
+    /// amounts given for individual recipients. Integers are lamports (or token base units),
+    /// decimals like `0.1` or `0.1 SOL` are whole SOL (or tokens), and `25%` is a share of
+    /// the sender's balance when sending
+    #[arg(long)]
+    amount: Option<Amount>,
+    /// Commitment level transfers are confirmed at, instead of `commitment` from the config
+    /// or `finalized`
+    #[arg(long)]
+    commitment: Option<CommitmentConfig>,
+    /// RPC endpoint to use instead of `rpc_url` and `rpc_urls` from the config
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// Public cluster to use instead of the endpoints from the config, `--rpc-url` taking
+    /// precedence
+    #[arg(long, value_enum)]
+    network: Option<Network>,
+    /// Output format
+    #[arg(long, value_enum, default_value_t)]
+    output: OutputFormat,
+    /// Explorer logged signatures and addresses link to, overrides `explorer` from the
+    /// config. JSON output and JSON logs carry no links
+    #[arg(long, value_enum)]
+    explorer: Option<Explorer>,
+    /// Read transfers from a `sender_key,recipient,amount,memo` CSV file instead of the
+    /// sender and recipient lists of the config, `-` for stdin
+    #[arg(long)]
+    csv: Option<String>,
+    /// Write every transfer result to this CSV file at the end of the run
+    #[arg(long)]
+    report: Option<String>,
+    /// Build and simulate every transfer without sending anything
+    #[arg(long, conflicts_with_all = ["sign_only", "broadcast"])]
+    dry_run: bool,
+    /// Sign every transfer without connecting to the network and write the signed
+    /// transactions to this file, to be sent later with `--broadcast`
+    #[arg(long, requires = "blockhash", conflicts_with = "broadcast")]
+    sign_only: Option<String>,
+    /// Blockhash the transactions are signed with in `--sign-only` mode. For senders with a
+    /// durable nonce this is the value stored in the nonce account
+    #[arg(long, requires = "sign_only")]
+    blockhash: Option<Hash>,
+    /// Fund every sender from the devnet or testnet faucet with `airdrop_amount` instead of
+    /// sending transfers
+    #[arg(long, conflicts_with_all = ["dry_run", "sign_only", "broadcast"])]
+    airdrop: bool,
+    /// Send the whole balance of every sender minus the fee instead of a fixed amount. For
+    /// token transfers the whole token balance is sent
+    #[arg(long, conflicts_with_all = ["amount", "sign_only", "broadcast"])]
+    sweep: bool,
+    /// Leave the rent-exempt minimum in every swept wallet instead of emptying it
+    #[arg(long, requires = "sweep")]
+    keep_rent_exempt: bool,
+    /// Raise transfers that would leave their recipient below the rent-exempt minimum so
+    /// it reaches it, instead of only warning about them
+    #[arg(long)]
+    top_up_rent_exempt: bool,
+    /// Divide the balance of every sender equally between its recipients, minus the fees,
+    /// ignoring configured amounts
+    #[arg(long, conflicts_with_all = ["amount", "sweep", "sign_only", "broadcast"])]
+    split: bool,
+    /// Add a unique Solana Pay reference key to every transfer, to find its transaction
+    /// later with `getSignaturesForAddress`
+    #[arg(long)]
+    reference: bool,
+    /// Send and confirm the signed transactions from a `--sign-only` file
+    #[arg(long, conflicts_with = "csv")]
+    broadcast: Option<String>,
+    /// Record the state of every transfer in this file as the run goes, so an interrupted
+    /// run can be picked up with `--resume`
+    #[arg(long, conflicts_with_all = ["dry_run", "sign_only", "broadcast", "airdrop"])]
+    checkpoint: Option<String>,
+    /// Continue the run recorded in `--checkpoint`, skipping the transfers it confirmed.
+    /// Transfers sent but not known to be confirmed are looked up first, and only sent
+    /// again once their transaction can no longer land
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+    /// Send transfers the idempotency store records as completed by an earlier run
+    #[arg(long)]
+    force: bool,
+    /// Send without showing the summary and asking to type `yes` first
+    #[arg(long, short)]
+    yes: bool,
+    /// Show a live dashboard of the run in the terminal instead of logging
+    #[arg(long, conflicts_with_all = ["output", "sign_only", "broadcast", "airdrop"])]
+    dashboard: bool,
+    #[command(flatten)]
+    log: LogArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Fetch a transaction and print its decoded instructions, the balances it changed,
+    /// its fee and its logs, e.g. to see why a transfer failed
+    Inspect { signature: Signature },
+    /// Print a Solana Pay URL asking for a transfer to a wallet of the config, in `mint`
+    /// when set, with a QR code of it to scan
+    Request(pay::RequestArgs),
+    /// Serve a REST API sending transfers from the senders of the config:
+    /// `GET /balances`, `POST /transfers` and `GET /jobs/:id` to follow a transfer job
+    #[cfg(feature = "api")]
+    Serve {
+        /// Address to listen on, a loopback one unless requests need a bearer token
+        /// (`api_token`)
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 struct YamlFile {
-    rpc_url: String,
-    sender_private_keys: Vec<Vec<u8>>,
-    recepient_pyblic_keys: Vec<String>,
+    #[serde(flatten)]
+    rpc: RpcEndpoints,
+    /// Commitment level of queries, confirmations and subscriptions
+    #[serde(default)]
+    commitment: Option<CommitmentLevel>,
+    #[serde(default)]
+    amount: Option<Amount>,
+    /// SPL token mint to transfer instead of SOL
+    #[serde(default)]
+    mint: Option<String>,
+    /// SOL requested per sender by `--airdrop`, 1 SOL by default
+    #[serde(default)]
+    airdrop_amount: Option<Amount>,
+    /// SPL Memo attached to every transfer without a memo of its own
+    #[serde(default)]
+    memo: Option<String>,
+    /// Decimals of `mint`, read from the chain when unset and checked against it
+    /// otherwise. Required for `--sign-only`
+    #[serde(default)]
+    mint_decimals: Option<u8>,
+    /// `mint` belongs to the Token-2022 program, for `--sign-only` which cannot look it up
+    #[serde(default)]
+    token_2022: bool,
+    /// Create the associated token account of recipients that have none, the sender paying
+    /// its rent, instead of failing the transfer
+    #[serde(default)]
+    create_recipient_ata: bool,
+    #[serde(default)]
+    sender_private_keys: Vec<KeySource>,
+    #[serde(default)]
+    recepient_pyblic_keys: Vec<RecipientEntry>,
+    /// Single recipient every sender pays, instead of `recepient_pyblic_keys`
+    #[serde(default)]
+    consolidation_address: Option<String>,
+    #[serde(flatten)]
+    compute_budget: ComputeBudgetConfig,
+    #[serde(flatten)]
+    transaction: TransactionConfig,
+    #[serde(default)]
+    retry: RetryConfig,
+    #[serde(flatten)]
+    timeouts: RpcTimeouts,
+    #[serde(default)]
+    send: SendConfig,
+    #[serde(flatten)]
+    telemetry: TelemetryConfig,
+    /// Client-side limit on RPC requests per second
+    #[serde(default)]
+    rate_limit: Option<RateLimitConfig>,
+    /// Durable nonce accounts used instead of a recent blockhash, per sender
+    #[serde(default)]
+    nonce_accounts: Vec<SenderNonce>,
+    /// Maximum number of transfers in flight at once, all of them when unset
+    #[serde(default)]
+    max_concurrency: Option<usize>,
+    /// Most transfers from one sender packed into a transaction, fewer when they would not
+    /// fit in a packet. 10 by default when one sender pays many recipients, 1 otherwise
+    #[serde(default)]
+    transfers_per_transaction: Option<usize>,
+    /// Squads multisigs whose vaults senders propose transfers from instead of sending
+    /// their own funds
+    #[serde(default)]
+    multisigs: Vec<SenderMultisig>,
+    /// SQLite database every transfer result is added to at the end of a run
+    #[serde(default)]
+    database_path: Option<String>,
+    /// Explorer logged signatures and addresses link to, Solscan by default
+    #[serde(default)]
+    explorer: Option<Explorer>,
+    /// Name of the payout, part of the idempotency key of every transfer. Change it to
+    /// send the same transfers again on purpose
+    #[serde(default)]
+    batch_id: String,
+    /// File completed transfers are remembered in, `sent_transfers.jsonl` by default
+    #[serde(default)]
+    idempotency_store: Option<String>,
+    /// Bearer token requests to `serve` must carry, required to listen on an address other
+    /// hosts can reach. `TASK2_API_TOKEN` takes precedence
+    #[serde(default)]
+    api_token: Option<String>,
+    /// Times a transaction that expired before landing is rebuilt with a fresh blockhash
+    /// and sent again, 2 by default
+    #[serde(default)]
+    max_rebuilds: Option<u32>,
+    /// Lamports every source keeps, e.g. to pay future fees. Transfers that would take it
+    /// lower are reduced, or skipped when nothing is left
+    #[serde(default)]
+    keep_minimum_lamports: Option<u64>,
+    #[serde(flatten)]
+    recipient_lists: RecipientListsConfig,
+    /// Labels of addresses such as senders, by address
+    #[serde(default)]
+    labels: Labels,
+    /// Webhooks told about failed transfers and large runs
+    #[serde(default)]
+    alerts: AlertConfig,
+}
+
+/// A recipient given either as a bare address or with its own amount, which takes
+/// precedence over the global one, and a label.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum RecipientEntry {
+    Address(String),
+    WithAmount {
+        address: String,
+        #[serde(default)]
+        amount: Option<Amount>,
+        #[serde(default)]
+        memo: Option<String>,
+        #[serde(default)]
+        label: Option<String>,
+    },
+}
+
+impl RecipientEntry {
+    fn address(&self) -> &str {
+        match self {
+            Self::Address(address) | Self::WithAmount { address, .. } => address,
+        }
+    }
+
+    fn amount(&self) -> Option<&Amount> {
+        match self {
+            Self::Address(_) => None,
+            Self::WithAmount { amount, .. } => amount.as_ref(),
+        }
+    }
+
+    fn memo(&self) -> Option<&String> {
+        match self {
+            Self::Address(_) => None,
+            Self::WithAmount { memo, .. } => memo.as_ref(),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        match self {
+            Self::Address(_) => None,
+            Self::WithAmount { label, .. } => label.as_deref(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SenderNonce {
+    /// Public key of the sender this nonce account belongs to
+    sender: String,
+    #[serde(flatten)]
+    nonce: NonceConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SenderMultisig {
+    /// Public key of the member that creates the proposals
+    sender: String,
+    #[serde(flatten)]
+    multisig: MultisigConfig,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenMint {
+    address: Pubkey,
+    decimals: u8,
+    /// The SPL Token or the Token-2022 program
+    program_id: Pubkey,
+    /// Transfer fee of a Token-2022 mint in the current epoch
+    transfer_fee: Option<TransferFee>,
+    /// Create the recipient's associated token account before transferring to it
+    create_recipient_ata: bool,
+    /// Size of a token account of the mint, whose rent opening a recipient's account costs
+    account_len: usize,
+}
+
+impl TokenMint {
+    // The associated token account of `owner` for this mint
+    fn token_account(&self, owner: &Pubkey) -> Pubkey {
+        get_associated_token_address_with_program_id(owner, &self.address, &self.program_id)
+    }
+
+    // Tokens the mint withholds from a transfer of `amount`, if it charges a transfer fee
+    fn fee(&self, amount: u64) -> Option<u64> {
+        self.transfer_fee
+            .and_then(|transfer_fee| transfer_fee.calculate_fee(amount))
+            .filter(|fee| *fee > 0)
+    }
 }
 
 #[derive(Debug)]
 struct Transfer {
+    /// Position in the config or CSV file, identifies the transfer in a checkpoint
+    id: usize,
     amount: u64,
-    sender_keypair: Keypair,
+    sender: Arc<dyn TxSigner>,
     recepient_public_key: Pubkey,
+    mint: Option<TokenMint>,
+    nonce: Option<DurableNonce>,
+    /// Multisig whose vault pays, the transfer becomes a proposal when set
+    multisig: Option<Multisig>,
+    /// Attached to the transaction as an SPL Memo
+    memo: Option<String>,
+    /// Solana Pay reference, a read-only account on the transfer instruction
+    reference: Option<Pubkey>,
+    /// Percentage of the source balance `amount` is set to right before sending
+    percent: Option<Amount>,
+    /// Why the transfer is not sent, when it was completed by an earlier run
+    skip: Option<SkipReason>,
 }
 
+impl Transfer {
+    // The account the funds leave from: the multisig vault, or the sender itself
+    fn source(&self) -> Pubkey {
+        match &self.multisig {
+            Some(multisig) => multisig.vault(),
+            None => self.sender.pubkey(),
+        }
+    }
+}
+
+#[derive(Serialize)]
 struct TransferResult {
     from: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_label: Option<String>,
     to: String,
-    signature: Signature,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_label: Option<String>,
+    amount: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    #[serde(serialize_with = "output::serialize_optional_display")]
+    signature: Option<Signature>,
+    /// Lamports of fee actually paid. The fee of a transaction packing several transfers
+    /// is divided between them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee: Option<u64>,
+    /// Tokens the Token-2022 mint withholds from the recipient as its transfer fee
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transfer_fee: Option<u64>,
+    /// Compute units consumed by the whole transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compute_units_consumed: Option<u64>,
+    /// Slot the transaction landed in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slot: Option<u64>,
+    /// Unix timestamp of the block the transaction landed in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    block_time: Option<i64>,
+    #[serde(
+        rename = "processing_time_ms",
+        serialize_with = "output::serialize_duration_ms"
+    )]
     processing_time: Duration,
+    #[serde(serialize_with = "output::serialize_status")]
     status: Option<transaction::Result<()>>,
+    /// Why the transfer could not be completed
+    #[serde(serialize_with = "output::serialize_optional_display")]
+    error: Option<TaskError>,
+    /// Set instead of the signature in dry-run mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    simulation: Option<Simulation>,
+    /// Set when the transfer was not attempted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<SkipReason>,
+    /// Earlier transactions of the transfer whose blockhash expired before they landed,
+    /// in the order they were sent
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    expired_signatures: Vec<String>,
+    /// Index of the multisig transaction proposed for the members to approve, set instead
+    /// of a transfer from the vault
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proposal_index: Option<u64>,
 }
 
-fn parse_yaml(fpath: &str) -> Result<YamlFile, Box<dyn Error>> {
-    let config = fs::read_to_string(fpath).expect("The config YAML file is missing");
-    let config_yaml: YamlFile =
-        serde_yaml::from_str::<YamlFile>(&config).expect("Incorrect YAML format");
-    for (i, item) in config_yaml.sender_private_keys.iter().enumerate() {
-        assert_eq!(
-            item.len(),
-            64,
-            "Private key number {} has lenght not equal to 64.",
-            i + 1
-        );
+impl TransferResult {
+    // A result for a transfer that has not been sent
+    fn new(transfer: &Transfer) -> Self {
+        TransferResult {
+            from: transfer.source().to_string(),
+            from_label: None,
+            to: transfer.recepient_public_key.to_string(),
+            to_label: None,
+            amount: transfer.amount,
+            memo: transfer.memo.clone(),
+            reference: transfer.reference.map(|reference| reference.to_string()),
+            signature: None,
+            fee: None,
+            transfer_fee: transfer.mint.and_then(|mint| mint.fee(transfer.amount)),
+            compute_units_consumed: None,
+            slot: None,
+            block_time: None,
+            processing_time: Duration::ZERO,
+            status: None,
+            error: None,
+            simulation: None,
+            skipped: None,
+            expired_signatures: Vec::new(),
+            proposal_index: None,
+        }
+    }
+
+    fn failed(transfer: &Transfer, error: TaskError) -> Self {
+        TransferResult {
+            error: Some(error),
+            ..Self::new(transfer)
+        }
+    }
+
+    fn skipped(transfer: &Transfer, reason: SkipReason) -> Self {
+        TransferResult {
+            skipped: Some(reason),
+            ..Self::new(transfer)
+        }
+    }
+
+    // A result for a transfer whose transaction was sent but not confirmed before the run
+    // stopped waiting, it may still land
+    fn unconfirmed(transfer: &Transfer, signature: Signature) -> Self {
+        TransferResult {
+            signature: Some(signature),
+            ..Self::new(transfer)
+        }
+    }
+
+    fn succeeded(&self) -> bool {
+        self.error.is_none() && matches!(self.status, Some(Ok(())))
+    }
+
+    // Whether the transaction was sent without its outcome being known
+    fn is_unconfirmed(&self) -> bool {
+        self.skipped.is_none()
+            && self.error.is_none()
+            && self.status.is_none()
+            && self.signature.is_some()
+    }
+
+    // Whether the funds actually moved, rather than a multisig transfer being proposed
+    fn transferred(&self) -> bool {
+        self.succeeded() && self.proposal_index.is_none()
+    }
+}
+
+/// Why a transfer was not attempted.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "reason")]
+enum SkipReason {
+    /// The sender cannot cover the amount and the fee. For token transfers the token and
+    /// the SOL balances are checked separately, in their own base units.
+    SkippedInsufficientFunds { balance: u64, required: u64 },
+    /// Nothing is left to send, e.g. a swept wallet that only holds the fee
+    SkippedZeroAmount,
+    /// Confirmed by the interrupted run being resumed
+    #[serde(rename = "SkippedAlreadyConfirmed")]
+    AlreadyConfirmed { signature: String },
+    /// Completed by an earlier run of the same batch, according to the idempotency store
+    #[serde(rename = "SkippedAlreadySent")]
+    AlreadySent { signature: String },
+    /// Not sent because the run was stopped with Ctrl+C
+    #[serde(rename = "SkippedInterrupted")]
+    Interrupted,
+    /// Sending would take the source below `keep_minimum_lamports`
+    FloorProtected { balance: u64, floor: u64 },
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::SkippedInsufficientFunds { balance, required } => write!(
+                f,
+                "insufficient funds, balance {balance} but {required} required"
+            ),
+            Self::SkippedZeroAmount => write!(f, "nothing to send"),
+            Self::AlreadyConfirmed { signature } => {
+                write!(f, "already confirmed in {signature}")
+            }
+            Self::AlreadySent { signature } => write!(
+                f,
+                "already sent in {signature} by an earlier run, pass --force to send it again"
+            ),
+            Self::Interrupted => write!(f, "interrupted before sending"),
+            Self::FloorProtected { balance, floor } => write!(
+                f,
+                "balance {balance} would drop below keep_minimum_lamports {floor}"
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Default)]
+struct TransferSummary {
+    transfers: usize,
+    succeeded: usize,
+    /// Multisig transfers proposed, not counted in `succeeded` or `total_amount`
+    proposed: usize,
+    failed: usize,
+    /// Sent before Ctrl+C but not confirmed in time, they may still land
+    unconfirmed: usize,
+    skipped: usize,
+    total_amount: u64,
+    total_fees: u64,
+    /// Processing times of the sent transfers, absent when nothing was sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency: Option<Latency>,
+}
+
+/// Distribution of processing times, in milliseconds.
+#[derive(Serialize)]
+struct Latency {
+    min_ms: u128,
+    p50_ms: u128,
+    p95_ms: u128,
+    max_ms: u128,
+}
+
+impl Latency {
+    fn from_durations(mut durations: Vec<Duration>) -> Option<Self> {
+        durations.sort();
+        // Nearest-rank percentile
+        let percentile = |p: usize| {
+            let rank = (durations.len() * p).div_ceil(100).max(1);
+            durations[rank - 1].as_millis()
+        };
+        Some(Latency {
+            min_ms: durations.first()?.as_millis(),
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            max_ms: durations.last()?.as_millis(),
+        })
+    }
+}
+
+// The part of a transaction fee charged to transfer `index` of the `count` packed into it,
+// the remainder going to the first one
+fn fee_share(fee: u64, count: usize, index: usize) -> u64 {
+    let count = count.max(1) as u64;
+    let share = fee / count;
+    if index == 0 {
+        share + fee % count
+    } else {
+        share
+    }
+}
+
+// Function for building the Solana Pay request of the `request` subcommand, paid to the
+// first sender unless another recipient is given
+async fn payment_request(
+    config_yaml: &YamlFile,
+    args: &pay::RequestArgs,
+    client: &RpcClient,
+) -> TaskResult<pay::PaymentRequest> {
+    let recipient = match (&args.recipient, config_yaml.sender_private_keys.first()) {
+        (Some(recipient), _) => Pubkey::from_str(recipient)
+            .map_err(|e| TaskError::KeyError(format!("recipient {recipient}: {e}")))?,
+        (None, Some(sender)) => sender
+            .signer()
+            .map_err(|e| TaskError::KeyError(format!("sender private key: {e}")))?
+            .pubkey(),
+        (None, None) => {
+            return Err(TaskError::ConfigError(
+                "no sender_private_keys to be paid, pass --recipient".to_string(),
+            ))
+        }
+    };
+    let (spl_token, decimals) = match (&config_yaml.mint, config_yaml.mint_decimals) {
+        (Some(mint), Some(decimals)) => (
+            Some(
+                Pubkey::from_str(mint)
+                    .map_err(|e| TaskError::KeyError(format!("mint {mint}: {e}")))?,
+            ),
+            decimals,
+        ),
+        (Some(mint), None) => {
+            let mint = fetch_mint(mint, client).await?;
+            (Some(mint.address), mint.decimals)
+        }
+        (None, _) => (None, 9),
+    };
+    let amount = match &args.amount {
+        Some(Amount::Percent(_)) => {
+            return Err(TaskError::ConfigError(
+                "a payment request cannot ask for a share of a balance".to_string(),
+            ))
+        }
+        Some(amount) => {
+            let units = amount
+                .to_units(spl_token.map(|_| decimals))
+                .map_err(TaskError::ConfigError)?;
+            Some(pay::format_units(units, decimals))
+        }
+        None => None,
+    };
+    Ok(pay::PaymentRequest {
+        recipient,
+        amount,
+        spl_token,
+        reference: args.reference.then(|| TxSigner::pubkey(&Keypair::new())),
+        label: args.label.clone(),
+        message: args.message.clone(),
+        memo: args.memo.clone(),
+    })
+}
+
+// Function for collecting the labels of the config with the ones of its recipients
+fn config_labels(config_yaml: &YamlFile) -> Labels {
+    let mut labels = config_yaml.labels.clone();
+    for entry in &config_yaml.recepient_pyblic_keys {
+        labels.insert(entry.address(), entry.label());
+    }
+    labels
+}
+
+fn parse_yaml(fpath: &str, profile: Option<&str>) -> TaskResult<YamlFile> {
+    let config_yaml: YamlFile = common::config::load(fpath, profile)?;
+    if config_yaml.consolidation_address.is_some() {
+        if !config_yaml.recepient_pyblic_keys.is_empty() {
+            return Err(TaskError::ConfigError(
+                "set either consolidation_address or recepient_pyblic_keys, not both".to_string(),
+            ));
+        }
+        return Ok(config_yaml);
+    }
+    // A single sender pays every recipient, see `is_fan_out`
+    if config_yaml.sender_private_keys.len() != config_yaml.recepient_pyblic_keys.len()
+        && config_yaml.sender_private_keys.len() != 1
+    {
+        return Err(TaskError::ConfigError(
+            "The numbers of sender and recepient wallets is not equal.".to_string(),
+        ));
     }
-    assert_eq!(
-        config_yaml.sender_private_keys.len(),
-        config_yaml.recepient_pyblic_keys.len(),
-        "The numbers of sender and recepint wallets is not equal."
-    );
 
     Ok(config_yaml)
 }
 
-fn form_transfers(config_yaml: &YamlFile, amount: u64) -> Result<Vec<Transfer>, Box<dyn Error>> {
-    let mut transfers: Vec<Transfer> = Vec::new();
-    for (send_priv_k, rec_pub_k) in config_yaml
-        .sender_private_keys
+// Function for fetching the decimals of the configured SPL Token or Token-2022 mint, with
+// the transfer fee of a Token-2022 mint in the current epoch
+async fn fetch_mint(mint: &str, client: &RpcClient) -> TaskResult<TokenMint> {
+    let address =
+        Pubkey::from_str(mint).map_err(|e| TaskError::KeyError(format!("mint {mint}: {e}")))?;
+    let account = client.get_account(&address).await?;
+    let not_a_mint = |e| TaskError::ConfigError(format!("{mint} is not an SPL token mint: {e}"));
+    if account.owner == spl_token::id() {
+        let state = spl_token::state::Mint::unpack(&account.data).map_err(not_a_mint)?;
+        return Ok(TokenMint {
+            address,
+            decimals: state.decimals,
+            program_id: spl_token::id(),
+            transfer_fee: None,
+            create_recipient_ata: false,
+            account_len: spl_token::state::Account::LEN,
+        });
+    }
+    if account.owner != spl_token_2022::id() {
+        return Err(TaskError::ConfigError(format!(
+            "{mint} is not an SPL token mint, it is owned by {}",
+            account.owner
+        )));
+    }
+    let state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&account.data)
+        .map_err(not_a_mint)?;
+    let transfer_fee = match state.get_extension::<TransferFeeConfig>() {
+        Ok(config) => {
+            let epoch = client.get_epoch_info().await?.epoch;
+            Some(*config.get_epoch_fee(epoch))
+        }
+        Err(_) => None,
+    };
+    // Accounts carry the extensions the mint requires, and associated token accounts
+    // always have an immutable owner
+    let mut extensions = ExtensionType::get_required_init_account_extensions(
+        &state.get_extension_types().map_err(not_a_mint)?,
+    );
+    extensions.push(ExtensionType::ImmutableOwner);
+    let account_len =
+        ExtensionType::try_calculate_account_len::<spl_token_2022::state::Account>(&extensions)
+            .map_err(not_a_mint)?;
+
+    Ok(TokenMint {
+        address,
+        decimals: state.base.decimals,
+        program_id: spl_token_2022::id(),
+        transfer_fee,
+        create_recipient_ata: false,
+        account_len,
+    })
+}
+
+// Function for finding the multisig a sender proposes transfers for, if any
+fn sender_multisig(config_yaml: &YamlFile, sender: &Pubkey) -> TaskResult<Option<Multisig>> {
+    let sender = sender.to_string();
+    config_yaml
+        .multisigs
         .iter()
-        .zip(config_yaml.recepient_pyblic_keys.iter())
-    {
-        let sender_keypair = Keypair::from_bytes(send_priv_k).expect("Invalid sender private key");
-        let recepient_public_key =
-            Pubkey::from_str(rec_pub_k).expect("Invalid recepient public key");
+        .find(|entry| entry.sender == sender)
+        .map(|entry| Multisig::from_config(&entry.multisig))
+        .transpose()
+}
+
+// Function for finding the durable nonce configured for a sender, if any
+fn sender_nonce(config_yaml: &YamlFile, sender: &Pubkey) -> TaskResult<Option<DurableNonce>> {
+    let sender = sender.to_string();
+    config_yaml
+        .nonce_accounts
+        .iter()
+        .find(|entry| entry.sender == sender)
+        .map(|entry| DurableNonce::from_config(&entry.nonce))
+        .transpose()
+}
+
+// Converts an amount from the config to lamports, or to base units of the mint
+fn amount_units(amount: &Amount, mint: Option<TokenMint>) -> Result<u64, String> {
+    amount.to_units(mint.map(|mint| mint.decimals))
+}
+
+// The amount of a transfer, and the percentage it is worked out from at send time if the
+// amount is a share of the balance
+fn transfer_amount(
+    amount: &Amount,
+    mint: Option<TokenMint>,
+) -> Result<(u64, Option<Amount>), String> {
+    match amount {
+        Amount::Percent(_) => Ok((0, Some(amount.clone()))),
+        _ => Ok((amount_units(amount, mint)?, None)),
+    }
+}
+
+// One sender key and several recipients: the sender pays all of them, packing several
+// transfers into each transaction
+fn is_fan_out(config_yaml: &YamlFile) -> bool {
+    config_yaml.sender_private_keys.len() == 1 && config_yaml.recepient_pyblic_keys.len() > 1
+}
+
+fn form_transfers(
+    config_yaml: &YamlFile,
+    default_amount: &Amount,
+    mint: Option<TokenMint>,
+) -> TaskResult<Vec<Transfer>> {
+    let mut senders = Vec::with_capacity(config_yaml.sender_private_keys.len());
+    for (i, send_priv_k) in config_yaml.sender_private_keys.iter().enumerate() {
+        senders.push(send_priv_k.signer().map_err(|e| {
+            TaskError::KeyError(format!("sender private key number {}: {e}", i + 1))
+        })?);
+    }
+
+    // With a consolidation address every sender pays that one address
+    let consolidation: Vec<RecipientEntry>;
+    let recipients = match &config_yaml.consolidation_address {
+        Some(address) => {
+            consolidation = vec![RecipientEntry::Address(address.clone()); senders.len()];
+            &consolidation
+        }
+        None => &config_yaml.recepient_pyblic_keys,
+    };
+
+    let mut transfers: Vec<Transfer> = Vec::new();
+    for (i, recipient) in recipients.iter().enumerate() {
+        let sender = if is_fan_out(config_yaml) {
+            senders[0].clone()
+        } else {
+            senders[i].clone()
+        };
+        let rec_pub_k = recipient.address();
+        let recepient_public_key = Pubkey::from_str(rec_pub_k)
+            .map_err(|e| TaskError::KeyError(format!("recepient public key {rec_pub_k}: {e}")))?;
+        let nonce = sender_nonce(config_yaml, &sender.pubkey())?;
+        let multisig = sender_multisig(config_yaml, &sender.pubkey())?;
+        let (amount, percent) = transfer_amount(recipient.amount().unwrap_or(default_amount), mint)
+            .map_err(|e| TaskError::ConfigError(format!("amount for {rec_pub_k}: {e}")))?;
         transfers.push(Transfer {
+            id: i,
             amount,
-            sender_keypair,
+            sender,
             recepient_public_key,
+            mint,
+            nonce,
+            multisig,
+            memo: recipient.memo().or(config_yaml.memo.as_ref()).cloned(),
+            reference: None,
+            percent,
+            skip: None,
         });
     }
 
     Ok(transfers)
 }
 
-async fn make_transfer(
-    transfer: &Transfer,
+// Builds a SystemProgram transfer, or a `transfer_checked` between the associated token
+// accounts of the sender and recipient when a mint is configured
+fn transfer_instruction(transfer: &Transfer) -> TaskResult<Instruction> {
+    let sender = transfer.source();
+    let mut instruction = match transfer.mint {
+        // The fee is stated so the transfer fails rather than withholding a different one
+        Some(mint) => match mint.fee(transfer.amount) {
+            Some(fee) => {
+                spl_token_2022::extension::transfer_fee::instruction::transfer_checked_with_fee(
+                    &mint.program_id,
+                    &mint.token_account(&sender),
+                    &mint.address,
+                    &mint.token_account(&transfer.recepient_public_key),
+                    &sender,
+                    &[],
+                    transfer.amount,
+                    mint.decimals,
+                    fee,
+                )
+            }
+            None => spl_token_2022::instruction::transfer_checked(
+                &mint.program_id,
+                &mint.token_account(&sender),
+                &mint.address,
+                &mint.token_account(&transfer.recepient_public_key),
+                &sender,
+                &[],
+                transfer.amount,
+                mint.decimals,
+            ),
+        }
+        .map_err(|e| TaskError::BuildError(format!("token transfer: {e}")))?,
+        None => {
+            system_instruction::transfer(&sender, &transfer.recepient_public_key, transfer.amount)
+        }
+    };
+    if let Some(reference) = transfer.reference {
+        instruction
+            .accounts
+            .push(AccountMeta::new_readonly(reference, false));
+    }
+    Ok(instruction)
+}
+
+// Checks that the sender can pay for the batch, returning why it must be skipped if not.
+// Besides the fee the sender pays the Jito tip, the rent of the recipient token accounts
+// it opens and that of the accounts of a multisig proposal
+async fn check_funds(
+    batch: &[Transfer],
     client: &RpcClient,
-) -> Result<TransferResult, Box<dyn Error>> {
-    let latest_blockhash = client
-        .get_latest_blockhash()
-        .await
-        .expect("Failed to get latest blockhash");
-
-    let tx: Transaction = system_transaction::transfer(
-        &transfer.sender_keypair,
-        &transfer.recepient_public_key,
-        transfer.amount,
-        latest_blockhash,
-    );
+    builder: &TransactionBuilder,
+    tx: &VersionedTransaction,
+    proposal: Option<Proposal>,
+) -> TaskResult<Option<SkipReason>> {
+    let transfer = &batch[0];
+    let amount = batch.iter().fold(0u64, |total, transfer| {
+        total.saturating_add(transfer.amount)
+    });
+    let sender = transfer.sender.pubkey();
+    let source = transfer.source();
+    let mut costs = common::transaction::fee_for(client, tx)
+        .await?
+        .saturating_add(builder.tip_lamports())
+        .saturating_add(recipient_account_rent(batch, client).await?);
+    for size in proposal
+        .map(|proposal| proposal.account_sizes)
+        .unwrap_or_default()
+    {
+        let rent = client.get_minimum_balance_for_rent_exemption(size).await?;
+        costs = costs.saturating_add(rent);
+    }
+    let balance = client.get_balance(&sender).await?;
+    let required = match transfer.mint {
+        Some(mint) => {
+            let token_account = mint.token_account(&source);
+            let token_balance = token_balance(client, &token_account).await?;
+            if token_balance < amount {
+                return Ok(Some(SkipReason::SkippedInsufficientFunds {
+                    balance: token_balance,
+                    required: amount,
+                }));
+            }
+            costs
+        }
+        // A multisig vault pays the amount, the proposing member only the fee
+        None if source != sender => {
+            let vault_balance = client.get_balance(&source).await?;
+            if vault_balance < amount {
+                return Ok(Some(SkipReason::SkippedInsufficientFunds {
+                    balance: vault_balance,
+                    required: amount,
+                }));
+            }
+            costs
+        }
+        None => amount.saturating_add(costs),
+    };
+
+    Ok((balance < required).then_some(SkipReason::SkippedInsufficientFunds { balance, required }))
+}
+
+// Tokens held by `token_account`, 0 when it does not exist
+async fn token_balance(client: &RpcClient, token_account: &Pubkey) -> TaskResult<u64> {
+    let account = client
+        .get_account_with_commitment(token_account, client.commitment())
+        .await?
+        .value;
+    let Some(account) = account else {
+        return Ok(0);
+    };
+    let state = StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+        .map_err(|e| {
+            TaskError::ConfigError(format!("{token_account} is not a token account: {e}"))
+        })?;
+    Ok(state.base.amount)
+}
+
+// Rent of the recipient token accounts a batch opens, those that do not exist yet
+async fn recipient_account_rent(batch: &[Transfer], client: &RpcClient) -> TaskResult<u64> {
+    let mut accounts: Vec<(Pubkey, usize)> = Vec::new();
+    for transfer in batch {
+        if let Some(mint) = transfer.mint.filter(|mint| mint.create_recipient_ata) {
+            let account = mint.token_account(&transfer.recepient_public_key);
+            if !accounts.iter().any(|(address, _)| *address == account) {
+                accounts.push((account, mint.account_len));
+            }
+        }
+    }
+    if accounts.is_empty() {
+        return Ok(0);
+    }
+    let addresses: Vec<Pubkey> = accounts.iter().map(|(address, _)| *address).collect();
+    let existing = client.get_multiple_accounts(&addresses).await?;
+    let mut rent = 0u64;
+    for ((_, len), account) in accounts.iter().zip(existing) {
+        if account.is_none() {
+            let minimum = client.get_minimum_balance_for_rent_exemption(*len).await?;
+            rent = rent.saturating_add(minimum);
+        }
+    }
+    Ok(rent)
+}
+
+// Function for building the instructions of a batch: every transfer and its memo, wrapped
+// into a proposal for multisig transfers
+async fn transfer_instructions(
+    batch: &[Transfer],
+    client: &RpcClient,
+) -> TaskResult<Vec<Instruction>> {
+    Ok(proposed_instructions(batch, client).await?.0)
+}
+
+// Like `transfer_instructions`, with the multisig transaction proposed
+async fn proposed_instructions(
+    batch: &[Transfer],
+    client: &RpcClient,
+) -> TaskResult<(Vec<Instruction>, Option<Proposal>)> {
+    let mut instructions = Vec::new();
+    let mut create_accounts: Vec<Instruction> = Vec::new();
+    for transfer in batch {
+        if let Some(mint) = transfer.mint.filter(|mint| mint.create_recipient_ata) {
+            let create = create_associated_token_account_idempotent(
+                &transfer.sender.pubkey(),
+                &transfer.recepient_public_key,
+                &mint.address,
+                &mint.program_id,
+            );
+            if !create_accounts.contains(&create) {
+                create_accounts.push(create);
+            }
+        }
+        instructions.push(transfer_instruction(transfer)?);
+        if let Some(memo) = &transfer.memo {
+            instructions.push(spl_memo::build_memo(memo.as_bytes(), &[]));
+        }
+    }
+    // The memo goes into the vault transaction, so it is recorded along with the payout
+    let mut proposal = None;
+    if let Some(multisig) = &batch[0].multisig {
+        let (wrapped, proposed) = multisig
+            .propose(client, &batch[0].sender.pubkey(), &instructions)
+            .await?;
+        instructions = wrapped;
+        proposal = Some(proposed);
+    }
+    // Accounts are created by the sender ahead of the transfers, also for a multisig
+    create_accounts.append(&mut instructions);
+    Ok((create_accounts, proposal))
+}
+
+// Function for building and signing the transaction of a batch, with the multisig
+// transaction it proposes
+async fn build_transaction(
+    batch: &[Transfer],
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+) -> TaskResult<(VersionedTransaction, Option<Proposal>)> {
+    let sender = &batch[0].sender;
+    let (instructions, proposal) = proposed_instructions(batch, client).await?;
+    let tx = builder
+        .build(
+            client,
+            instructions,
+            &sender.pubkey(),
+            &[sender.as_ref()],
+            batch[0].nonce.as_ref(),
+        )
+        .await?;
+    Ok((tx, proposal))
+}
+
+// Splits a batch whose transaction would exceed the packet size in halves, until every part
+// fits. Sizes are checked before signing, so signers are not asked twice
+async fn fit_packet<'a>(
+    batch: &'a [Transfer],
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+) -> TaskResult<Vec<&'a [Transfer]>> {
+    let mut parts = Vec::new();
+    let mut pending = vec![batch];
+    while let Some(part) = pending.pop() {
+        if part.len() == 1 {
+            parts.push(part);
+            continue;
+        }
+        let instructions = transfer_instructions(part, client).await?;
+        let size = builder
+            .size(
+                client,
+                instructions,
+                &part[0].sender.pubkey(),
+                part[0].nonce.as_ref(),
+            )
+            .await?;
+        if size <= PACKET_DATA_SIZE {
+            parts.push(part);
+        } else {
+            let (first, second) = part.split_at(part.len() / 2);
+            pending.push(second);
+            pending.push(first);
+        }
+    }
+    Ok(parts)
+}
+
+// Sends the transfers of one batch in a single transaction. Every transfer of the batch gets
+// its own result, they all share the signature and status of the transaction. The
+// signature of every transaction is put in `sent` before it is sent.
+#[tracing::instrument(skip_all, fields(sender = %batch[0].sender.pubkey(), transfers = batch.len()))]
+async fn make_batch(
+    batch: &[Transfer],
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+    options: &SendOptions<'_>,
+    dashboard: Option<&Dashboard>,
+    sent: &Mutex<Option<Signature>>,
+) -> TaskResult<Vec<TransferResult>> {
+    let (mut tx, mut proposal) = build_transaction(batch, client, builder).await?;
+
+    if let Some(reason) = check_funds(batch, client, builder, &tx, proposal).await? {
+        return Ok(batch
+            .iter()
+            .map(|transfer| TransferResult::skipped(transfer, reason.clone()))
+            .collect());
+    }
+
+    if options.dry_run {
+        let simulation = common::transaction::simulate(client, &tx).await?;
+        return Ok(batch
+            .iter()
+            .map(|transfer| TransferResult {
+                status: Some(simulation.status()),
+                simulation: Some(simulation.clone()),
+                ..TransferResult::new(transfer)
+            })
+            .collect());
+    }
+
+    if let Some(dashboard) = dashboard {
+        for transfer in batch {
+            let (from, to) = (transfer.source(), transfer.recepient_public_key);
+            dashboard.sending(&from.to_string(), &to.to_string(), transfer.amount);
+        }
+    }
 
     // Measure the time before sending the transaction
     let start_time = Instant::now();
 
-    // Send the transaction
-    let signature = client
-        .send_and_confirm_transaction(&tx)
-        .await
-        .expect("Failed to send transaction");
+    // Send the transaction, again with a fresh blockhash every time it expires
+    let (send, timeout) = (options.send, options.confirmation_timeout);
+    let mut expired_signatures = Vec::new();
+    let signature = loop {
+        // Record the signature first, so a resumed run can tell whether the transaction landed
+        let blockhash = tx.message.recent_blockhash();
+        if let Some(checkpoint) = options.checkpoint {
+            for transfer in batch {
+                let signature = Some(&tx.signatures[0]);
+                checkpoint.record(transfer, TransferState::Sent, signature, Some(blockhash))?;
+            }
+        }
+        if let Some(idempotency) = options.idempotency {
+            for transfer in batch {
+                let signature = &tx.signatures[0];
+                idempotency.record(transfer, TransferState::Sent, signature, Some(blockhash))?;
+            }
+        }
+        *sent.lock().expect("sent signature lock poisoned") = Some(tx.signatures[0]);
+        match common::transaction::send_and_confirm(client, &tx, send, timeout).await {
+            Err(TaskError::ConfirmationTimeout(signature) | TaskError::Expired(signature))
+                if expired_signatures.len() < options.max_rebuilds as usize
+                    && expired(client, &tx).await? =>
+            {
+                warn!(%signature, "transaction expired, rebuilding it with a fresh blockhash");
+                expired_signatures.push(signature.to_string());
+                (tx, proposal) = build_transaction(batch, client, builder).await?;
+            }
+            result => break result?,
+        }
+    };
 
     // Measure the time after the transaction is sent
     let end_time = Instant::now();
     let duration = end_time.duration_since(start_time);
 
     // Get transaction processing stats
-    let tx_status = client
-        .get_signature_status(&signature)
-        .await
-        .expect("Failed to get transaction status");
-
-    Ok(TransferResult {
-        from: transfer.sender_keypair.pubkey().to_string(),
-        to: transfer.recepient_public_key.to_string(),
-        signature: signature,
-        processing_time: duration,
-        status: tx_status,
-    })
+    let tx_status = client.get_signature_status(&signature).await?;
+    // The transfer went through, details that cannot be looked up are left out
+    let landed = common::transaction::landed(client, &signature).await.ok();
+
+    Ok(batch
+        .iter()
+        .enumerate()
+        .map(|(i, transfer)| TransferResult {
+            signature: Some(signature),
+            fee: landed
+                .and_then(|landed| landed.fee)
+                .map(|fee| fee_share(fee, batch.len(), i)),
+            compute_units_consumed: landed.and_then(|landed| landed.compute_units_consumed),
+            slot: landed.map(|landed| landed.slot),
+            block_time: landed.and_then(|landed| landed.block_time),
+            processing_time: duration,
+            status: tx_status.clone(),
+            expired_signatures: expired_signatures.clone(),
+            proposal_index: proposal.map(|proposal| proposal.transaction_index),
+            ..TransferResult::new(transfer)
+        })
+        .collect())
+}
+
+// Whether a transaction that was not confirmed can no longer land, so building it again
+// cannot pay twice. Transactions bound to a durable nonce never expire
+async fn expired(client: &RpcClient, tx: &VersionedTransaction) -> TaskResult<bool> {
+    if tx.uses_durable_nonce()
+        || client
+            .is_blockhash_valid(tx.message.recent_blockhash(), CommitmentConfig::processed())
+            .await?
+    {
+        return Ok(false);
+    }
+    // Looked up at any commitment, one that landed but is not final is not built again
+    Ok(
+        common::transaction::signature_status(client, &tx.signatures[0])
+            .await?
+            .is_none(),
+    )
 }
 
+// Splits the transfers into batches sent in one transaction each: runs of consecutive
+// transfers from the same sender, at most `batch_size` long. Transfers with nothing to send
+// or already confirmed are left out and skipped
+fn batches(transfers: &[Transfer], batch_size: usize) -> (Vec<&[Transfer]>, Vec<&Transfer>) {
+    let mut batches: Vec<&[Transfer]> = Vec::new();
+    let mut empty = Vec::new();
+    let mut start = 0;
+    for (i, transfer) in transfers.iter().enumerate() {
+        if transfer.amount == 0 || transfer.skip.is_some() {
+            if start < i {
+                batches.push(&transfers[start..i]);
+            }
+            empty.push(transfer);
+            start = i + 1;
+            continue;
+        }
+        let same_sender = transfers[start].sender.pubkey() == transfer.sender.pubkey();
+        if start < i && (!same_sender || i - start == batch_size) {
+            batches.push(&transfers[start..i]);
+            start = i;
+        }
+    }
+    if start < transfers.len() {
+        batches.push(&transfers[start..]);
+    }
+    (batches, empty)
+}
+
+/// How results are shown: on the dashboard, or printed to stdout in `output` format with a
+/// progress bar on stderr when it is a terminal and per-transfer logs are not asked for
+/// with `-v`.
+#[derive(Debug, Clone)]
+struct Printer {
+    output: OutputFormat,
+    labels: Labels,
+    progress: bool,
+    dashboard: Option<Dashboard>,
+    explorer: Option<ExplorerLinks>,
+}
+
+impl Printer {
+    fn new(args: &Args, labels: Labels) -> Self {
+        Printer {
+            output: args.output,
+            labels,
+            progress: io::stderr().is_terminal()
+                && args.log.verbose == 0
+                && !args.log.quiet
+                && !args.dashboard,
+            dashboard: None,
+            explorer: None,
+        }
+    }
+
+    // Shows the outcome of a transfer on the dashboard
+    fn show(&self, result: &TransferResult) {
+        let Some(dashboard) = &self.dashboard else {
+            return;
+        };
+        let from = &labels::labeled(&result.from, result.from_label.as_deref());
+        let to = &labels::labeled(&result.to, result.to_label.as_deref());
+        match (
+            &result.skipped,
+            &result.error,
+            &result.status,
+            &result.signature,
+        ) {
+            (Some(reason), _, _, _) => dashboard.failed(from, to, &format!("skipped: {reason}")),
+            (None, Some(e), _, _) => dashboard.failed(from, to, &e.to_string()),
+            (None, None, Some(Err(e)), _) => dashboard.failed(from, to, &e.to_string()),
+            (None, None, Some(Ok(())), Some(_)) if result.proposal_index.is_some() => {
+                let proposal = format!("proposed #{}", result.proposal_index.unwrap_or_default());
+                dashboard.confirmed(from, to, result.amount, &proposal, result.processing_time)
+            }
+            (None, None, Some(Ok(())), Some(signature)) => dashboard.confirmed(
+                from,
+                to,
+                result.amount,
+                &signature.to_string(),
+                result.processing_time,
+            ),
+            (None, None, Some(Ok(())), None) => {
+                dashboard.confirmed(from, to, result.amount, "simulated", Duration::ZERO)
+            }
+            (None, None, None, Some(_)) => dashboard.failed(from, to, "sent, unconfirmed"),
+            (None, None, None, None) => dashboard.failed(from, to, "status unknown"),
+        }
+    }
+
+    fn progress_bar(&self, total: usize) -> Option<ProgressBar> {
+        if !self.progress {
+            return None;
+        }
+        let bar = ProgressBar::new(total as u64);
+        let style = ProgressStyle::with_template("{bar:40} {pos}/{len} {msg} ETA {eta}")
+            .expect("valid progress bar template");
+        bar.set_style(style);
+        Some(bar)
+    }
+}
+
+/// How transfers are packed into transactions and sent, and where their outcomes are
+/// recorded.
+#[derive(Debug, Clone, Copy)]
+struct SendOptions<'a> {
+    /// Simulate the transactions instead of sending them
+    dry_run: bool,
+    send: &'a SendConfig,
+    /// Longest wait for the confirmation of a transaction
+    confirmation_timeout: Option<Duration>,
+    /// Most transactions in flight at once
+    max_concurrency: usize,
+    /// Most transfers packed into one transaction
+    batch_size: usize,
+    /// Times an expired transaction is rebuilt and sent again
+    max_rebuilds: u32,
+    checkpoint: Option<&'a Checkpoint>,
+    /// Left out of dry runs, which complete nothing
+    idempotency: Option<&'a IdempotencyStore>,
+    shutdown: &'a Shutdown,
+}
+
+// Records the outcome of every transfer of a batch in the checkpoint and the idempotency
+// store. Skipped transfers stay pending, so a resumed run tries them again, and unconfirmed
+// ones stay sent, so it checks whether they landed first
+fn record_results(options: &SendOptions, batch: &[Transfer], results: &[TransferResult]) {
+    for (transfer, result) in batch.iter().zip(results) {
+        let state = if result.skipped.is_some() || result.is_unconfirmed() {
+            continue;
+        } else if result.succeeded() {
+            TransferState::Confirmed
+        } else {
+            TransferState::Failed
+        };
+        let signature = result.signature.as_ref();
+        if let Some(checkpoint) = options.checkpoint {
+            if let Err(e) = checkpoint.record(transfer, state, signature, None) {
+                warn!(error = %e, "cannot update the checkpoint");
+            }
+        }
+        if let (Some(idempotency), Some(signature), TransferState::Confirmed) =
+            (options.idempotency, signature, state)
+        {
+            if let Err(e) = idempotency.record(transfer, state, signature, None) {
+                warn!(error = %e, "cannot update the idempotency store");
+            }
+        }
+    }
+}
+
+// Sends the batches concurrently, at most `max_concurrency` at a time. A failed batch
+// does not stop the run, its error is recorded in the results of its transfers instead.
+// After Ctrl+C no more batches are started, and the ones in flight are given
+// `DRAIN_TIMEOUT` to be confirmed. Those still unconfirmed then are reported with the
+// signature they were last sent with.
 async fn make_transfers(
-    transfers: &Vec<Transfer>,
+    transfers: &[Transfer],
     client: &RpcClient,
-) -> Result<(), Box<dyn Error>> {
-    let mut tasks: FuturesUnordered<_> = FuturesUnordered::<
-        Pin<Box<dyn Future<Output = Result<TransferResult, Box<dyn Error>>>>>,
-    >::new();
-    for transfer in transfers {
-        tasks.push(Box::pin(make_transfer(transfer, &client)));
-    }
-
-    while let Some(result) = tasks.next().await {
-        let result = result?;
-
-        println!("{} -> {}", result.from, result.to);
-        println!("Signature {}", result.signature);
-        println!("Processing time {:?}", result.processing_time);
-        match result.status {
-            Some(status_result) => match status_result {
-                Ok(()) => println!("Transaction status is OK"),
-                Err(e) => println!("Trasaction status got error: {}", e),
+    builder: &TransactionBuilder,
+    printer: &Printer,
+    options: &SendOptions<'_>,
+) -> TaskResult<Vec<TransferResult>> {
+    let (batches, empty) = batches(transfers, options.batch_size);
+    let skipped = empty.into_iter().map(|transfer| {
+        let reason = transfer
+            .skip
+            .clone()
+            .unwrap_or(SkipReason::SkippedZeroAmount);
+        TransferResult::skipped(transfer, reason)
+    });
+    let failed = |batch: &[Transfer], e: TaskError| -> Vec<TransferResult> {
+        batch
+            .iter()
+            .map(|transfer| TransferResult::failed(transfer, e.duplicate()))
+            .collect()
+    };
+    let interrupted = |batch: &[Transfer]| -> Vec<TransferResult> {
+        batch
+            .iter()
+            .map(|transfer| TransferResult::skipped(transfer, SkipReason::Interrupted))
+            .collect()
+    };
+    // A proposal takes the next transaction index of its multisig, so proposals for one
+    // multisig are created one at a time
+    let proposing: HashMap<Pubkey, tokio::sync::Mutex<()>> = transfers
+        .iter()
+        .filter_map(|transfer| transfer.multisig)
+        .map(|multisig| (multisig.address, tokio::sync::Mutex::new(())))
+        .collect();
+    let proposing = &proposing;
+    let tasks = stream::iter(batches)
+        .map(|batch| async move {
+            if options.shutdown.requested() {
+                return interrupted(batch);
+            }
+            let parts = match fit_packet(batch, client, builder).await {
+                Ok(parts) => parts,
+                Err(e) => return failed(batch, e),
+            };
+            let mut results = Vec::with_capacity(batch.len());
+            for part in parts {
+                if options.shutdown.requested() {
+                    results.extend(interrupted(part));
+                    continue;
+                }
+                let dashboard = printer.dashboard.as_ref();
+                let _proposing = match &part[0].multisig {
+                    Some(multisig) => Some(proposing[&multisig.address].lock().await),
+                    None => None,
+                };
+                let sent = Mutex::new(None);
+                let part_results = tokio::select! {
+                    result = make_batch(part, client, builder, options, dashboard, &sent) => {
+                        result.unwrap_or_else(|e| failed(part, e))
+                    }
+                    () = options.shutdown.deadline() => {
+                        match *sent.lock().expect("sent signature lock poisoned") {
+                            Some(signature) => part
+                                .iter()
+                                .map(|transfer| TransferResult::unconfirmed(transfer, signature))
+                                .collect(),
+                            None => failed(part, TaskError::Interrupted),
+                        }
+                    }
+                };
+                record_results(options, part, &part_results);
+                results.extend(part_results);
+            }
+            results
+        })
+        .buffer_unordered(options.max_concurrency)
+        .flat_map(stream::iter);
+    let tasks = stream::iter(skipped).chain(tasks);
+    print_results(tasks, transfers.len(), printer).await
+}
+
+// Prints every result of the `total` expected as it arrives, followed by a summary
+async fn print_results(
+    mut tasks: impl Stream<Item = TransferResult> + Unpin,
+    total: usize,
+    printer: &Printer,
+) -> TaskResult<Vec<TransferResult>> {
+    let progress = printer.progress_bar(total);
+    // Output is written with the bar hidden, so lines do not mix with it
+    let suspend = |print: &mut dyn FnMut() -> TaskResult<()>| match &progress {
+        Some(bar) => bar.suspend(print),
+        None => print(),
+    };
+    let mut results = Vec::new();
+    let mut summary = TransferSummary::default();
+    let mut durations = Vec::new();
+    while let Some(mut result) = tasks.next().await {
+        result.from_label = printer.labels.get(&result.from).map(str::to_string);
+        result.to_label = printer.labels.get(&result.to).map(str::to_string);
+        summary.transfers += 1;
+        if result.skipped.is_some() {
+            summary.skipped += 1;
+        } else if result.succeeded() && result.proposal_index.is_some() {
+            summary.proposed += 1;
+            summary.total_fees += result.fee.unwrap_or_default();
+        } else if result.succeeded() {
+            summary.succeeded += 1;
+            summary.total_amount += result.amount;
+            summary.total_fees += result.fee.unwrap_or_default();
+        } else if result.is_unconfirmed() {
+            summary.unconfirmed += 1;
+        } else {
+            summary.failed += 1;
+        }
+        if result.signature.is_some() {
+            durations.push(result.processing_time);
+        }
+        if let Some(bar) = &progress {
+            bar.inc(1);
+            let ok = summary.succeeded + summary.proposed;
+            let finished = ok + summary.failed;
+            bar.set_message(format!(
+                "{} ok, {} failed, {}% success",
+                ok,
+                summary.failed,
+                (ok * 100).checked_div(finished).unwrap_or(100)
+            ));
+        }
+        printer.show(&result);
+        suspend(&mut || match printer.output {
+            OutputFormat::Json => output::print_json(&result),
+            // Behind a progress bar only the transfers that went wrong are logged
+            OutputFormat::Text if progress.is_some() && result.succeeded() => Ok(()),
+            OutputFormat::Text => {
+                log_result(&result, printer.explorer.as_ref());
+                Ok(())
+            }
+        })?;
+        results.push(result);
+    }
+    if let Some(bar) = &progress {
+        bar.finish_and_clear();
+    }
+
+    summary.latency = Latency::from_durations(durations);
+    if printer.output == OutputFormat::Json {
+        output::print_summary(&summary)?;
+        return Ok(results);
+    }
+    info!(
+        transfers = summary.transfers,
+        succeeded = summary.succeeded,
+        proposed = summary.proposed,
+        failed = summary.failed,
+        unconfirmed = summary.unconfirmed,
+        skipped = summary.skipped,
+        total_amount = summary.total_amount,
+        total_fees = summary.total_fees,
+        "run finished"
+    );
+    if let Some(latency) = &summary.latency {
+        info!(
+            min_ms = latency.min_ms as u64,
+            p50_ms = latency.p50_ms as u64,
+            p95_ms = latency.p95_ms as u64,
+            max_ms = latency.max_ms as u64,
+            "processing time"
+        );
+    }
+    Ok(results)
+}
+
+// Posts the alerts a finished run calls for: one for its failed transfers, and one for the
+// SOL it sent when that is over the threshold. Token amounts and proposals are not counted
+async fn alert_results(notifier: &Notifier, results: &[TransferResult], sol: bool) {
+    let failures: Vec<FailedTransfer> = results
+        .iter()
+        .filter(|result| result.skipped.is_none() && !result.succeeded())
+        .map(|result| FailedTransfer {
+            from: labels::labeled(&result.from, result.from_label.as_deref()),
+            to: labels::labeled(&result.to, result.to_label.as_deref()),
+            amount: result.amount,
+            error: match (&result.error, &result.status) {
+                (Some(e), _) => e.to_string(),
+                (None, Some(Err(e))) => e.to_string(),
+                (None, _) if result.is_unconfirmed() => "sent, unconfirmed".to_string(),
+                (None, _) => "status unknown".to_string(),
             },
-            None => println!("Transaction has None status."),
+        })
+        .collect();
+    if !failures.is_empty() {
+        notifier.notify(&Alert::transfers_failed(failures)).await;
+    }
+    let sent: u64 = results
+        .iter()
+        .filter(|result| result.transferred())
+        .map(|result| result.amount)
+        .sum();
+    if let Some(alert) = notifier.large_run(sent).filter(|_| sol) {
+        notifier.notify(&alert).await;
+    }
+}
+
+// Logs the outcome of a transfer in text output mode, with the details at debug level
+fn log_result(result: &TransferResult, explorer: Option<&ExplorerLinks>) {
+    let from = labels::labeled(&result.from, result.from_label.as_deref());
+    let to = labels::labeled(&result.to, result.to_label.as_deref());
+    let signature = result.signature.map(|signature| signature.to_string());
+    let url = explorer
+        .zip(signature.as_deref())
+        .map(|(links, signature)| links.transaction(signature));
+    match (&result.error, &result.status, &result.skipped) {
+        (_, _, Some(reason)) => warn!(from, to, %reason, "transfer skipped"),
+        (Some(e), _, _) => error!(from, to, signature, url, error = %e, "transfer failed"),
+        (None, Some(Ok(())), None) if result.simulation.is_some() => {
+            info!(from, to, amount = result.amount, "transfer simulated")
+        }
+        (None, Some(Ok(())), None) if result.proposal_index.is_some() => info!(
+            from,
+            to,
+            amount = result.amount,
+            signature,
+            url,
+            transaction_index = result.proposal_index,
+            "transfer proposed"
+        ),
+        (None, Some(Ok(())), None) => info!(
+            from,
+            to,
+            amount = result.amount,
+            signature,
+            url,
+            processing_time = ?result.processing_time,
+            fee = result.fee,
+            transfer_fee = result.transfer_fee,
+            "transfer confirmed"
+        ),
+        (None, Some(Err(e)), None) => {
+            error!(from, to, signature, url, error = %e, "transaction failed")
+        }
+        (None, None, None) if result.is_unconfirmed() => {
+            warn!(from, to, signature, url, "transfer sent, unconfirmed")
+        }
+        (None, None, None) => warn!(from, to, signature, url, "transaction status unknown"),
+    }
+    debug!(
+        from_url = explorer.map(|links| links.account(&result.from)),
+        to_url = explorer.map(|links| links.account(&result.to)),
+        memo = result.memo,
+        reference = result.reference,
+        compute_units = result.compute_units_consumed,
+        slot = result.slot,
+        block_time = result.block_time.map(output::format_unix_time),
+        "transfer details"
+    );
+    if let Some(simulation) = &result.simulation {
+        debug!(
+            compute_units = simulation.units_consumed,
+            error = simulation.error.as_ref().map(|e| e.to_string()),
+            "simulation"
+        );
+        for log in &simulation.logs {
+            debug!("  {}", log);
         }
-        println!("--------------------------------------------------------------------------------------\n")
     }
-    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let amount = 100_000_000; // 0.1 SOL in lamports
-    let config_yaml = parse_yaml("config.yaml")?;
-    let transfers = form_transfers(&config_yaml, amount)?;
-    let client = RpcClient::new_with_commitment(
-        config_yaml.rpc_url.to_string(),
-        CommitmentConfig::finalized(),
+async fn run(args: Args) -> TaskResult<()> {
+    let config_yaml = parse_yaml(&args.config, args.profile.as_deref())?;
+    if args.sign_only.is_some() {
+        offline::check_config(&config_yaml)?;
+    }
+    let log = LogArgs {
+        silent: args.dashboard,
+        ..args.log
+    };
+    let _telemetry = common::telemetry::init(&log, &config_yaml.telemetry, "task2")?;
+    let mut printer = Printer::new(&args, config_labels(&config_yaml));
+    let rpc_urls = match config_yaml.rpc.urls(args.rpc_url.clone(), args.network) {
+        Err(_) if args.sign_only.is_some() => vec![OFFLINE_RPC_URL.to_string()],
+        urls => urls?,
+    };
+    // Links are for people, machine output is left without them
+    if args.output == OutputFormat::Text && args.log.log_format == LogFormat::Text {
+        let explorer = args.explorer.or(config_yaml.explorer).unwrap_or_default();
+        printer.explorer = ExplorerLinks::new(explorer, &rpc_urls[0]);
+    }
+    let commitment = common::config::commitment(
+        args.commitment,
+        config_yaml.commitment,
+        CommitmentLevel::Finalized,
     );
+    let client = Arc::new(common::rpc::new_client(
+        rpc_urls,
+        config_yaml.rpc.fan_out,
+        commitment,
+        &config_yaml.retry,
+        config_yaml.rate_limit.as_ref(),
+        &config_yaml.timeouts,
+        None,
+    )?);
+
+    match &args.command {
+        Some(Command::Inspect { signature }) => {
+            let explorer = printer.explorer.as_ref();
+            return inspect::inspect(&client, signature, &printer.labels, explorer, args.output)
+                .await;
+        }
+        Some(Command::Request(request)) => {
+            let request = payment_request(&config_yaml, request, &client).await?;
+            return pay::print(&request, args.output);
+        }
+        #[cfg(feature = "api")]
+        Some(Command::Serve { .. }) => {}
+        None => {}
+    }
+
+    let notifier = Notifier::new(&config_yaml.alerts, "task2")?;
+    if let Some(path) = &args.broadcast {
+        let (send, timeout) = (&config_yaml.send, config_yaml.timeouts.confirmation_timeout);
+        let mut results =
+            offline::broadcast(path, &client, send, timeout, &printer, args.yes).await?;
+        if let Some(path) = &args.report {
+            report::write_csv(path, &results)?;
+        }
+        if let Some(path) = &config_yaml.database_path {
+            report::save(path, &results)?;
+        }
+        alert_results(&notifier, &results, config_yaml.mint.is_none()).await;
+        return match results.iter_mut().find_map(|result| result.error.take()) {
+            Some(err) => Err(err),
+            None => Ok(()),
+        };
+    }
+
+    let mint = match (&config_yaml.mint, config_yaml.mint_decimals) {
+        (Some(mint), Some(decimals)) if args.sign_only.is_some() => Some(TokenMint {
+            address: Pubkey::from_str(mint)
+                .map_err(|e| TaskError::KeyError(format!("mint {mint}: {e}")))?,
+            decimals,
+            program_id: match config_yaml.token_2022 {
+                true => spl_token_2022::id(),
+                false => spl_token::id(),
+            },
+            transfer_fee: None,
+            create_recipient_ata: false,
+            account_len: spl_token::state::Account::LEN,
+        }),
+        (Some(_), None) if args.sign_only.is_some() => {
+            return Err(TaskError::ConfigError(
+                "mint_decimals must be set to sign token transfers offline".to_string(),
+            ))
+        }
+        (Some(mint), decimals) => {
+            let mint = fetch_mint(mint, &client).await?;
+            if decimals.is_some_and(|decimals| decimals != mint.decimals) {
+                return Err(TaskError::ConfigError(format!(
+                    "mint_decimals does not match the {} decimals of the mint",
+                    mint.decimals
+                )));
+            }
+            Some(mint)
+        }
+        (None, _) => None,
+    }
+    .map(|mint| TokenMint {
+        create_recipient_ata: config_yaml.create_recipient_ata,
+        ..mint
+    });
+    let amount = args
+        .amount
+        .or(config_yaml.amount.clone())
+        .unwrap_or(Amount::Units(DEFAULT_AMOUNT));
+    transfer_amount(&amount, mint).map_err(|e| TaskError::ConfigError(format!("amount: {e}")))?;
+    if args.csv.as_deref() == Some("-") && !args.dry_run && !args.yes {
+        return Err(TaskError::ConfigError(
+            "--csv - reads the transfers from stdin, pass --yes to skip the confirmation prompt"
+                .to_string(),
+        ));
+    }
+    let mut transfers = match &args.csv {
+        Some(path) => csv_input::read_transfers(path, &config_yaml, &amount, mint)?,
+        None => form_transfers(&config_yaml, &amount, mint)?,
+    };
+    let policy = RecipientPolicy::load(&config_yaml.recipient_lists)?;
+    for transfer in &transfers {
+        policy.check(&transfer.recepient_public_key)?;
+    }
+    if args.airdrop {
+        let lamports = match &config_yaml.airdrop_amount {
+            Some(amount) => amount
+                .to_lamports()
+                .map_err(|e| TaskError::ConfigError(format!("airdrop_amount: {e}")))?,
+            None => DEFAULT_AIRDROP,
+        };
+        let mut wallets: Vec<Pubkey> = Vec::new();
+        for transfer in &transfers {
+            let sender = transfer.sender.pubkey();
+            if !wallets.contains(&sender) {
+                wallets.push(sender);
+            }
+        }
+        let explorer = printer.explorer.as_ref();
+        return airdrop::fund(&wallets, lamports, &client, explorer, args.output).await;
+    }
+    if args.reference {
+        for transfer in &mut transfers {
+            transfer.reference = Some(TxSigner::pubkey(&Keypair::new()));
+        }
+    }
+
+    let max_concurrency = match config_yaml.max_concurrency {
+        Some(0) => {
+            return Err(TaskError::ConfigError(
+                "max_concurrency must be at least 1".to_string(),
+            ))
+        }
+        Some(limit) => limit,
+        None => transfers.len().max(1),
+    };
+    let fan_out = args.csv.is_none() && is_fan_out(&config_yaml);
+    let batch_size = match config_yaml.transfers_per_transaction {
+        Some(0) => {
+            return Err(TaskError::ConfigError(
+                "transfers_per_transaction must be at least 1".to_string(),
+            ))
+        }
+        Some(size) => size,
+        None if fan_out => FAN_OUT_BATCH_SIZE,
+        None => 1,
+    };
+
+    let builder = TransactionBuilder::new(
+        &client,
+        config_yaml.compute_budget.clone(),
+        &config_yaml.transaction,
+    )
+    .await?
+    .with_send_config(&config_yaml.send)?;
+
+    if let (Some(path), Some(blockhash)) = (&args.sign_only, args.blockhash) {
+        if transfers.iter().any(|transfer| transfer.percent.is_some()) {
+            return Err(TaskError::ConfigError(
+                "percentages of the balance cannot be signed offline".to_string(),
+            ));
+        }
+        let builder = builder.with_blockhash(blockhash);
+        return offline::sign_transfers(path, &transfers, &client, &builder).await;
+    }
+
+    let builder = builder.with_blockhash_cache(BlockhashCache::new(client.clone()).await?);
+    #[cfg(feature = "api")]
+    if let Some(Command::Serve { addr }) = &args.command {
+        let api = api::Api {
+            config: config_yaml,
+            amount,
+            mint,
+            client,
+            builder,
+            printer: Printer {
+                progress: false,
+                ..printer
+            },
+            notifier,
+            policy,
+        };
+        return api::serve(addr, api, Shutdown::listen(DRAIN_TIMEOUT)).await;
+    }
+    let checkpoint = match &args.checkpoint {
+        Some(path) if args.resume => {
+            let (checkpoint, records) = Checkpoint::resume(path, &transfers)?;
+            for transfer in &mut transfers {
+                let Some(record) = records.get(&transfer.id) else {
+                    continue;
+                };
+                let signature = record.signature();
+                if record.state != TransferState::Confirmed {
+                    if !checkpoint::landed(record, &client).await? {
+                        continue;
+                    }
+                    let state = TransferState::Confirmed;
+                    checkpoint.record(transfer, state, signature.as_ref(), None)?;
+                }
+                transfer.skip = Some(SkipReason::AlreadyConfirmed {
+                    signature: signature.map(|s| s.to_string()).unwrap_or_default(),
+                });
+            }
+            Some(checkpoint)
+        }
+        Some(path) => Some(Checkpoint::create(path, &transfers)?),
+        None => None,
+    };
+    if args.sweep {
+        if fan_out {
+            return Err(TaskError::ConfigError(
+                "--sweep needs one recipient per sender".to_string(),
+            ));
+        }
+        sweep::set_amounts(&mut transfers, &client, &builder, args.keep_rent_exempt).await?;
+    }
+    if args.split || transfers.iter().any(|transfer| transfer.percent.is_some()) {
+        shares::set_amounts(&mut transfers, &client, &builder, args.split).await?;
+    }
+    let idempotency_store = match &config_yaml.idempotency_store {
+        Some(path) => path.as_str(),
+        None => DEFAULT_IDEMPOTENCY_STORE,
+    };
+    let mode = if args.sweep {
+        AmountMode::Sweep
+    } else if args.split {
+        AmountMode::Split
+    } else {
+        AmountMode::Configured
+    };
+    // A dry run neither reads nor creates the store
+    let mut idempotency = if args.dry_run {
+        None
+    } else {
+        Some(IdempotencyStore::open(
+            idempotency_store,
+            &config_yaml.batch_id,
+        )?)
+    };
+    let completed = match &mut idempotency {
+        Some(store) => store.check(&transfers, mode, &client).await?,
+        None => HashMap::new(),
+    };
+    if !args.force {
+        for transfer in &mut transfers {
+            if let (None, Some(signature)) = (&transfer.skip, completed.get(&transfer.id)) {
+                let signature = signature.clone();
+                transfer.skip = Some(SkipReason::AlreadySent { signature });
+            }
+        }
+    }
+    // After the idempotency keys, which must not depend on balances topped up to
+    rent::check_recipients(&mut transfers, &client, args.top_up_rent_exempt).await?;
+    if let Some(floor) = config_yaml.keep_minimum_lamports {
+        floor::protect(&mut transfers, &client, &builder, floor).await?;
+    }
+    if !args.dry_run {
+        Preview::of_transfers(&transfers, batch_size, &client, &builder)
+            .await?
+            .confirm(args.yes)?;
+    }
+
+    let dashboard = if args.dashboard {
+        Some(Dashboard::start("task2")?)
+    } else {
+        None
+    };
+    if let Some((dashboard, _)) = &dashboard {
+        let mut sources: Vec<Pubkey> = Vec::new();
+        for transfer in &transfers {
+            if !sources.contains(&transfer.source()) {
+                sources.push(transfer.source());
+            }
+        }
+        dashboard.watch_balances(client.clone(), sources);
+        printer.dashboard = Some(dashboard.clone());
+    }
+    let shutdown = Shutdown::listen(DRAIN_TIMEOUT);
+    let mut results = make_transfers(
+        &transfers,
+        &client,
+        &builder,
+        &printer,
+        &SendOptions {
+            dry_run: args.dry_run,
+            send: &config_yaml.send,
+            confirmation_timeout: config_yaml.timeouts.confirmation_timeout,
+            max_concurrency,
+            batch_size,
+            max_rebuilds: config_yaml.max_rebuilds.unwrap_or(DEFAULT_MAX_REBUILDS),
+            checkpoint: checkpoint.as_ref(),
+            idempotency: idempotency.as_ref(),
+            shutdown: &shutdown,
+        },
+    )
+    .await?;
+    if let Some(path) = &args.report {
+        report::write_csv(path, &results)?;
+    }
+    if let Some(path) = &config_yaml.database_path {
+        report::save(path, &results)?;
+    }
+    if !args.dry_run {
+        alert_results(&notifier, &results, mint.is_none()).await;
+    }
+    if shutdown.requested() {
+        return Err(TaskError::Interrupted);
+    }
+
+    // Exit with the code of the first failure so scripts notice a partially failed batch
+    match results.iter_mut().find_map(|result| result.error.take()) {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    common::exit(run(Args::parse()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::rpc_request::RpcRequest;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::{Message, VersionedMessage};
+    use solana_sdk::signature::Keypair;
 
-    make_transfers(&transfers, &client).await?;
+    fn transfer(id: usize, sender: &Arc<dyn TxSigner>, amount: u64) -> Transfer {
+        Transfer {
+            id,
+            amount,
+            sender: sender.clone(),
+            recepient_public_key: Pubkey::new_unique(),
+            mint: None,
+            nonce: None,
+            multisig: None,
+            memo: None,
+            reference: None,
+            percent: None,
+            skip: None,
+        }
+    }
+
+    fn ids(batches: &[&[Transfer]]) -> Vec<Vec<usize>> {
+        batches
+            .iter()
+            .map(|batch| batch.iter().map(|transfer| transfer.id).collect())
+            .collect()
+    }
 
-    Ok(())
+    // A builder that never needs the network: no `auto` price, no lookup tables and a
+    // fixed blockhash
+    async fn offline_builder(client: &RpcClient) -> TransactionBuilder {
+        TransactionBuilder::new(client, ComputeBudgetConfig::default(), &Default::default())
+            .await
+            .unwrap()
+            .with_blockhash(Hash::default())
+    }
+
+    #[test]
+    fn batches_split_by_sender_and_size() {
+        let alice: Arc<dyn TxSigner> = Arc::new(Keypair::new());
+        let bob: Arc<dyn TxSigner> = Arc::new(Keypair::new());
+        let transfers: Vec<Transfer> = [&alice, &alice, &alice, &bob, &alice]
+            .into_iter()
+            .enumerate()
+            .map(|(id, sender)| transfer(id, sender, 1))
+            .collect();
+        let (batches, empty) = batches(&transfers, 2);
+        assert_eq!(ids(&batches), [vec![0, 1], vec![2], vec![3], vec![4]]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn batches_leave_out_empty_and_skipped_transfers() {
+        let alice: Arc<dyn TxSigner> = Arc::new(Keypair::new());
+        let mut transfers: Vec<Transfer> = (0..5).map(|id| transfer(id, &alice, 1)).collect();
+        transfers[1].amount = 0;
+        transfers[3].skip = Some(SkipReason::Interrupted);
+        let (batches, empty) = batches(&transfers, 10);
+        assert_eq!(ids(&batches), [vec![0], vec![2], vec![4]]);
+        let empty: Vec<usize> = empty.iter().map(|transfer| transfer.id).collect();
+        assert_eq!(empty, [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn fit_packet_keeps_a_batch_that_fits() {
+        let client = RpcClient::new(OFFLINE_RPC_URL.to_string());
+        let builder = offline_builder(&client).await;
+        let alice: Arc<dyn TxSigner> = Arc::new(Keypair::new());
+        let transfers: Vec<Transfer> = (0..5).map(|id| transfer(id, &alice, 1)).collect();
+        let parts = fit_packet(&transfers, &client, &builder).await.unwrap();
+        assert_eq!(ids(&parts), [vec![0, 1, 2, 3, 4]]);
+    }
+
+    #[tokio::test]
+    async fn fit_packet_halves_a_batch_over_the_packet_size() {
+        let client = RpcClient::new(OFFLINE_RPC_URL.to_string());
+        let builder = offline_builder(&client).await;
+        let alice: Arc<dyn TxSigner> = Arc::new(Keypair::new());
+        let transfers: Vec<Transfer> = (0..4)
+            .map(|id| Transfer {
+                memo: Some("m".repeat(300)),
+                ..transfer(id, &alice, 1)
+            })
+            .collect();
+        let parts = fit_packet(&transfers, &client, &builder).await.unwrap();
+        assert_eq!(ids(&parts), [vec![0, 1], vec![2, 3]]);
+        for part in parts {
+            let instructions = transfer_instructions(part, &client).await.unwrap();
+            let size = builder
+                .size(&client, instructions, &alice.pubkey(), None)
+                .await
+                .unwrap();
+            assert!(size <= PACKET_DATA_SIZE);
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_keeps_a_landed_transaction_that_is_not_final() {
+        let payer = Keypair::new();
+        let instruction = system_instruction::transfer(&payer.pubkey(), &Pubkey::new_unique(), 1);
+        let message =
+            Message::new_with_blockhash(&[instruction], Some(&payer.pubkey()), &Hash::new_unique());
+        let tx =
+            VersionedTransaction::try_new(VersionedMessage::Legacy(message), &[&payer]).unwrap();
+        // The blockhash expired, the transaction landed at confirmed only
+        let landed = serde_json::json!({
+            "context": {"slot": 1},
+            "value": [{
+                "slot": 1,
+                "confirmations": 0,
+                "err": null,
+                "status": {"Ok": null},
+                "confirmationStatus": "confirmed",
+            }],
+        });
+        let invalid = serde_json::json!({"context": {"slot": 1}, "value": false});
+        let mocks = HashMap::from([
+            (RpcRequest::GetSignatureStatuses, landed),
+            (RpcRequest::IsBlockhashValid, invalid.clone()),
+        ]);
+        let client = RpcClient::new_mock_with_mocks("succeeds".to_string(), mocks);
+        assert!(!expired(&client, &tx).await.unwrap());
+
+        let mocks = HashMap::from([(RpcRequest::IsBlockhashValid, invalid)]);
+        let client = RpcClient::new_mock_with_mocks("sig_not_found".to_string(), mocks);
+        assert!(expired(&client, &tx).await.unwrap());
+    }
 }
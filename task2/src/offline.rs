@@ -0,0 +1,170 @@
+use crate::preview::Preview;
+use crate::{build_transaction, print_results, Printer, Transfer, TransferResult, YamlFile};
+use base64::{prelude::BASE64_STANDARD, Engine};
+use common::compute_budget::ComputeUnitPrice;
+use common::transaction::{SendConfig, TransactionBuilder};
+use common::{TaskError, TaskResult};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::transaction::VersionedTransaction;
+use std::{fs, slice, time::Duration, time::Instant};
+
+/// A transaction signed by `--sign-only`, with the details of its transfer for review.
+#[derive(Serialize, Deserialize)]
+struct SignedTransfer {
+    from: String,
+    to: String,
+    amount: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    memo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    /// Bincode serialized transaction, base64 encoded
+    transaction: String,
+}
+
+/// Rejects the settings `--sign-only` would need the network for, before anything is
+/// signed against the placeholder endpoint.
+pub fn check_config(config: &YamlFile) -> TaskResult<()> {
+    if matches!(
+        config.compute_budget.compute_unit_price,
+        Some(ComputeUnitPrice::Auto)
+    ) {
+        return Err(TaskError::ConfigError(
+            "compute_unit_price `auto` needs the network, set a price to sign offline".to_string(),
+        ));
+    }
+    if !config.multisigs.is_empty() {
+        return Err(TaskError::ConfigError(
+            "multisig transfers cannot be signed offline, propose them online instead".to_string(),
+        ));
+    }
+    if !config.transaction.address_lookup_tables.is_empty() {
+        return Err(TaskError::ConfigError(
+            "address_lookup_tables are looked up on the network and cannot be used offline"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Signs every transfer one after another and writes them to `path` as a JSON array.
+pub async fn sign_transfers(
+    path: &str,
+    transfers: &[Transfer],
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+) -> TaskResult<()> {
+    let mut signed = Vec::with_capacity(transfers.len());
+    for transfer in transfers {
+        let (tx, _) = build_transaction(slice::from_ref(transfer), client, builder).await?;
+        let bytes = bincode::serialize(&tx).map_err(|e| TaskError::BuildError(e.to_string()))?;
+        signed.push(SignedTransfer {
+            from: transfer.source().to_string(),
+            to: transfer.recepient_public_key.to_string(),
+            amount: transfer.amount,
+            memo: transfer.memo.clone(),
+            reference: transfer.reference.map(|reference| reference.to_string()),
+            transaction: BASE64_STANDARD.encode(bytes),
+        });
+    }
+    let json =
+        serde_json::to_string_pretty(&signed).map_err(|e| TaskError::BuildError(e.to_string()))?;
+    fs::write(path, json)?;
+    tracing::info!("signed {} transfer(s) into {path}", signed.len());
+    Ok(())
+}
+
+fn decode(entry: &SignedTransfer) -> TaskResult<VersionedTransaction> {
+    let bytes = BASE64_STANDARD
+        .decode(&entry.transaction)
+        .map_err(|e| TaskError::ConfigError(format!("transaction to {}: {e}", entry.to)))?;
+    let tx: VersionedTransaction = bincode::deserialize(&bytes)
+        .map_err(|e| TaskError::ConfigError(format!("transaction to {}: {e}", entry.to)))?;
+    if tx.verify_with_results().contains(&false) {
+        return Err(TaskError::ConfigError(format!(
+            "transaction to {} is not fully signed",
+            entry.to
+        )));
+    }
+    Ok(tx)
+}
+
+async fn send(
+    entry: &SignedTransfer,
+    tx: &VersionedTransaction,
+    client: &RpcClient,
+    send_config: &SendConfig,
+    timeout: Option<Duration>,
+) -> TransferResult {
+    let mut result = TransferResult {
+        from: entry.from.clone(),
+        from_label: None,
+        to: entry.to.clone(),
+        to_label: None,
+        amount: entry.amount,
+        memo: entry.memo.clone(),
+        reference: entry.reference.clone(),
+        signature: None,
+        fee: None,
+        transfer_fee: None,
+        compute_units_consumed: None,
+        slot: None,
+        block_time: None,
+        processing_time: Duration::ZERO,
+        status: None,
+        error: None,
+        simulation: None,
+        skipped: None,
+        expired_signatures: Vec::new(),
+        proposal_index: None,
+    };
+    let start_time = Instant::now();
+    match common::transaction::send_and_confirm(client, tx, send_config, timeout).await {
+        Ok(signature) => {
+            result.processing_time = start_time.elapsed();
+            result.signature = Some(signature);
+            match client.get_signature_status(&signature).await {
+                Ok(status) => result.status = status,
+                Err(e) => result.error = Some(e.into()),
+            }
+            if let Ok(landed) = common::transaction::landed(client, &signature).await {
+                result.fee = landed.fee;
+                result.compute_units_consumed = landed.compute_units_consumed;
+                result.slot = Some(landed.slot);
+                result.block_time = landed.block_time;
+            }
+        }
+        Err(e) => result.error = Some(e),
+    }
+    result
+}
+
+/// Sends and confirms the transactions of a `--sign-only` file one by one. Every
+/// transaction is checked to be fully signed, and the batch confirmed unless `yes`, before
+/// anything is sent.
+pub async fn broadcast(
+    path: &str,
+    client: &RpcClient,
+    send_config: &SendConfig,
+    timeout: Option<Duration>,
+    printer: &Printer,
+    yes: bool,
+) -> TaskResult<Vec<TransferResult>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| TaskError::ConfigError(format!("cannot read {path}: {e}")))?;
+    let entries: Vec<SignedTransfer> = serde_json::from_str(&content)
+        .map_err(|e| TaskError::ConfigError(format!("{path}: {e}")))?;
+    let transactions = entries.iter().map(decode).collect::<TaskResult<Vec<_>>>()?;
+    let sent = entries
+        .iter()
+        .map(|entry| (entry.to.as_str(), entry.amount));
+    Preview::of_transactions(sent, &transactions, client)
+        .await?
+        .confirm(yes)?;
+
+    let tasks = stream::iter(entries.iter().zip(&transactions))
+        .then(|(entry, tx)| send(entry, tx, client, send_config, timeout));
+    print_results(Box::pin(tasks), entries.len(), printer).await
+}
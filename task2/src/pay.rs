@@ -0,0 +1,113 @@
+use common::amount::Amount;
+use common::output::{self, percent_encode, OutputFormat};
+use common::{TaskError, TaskResult};
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+/// Options of the `request` subcommand.
+#[derive(clap::Args, Debug)]
+pub struct RequestArgs {
+    /// Amount asked for: lamports (or token base units), or whole SOL (or tokens) with a
+    /// decimal point. Left to the payer when omitted
+    pub amount: Option<Amount>,
+    /// Wallet to be paid instead of the first sender of the config
+    #[arg(long)]
+    pub recipient: Option<String>,
+    /// Add a new reference key to the request, to find the payment later with
+    /// `getSignaturesForAddress`
+    #[arg(long)]
+    pub reference: bool,
+    /// Name of the merchant or payee the wallet shows
+    #[arg(long)]
+    pub label: Option<String>,
+    /// What the payment is for, shown by the wallet
+    #[arg(long)]
+    pub message: Option<String>,
+    /// SPL Memo the wallet attaches to the payment
+    #[arg(long)]
+    pub memo: Option<String>,
+}
+
+/// A Solana Pay transfer request, see https://docs.solanapay.com/spec.
+#[derive(Serialize)]
+pub struct PaymentRequest {
+    #[serde(serialize_with = "output::serialize_display")]
+    pub recipient: Pubkey,
+    /// Whole SOL or tokens, as the spec writes amounts
+    pub amount: Option<String>,
+    #[serde(serialize_with = "output::serialize_optional_display")]
+    pub spl_token: Option<Pubkey>,
+    #[serde(serialize_with = "output::serialize_optional_display")]
+    pub reference: Option<Pubkey>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+    pub memo: Option<String>,
+}
+
+impl PaymentRequest {
+    /// The `solana:` URL wallets open.
+    pub fn url(&self) -> String {
+        let spl_token = self.spl_token.map(|mint| mint.to_string());
+        let reference = self.reference.map(|reference| reference.to_string());
+        let params = [
+            ("amount", self.amount.as_deref()),
+            ("spl-token", spl_token.as_deref()),
+            ("reference", reference.as_deref()),
+            ("label", self.label.as_deref()),
+            ("message", self.message.as_deref()),
+            ("memo", self.memo.as_deref()),
+        ];
+        let query: Vec<String> = params
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{name}={}", percent_encode(value?))))
+            .collect();
+        match query.is_empty() {
+            true => format!("solana:{}", self.recipient),
+            false => format!("solana:{}?{}", self.recipient, query.join("&")),
+        }
+    }
+}
+
+/// Writes `units` of a token with `decimals` decimals as a decimal number without trailing
+/// zeros, e.g. `1.5` for 1500000 units with 6 decimals.
+pub fn format_units(units: u64, decimals: u8) -> String {
+    let scale = 10u64.pow(decimals as u32);
+    let fraction = format!("{:0width$}", units % scale, width = decimals as usize);
+    match fraction.trim_end_matches('0') {
+        "" => (units / scale).to_string(),
+        fraction => format!("{}.{fraction}", units / scale),
+    }
+}
+
+/// Prints the URL of `request` with a QR code of it for the terminal, or the request as
+/// JSON.
+pub fn print(request: &PaymentRequest, output: OutputFormat) -> TaskResult<()> {
+    #[derive(Serialize)]
+    struct RequestLine<'a> {
+        url: String,
+        #[serde(flatten)]
+        request: &'a PaymentRequest,
+    }
+
+    let url = request.url();
+    if output == OutputFormat::Json {
+        return output::print_json(&RequestLine { url, request });
+    }
+    let code = QrCode::new(url.as_bytes()).map_err(|e| {
+        TaskError::ConfigError(format!("cannot encode the request as a QR code: {e}"))
+    })?;
+    // Light modules are drawn, so the code reads on dark terminal backgrounds
+    let qr = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build();
+    println!("{qr}");
+    println!("{url}");
+    if let Some(reference) = request.reference {
+        println!("reference: {reference}");
+    }
+    Ok(())
+}
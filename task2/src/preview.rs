@@ -0,0 +1,110 @@
+use crate::{batches, transfer_instructions, Transfer};
+use common::transaction::TransactionBuilder;
+use common::{TaskError, TaskResult};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    native_token::lamports_to_sol, pubkey::Pubkey, transaction::VersionedTransaction,
+};
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+
+/// What a run is about to send, shown before it asks for confirmation.
+#[derive(Debug, Default)]
+pub struct Preview {
+    transfers: usize,
+    /// In lamports, or in base units of `mint`
+    total_amount: u64,
+    total_fees: u64,
+    recipients: usize,
+    mint: Option<Pubkey>,
+}
+
+impl Preview {
+    /// Sums up the transfers that will be sent, with the fee of every transaction they are
+    /// packed into.
+    pub async fn of_transfers(
+        transfers: &[Transfer],
+        batch_size: usize,
+        client: &RpcClient,
+        builder: &TransactionBuilder,
+    ) -> TaskResult<Self> {
+        let (batches, _) = batches(transfers, batch_size);
+        let mut preview = Preview::default();
+        let mut recipients = HashSet::new();
+        for batch in batches {
+            let sender = batch[0].sender.pubkey();
+            let instructions = transfer_instructions(batch, client).await?;
+            let fee = builder
+                .fee(client, instructions, &sender, batch[0].nonce.as_ref())
+                .await?;
+            preview.total_fees = preview.total_fees.saturating_add(fee);
+            for transfer in batch {
+                preview.transfers += 1;
+                preview.total_amount = preview.total_amount.saturating_add(transfer.amount);
+                preview.mint = transfer.mint.map(|mint| mint.address);
+                recipients.insert(transfer.recepient_public_key);
+            }
+        }
+        preview.recipients = recipients.len();
+        Ok(preview)
+    }
+
+    /// Sums up signed transactions of a `--sign-only` file, each with its recipient and
+    /// amount as recorded in the file.
+    pub async fn of_transactions(
+        entries: impl Iterator<Item = (&str, u64)>,
+        transactions: &[VersionedTransaction],
+        client: &RpcClient,
+    ) -> TaskResult<Self> {
+        let mut preview = Preview::default();
+        let mut recipients = HashSet::new();
+        for (to, amount) in entries {
+            preview.transfers += 1;
+            preview.total_amount = preview.total_amount.saturating_add(amount);
+            recipients.insert(to);
+        }
+        for tx in transactions {
+            let fee = common::transaction::fee_for(client, tx).await?;
+            preview.total_fees = preview.total_fees.saturating_add(fee);
+        }
+        preview.recipients = recipients.len();
+        Ok(preview)
+    }
+
+    /// Prints the preview and waits for `yes` on stdin, unless `yes` is already given.
+    /// Anything else stops the run before a single transaction is sent.
+    pub fn confirm(&self, yes: bool) -> TaskResult<()> {
+        if self.transfers == 0 || yes {
+            return Ok(());
+        }
+        let total = match self.mint {
+            Some(mint) => format!("{} base units of {mint}", self.total_amount),
+            None => format!(
+                "{} SOL ({} lamports)",
+                lamports_to_sol(self.total_amount),
+                self.total_amount
+            ),
+        };
+        let mut stderr = io::stderr().lock();
+        writeln!(stderr, "Transfers:           {}", self.transfers)?;
+        writeln!(stderr, "Total amount:        {total}")?;
+        writeln!(
+            stderr,
+            "Estimated fees:      {} SOL ({} lamports)",
+            lamports_to_sol(self.total_fees),
+            self.total_fees
+        )?;
+        writeln!(stderr, "Distinct recipients: {}", self.recipients)?;
+        write!(stderr, "Type `yes` to send: ")?;
+        stderr.flush()?;
+
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        if answer.trim() != "yes" {
+            return Err(TaskError::ConfigError(
+                "not confirmed, nothing was sent (pass --yes to skip the prompt)".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use crate::Transfer;
+use common::TaskResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use tracing::warn;
+
+// Most addresses a single getMultipleAccounts request accepts
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Warns about every recipient of SOL that would hold less than the rent-exempt minimum of
+/// an empty account once paid, which the network refuses for new accounts. With `top_up`,
+/// the last transfer to such a recipient is raised to reach the minimum instead.
+pub async fn check_recipients(
+    transfers: &mut [Transfer],
+    client: &RpcClient,
+    top_up: bool,
+) -> TaskResult<()> {
+    // Lamports each recipient is about to receive
+    let mut incoming: HashMap<Pubkey, u64> = HashMap::new();
+    for transfer in transfers.iter() {
+        if transfer.mint.is_none() && transfer.amount > 0 && transfer.skip.is_none() {
+            *incoming.entry(transfer.recepient_public_key).or_default() += transfer.amount;
+        }
+    }
+    if incoming.is_empty() {
+        return Ok(());
+    }
+
+    let minimum = client.get_minimum_balance_for_rent_exemption(0).await?;
+    let recipients: Vec<Pubkey> = incoming.keys().copied().collect();
+    let mut shortfalls = HashMap::new();
+    for chunk in recipients.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let accounts = client.get_multiple_accounts(chunk).await?;
+        for (recipient, account) in chunk.iter().zip(accounts) {
+            let balance = account.map_or(0, |account| account.lamports);
+            let after = balance.saturating_add(incoming[recipient]);
+            if after >= minimum {
+                continue;
+            }
+            warn!(
+                %recipient,
+                balance,
+                incoming = incoming[recipient],
+                minimum,
+                top_up,
+                "recipient would be left below the rent-exempt minimum"
+            );
+            shortfalls.insert(*recipient, minimum - after);
+        }
+    }
+
+    if top_up {
+        for transfer in transfers.iter_mut().rev() {
+            if transfer.mint.is_some() || transfer.amount == 0 || transfer.skip.is_some() {
+                continue;
+            }
+            if let Some(shortfall) = shortfalls.remove(&transfer.recepient_public_key) {
+                transfer.amount += shortfall;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use crate::TransferResult;
+use common::database::{Database, TransferRecord};
+use common::{output, TaskResult};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct ReportRow<'a> {
+    from: &'a str,
+    from_label: &'a str,
+    to: &'a str,
+    to_label: &'a str,
+    signature: String,
+    lamports: u64,
+    fee_lamports: Option<u64>,
+    compute_units: Option<u64>,
+    slot: Option<u64>,
+    block_time: String,
+    duration_ms: u128,
+    status: &'static str,
+    error: String,
+}
+
+impl<'a> From<&'a TransferResult> for ReportRow<'a> {
+    fn from(result: &'a TransferResult) -> Self {
+        let status = match (&result.skipped, &result.error, &result.status) {
+            (Some(_), _, _) => "skipped",
+            (None, None, Some(Ok(()))) if result.proposal_index.is_some() => "proposed",
+            (None, None, Some(Ok(()))) => "ok",
+            (None, None, None) if result.signature.is_some() => "unconfirmed",
+            (None, None, None) => "unknown",
+            _ => "failed",
+        };
+        let error = match (&result.skipped, &result.error, &result.status) {
+            (Some(reason), _, _) => reason.to_string(),
+            (None, Some(e), _) => e.to_string(),
+            (None, None, Some(Err(e))) => e.to_string(),
+            _ => String::new(),
+        };
+
+        ReportRow {
+            from: &result.from,
+            from_label: result.from_label.as_deref().unwrap_or_default(),
+            to: &result.to,
+            to_label: result.to_label.as_deref().unwrap_or_default(),
+            signature: result
+                .signature
+                .map(|signature| signature.to_string())
+                .unwrap_or_default(),
+            lamports: result.amount,
+            fee_lamports: result.fee,
+            compute_units: result.compute_units_consumed,
+            slot: result.slot,
+            block_time: result
+                .block_time
+                .map(output::format_unix_time)
+                .unwrap_or_default(),
+            duration_ms: result.processing_time.as_millis(),
+            status,
+            error,
+        }
+    }
+}
+
+/// Writes one CSV row per transfer result so the batch can be reconciled.
+pub fn write_csv(path: &str, results: &[TransferResult]) -> TaskResult<()> {
+    let mut writer = csv::Writer::from_path(path).map_err(std::io::Error::from)?;
+    for result in results {
+        writer
+            .serialize(ReportRow::from(result))
+            .map_err(std::io::Error::from)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Inserts every transfer result into the `transfers` table of the database at `path`.
+pub fn save(path: &str, results: &[TransferResult]) -> TaskResult<()> {
+    let rows: Vec<ReportRow> = results.iter().map(ReportRow::from).collect();
+    let records: Vec<TransferRecord> = results
+        .iter()
+        .zip(&rows)
+        .map(|(result, row)| TransferRecord {
+            sender: row.from,
+            recipient: row.to,
+            amount: row.lamports,
+            memo: result.memo.as_deref(),
+            signature: Some(row.signature.as_str()).filter(|signature| !signature.is_empty()),
+            fee_lamports: row.fee_lamports,
+            compute_units: row.compute_units,
+            slot: row.slot,
+            block_time: result.block_time,
+            duration_ms: row.duration_ms,
+            status: row.status,
+            error: Some(row.error.as_str()).filter(|error| !error.is_empty()),
+        })
+        .collect();
+    Database::open(path)?.insert_transfers(&records)
+}
@@ -0,0 +1,50 @@
+use crate::sweep;
+use crate::Transfer;
+use common::transaction::TransactionBuilder;
+use common::TaskResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{hash_map::Entry, HashMap};
+
+/// Works out the amounts given as a share of the balance, right before sending. Every
+/// balance is read once, so percentages of one sender all refer to its balance before the
+/// run. With `split` the balance of every source is divided equally between its transfers,
+/// after setting aside the fee of each SOL transfer.
+pub async fn set_amounts(
+    transfers: &mut [Transfer],
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+    split: bool,
+) -> TaskResult<()> {
+    let mut balances: HashMap<Pubkey, u64> = HashMap::new();
+    let mut counts: HashMap<Pubkey, u64> = HashMap::new();
+    for transfer in transfers.iter() {
+        let source = transfer.source();
+        *counts.entry(source).or_default() += 1;
+        if let Entry::Vacant(entry) = balances.entry(source) {
+            entry.insert(sweep::balance(transfer, client).await?);
+        }
+    }
+
+    for transfer in transfers.iter_mut() {
+        let balance = balances[&transfer.source()];
+        if !split {
+            if let Some(amount) = transfer
+                .percent
+                .as_ref()
+                .and_then(|p| p.percent_of(balance))
+            {
+                transfer.amount = amount;
+            }
+            continue;
+        }
+        let count = counts[&transfer.source()];
+        let fee = if transfer.mint.is_some() {
+            0
+        } else {
+            sweep::source_fee(transfer, client, builder).await?
+        };
+        transfer.amount = balance.saturating_sub(fee.saturating_mul(count)) / count;
+    }
+    Ok(())
+}
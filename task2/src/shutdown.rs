@@ -0,0 +1,54 @@
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Exit code of a process stopped by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Tells a run that Ctrl+C was pressed. The first press asks the run to stop launching
+/// transfers and to wait for the ones in flight, at most `drain_timeout`. A second press
+/// quits right away.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    requested: watch::Receiver<bool>,
+    drain_timeout: Duration,
+}
+
+impl Shutdown {
+    /// Starts listening for Ctrl+C, which no longer kills the process from then on.
+    pub fn listen(drain_timeout: Duration) -> Self {
+        let (sender, requested) = watch::channel(false);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            warn!(
+                "interrupted, waiting up to {}s for the transfers in flight, press Ctrl+C again to quit now",
+                drain_timeout.as_secs()
+            );
+            let _ = sender.send(true);
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+        });
+        Shutdown {
+            requested,
+            drain_timeout,
+        }
+    }
+
+    pub fn requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Resolves once the transfers in flight have had `drain_timeout` to finish after
+    /// Ctrl+C, never if it is not pressed.
+    pub async fn deadline(&self) {
+        let mut requested = self.requested.clone();
+        if requested.wait_for(|requested| *requested).await.is_err() {
+            // Ctrl+C cannot be listened for
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(self.drain_timeout).await;
+    }
+}
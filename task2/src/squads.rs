@@ -0,0 +1,266 @@
+use common::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    hash,
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    pubkey,
+    pubkey::Pubkey,
+    system_program,
+};
+use std::str::FromStr;
+
+/// Squads v4 multisig program
+const PROGRAM_ID: Pubkey = pubkey!("SQDS4ep65T869zMMBKyuUq6aD6EgTu8psMjkvj52pCf");
+// Offset of `transaction_index` in a multisig account: discriminator, create_key,
+// config_authority, threshold and time_lock come first
+const TRANSACTION_INDEX_OFFSET: usize = 8 + 32 + 32 + 2 + 4;
+
+/// A vault transaction and its proposal, as created by `Multisig::propose`.
+#[derive(Debug, Clone, Copy)]
+pub struct Proposal {
+    /// Index of the vault transaction in the multisig
+    pub transaction_index: u64,
+    /// Sizes of the vault transaction and proposal accounts, whose rent the creator pays
+    pub account_sizes: [usize; 2],
+}
+
+/// Squads multisig whose vault a sender proposes transfers from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultisigConfig {
+    pub multisig: String,
+    /// Index of the vault the funds leave from, 0 by default
+    #[serde(default)]
+    pub vault_index: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Multisig {
+    pub address: Pubkey,
+    pub vault_index: u8,
+}
+
+impl Multisig {
+    pub fn from_config(config: &MultisigConfig) -> TaskResult<Self> {
+        let address = Pubkey::from_str(&config.multisig)
+            .map_err(|e| TaskError::KeyError(format!("multisig {}: {e}", config.multisig)))?;
+        Ok(Self {
+            address,
+            vault_index: config.vault_index,
+        })
+    }
+
+    /// The vault account holding the funds of the multisig.
+    pub fn vault(&self) -> Pubkey {
+        self.pda(&[b"vault", &[self.vault_index]])
+    }
+
+    fn pda(&self, seeds: &[&[u8]]) -> Pubkey {
+        let mut all: Vec<&[u8]> = vec![b"multisig", self.address.as_ref()];
+        all.extend_from_slice(seeds);
+        Pubkey::find_program_address(&all, &PROGRAM_ID).0
+    }
+
+    /// Instructions creating a vault transaction that runs `instructions` from the vault,
+    /// and a proposal for the members to approve it. `creator` must be a member allowed to
+    /// initiate transactions, and pays the rent of both accounts.
+    pub async fn propose(
+        &self,
+        client: &RpcClient,
+        creator: &Pubkey,
+        instructions: &[Instruction],
+    ) -> TaskResult<(Vec<Instruction>, Proposal)> {
+        let (transaction_index, members) = self.read_state(client).await?;
+        let index = transaction_index.to_le_bytes();
+        let transaction = self.pda(&[b"transaction", &index]);
+        let proposal = self.pda(&[b"transaction", &index, b"proposal"]);
+
+        let mut create_args = vec![self.vault_index, 0];
+        let message = transaction_message(&self.vault(), instructions)?;
+        let proposal_info = Proposal {
+            transaction_index,
+            account_sizes: [
+                vault_transaction_size(message.len(), instructions.len()),
+                proposal_size(members),
+            ],
+        };
+        create_args.extend((message.len() as u32).to_le_bytes());
+        create_args.extend(message);
+        create_args.push(0); // no memo
+        let create = Instruction::new_with_bytes(
+            PROGRAM_ID,
+            &instruction_data("vault_transaction_create", &create_args),
+            vec![
+                AccountMeta::new(self.address, false),
+                AccountMeta::new(transaction, false),
+                AccountMeta::new_readonly(*creator, true),
+                AccountMeta::new(*creator, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+
+        let mut proposal_args = index.to_vec();
+        proposal_args.push(0); // not a draft, open for voting right away
+        let propose = Instruction::new_with_bytes(
+            PROGRAM_ID,
+            &instruction_data("proposal_create", &proposal_args),
+            vec![
+                AccountMeta::new_readonly(self.address, false),
+                AccountMeta::new(proposal, false),
+                AccountMeta::new_readonly(*creator, true),
+                AccountMeta::new(*creator, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        Ok((vec![create, propose], proposal_info))
+    }
+
+    // The index the next vault transaction gets, and the number of members
+    async fn read_state(&self, client: &RpcClient) -> TaskResult<(u64, usize)> {
+        let data = client.get_account_data(&self.address).await?;
+        let not_a_multisig =
+            || TaskError::ConfigError(format!("{} is not a Squads multisig", self.address));
+        let index = data
+            .get(TRANSACTION_INDEX_OFFSET..TRANSACTION_INDEX_OFFSET + 8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().expect("slice of 8 bytes")))
+            .ok_or_else(not_a_multisig)?;
+        // Then stale_transaction_index, the optional rent_collector and the bump
+        let mut offset = TRANSACTION_INDEX_OFFSET + 16;
+        offset += match data.get(offset) {
+            Some(0) => 1,
+            Some(_) => 1 + 32,
+            None => return Err(not_a_multisig()),
+        };
+        offset += 1;
+        let members = data
+            .get(offset..offset + 4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().expect("slice of 4 bytes")))
+            .ok_or_else(not_a_multisig)?;
+        Ok((index + 1, members as usize))
+    }
+}
+
+// Size of a vault transaction account storing a message of `message_len` bytes in the
+// compact format. The account keeps it Borsh encoded, with u32 lengths instead of the u8
+// ones of the key, instruction and lookup lists, and of the accounts of each instruction
+fn vault_transaction_size(message_len: usize, instructions: usize) -> usize {
+    let stored_message = message_len + 3 * 3 + instructions * (3 + 2);
+    // Discriminator, multisig, creator, index, bump, vault index, vault bump and the
+    // empty list of ephemeral signer bumps
+    8 + 32 + 32 + 8 + 1 + 1 + 1 + 4 + stored_message
+}
+
+// Size of a proposal account, with room for every member in its approved, rejected and
+// cancelled lists
+fn proposal_size(members: usize) -> usize {
+    8 + 32 + 8 + 1 + 8 + 1 + 3 * (4 + 32 * members)
+}
+
+// Anchor instruction data: the first 8 bytes of sha256("global:<name>"), then the arguments
+fn instruction_data(name: &str, args: &[u8]) -> Vec<u8> {
+    let mut data = hash::hash(format!("global:{name}").as_bytes()).to_bytes()[..8].to_vec();
+    data.extend_from_slice(args);
+    data
+}
+
+// Compiles `instructions` with the vault as payer into the compact message format Squads
+// stores, where lengths are single bytes except the u16 length of instruction data
+fn transaction_message(vault: &Pubkey, instructions: &[Instruction]) -> TaskResult<Vec<u8>> {
+    let message = Message::new(instructions, Some(vault));
+    let header = message.header;
+    let keys = message.account_keys.len();
+    let too_large = || TaskError::BuildError("vault transaction is too large".to_string());
+    let signers = header.num_required_signatures;
+    let writable_non_signers = keys
+        .checked_sub(usize::from(signers) + usize::from(header.num_readonly_unsigned_accounts))
+        .and_then(|count| u8::try_from(count).ok())
+        .ok_or_else(too_large)?;
+
+    let mut data = vec![
+        signers,
+        signers - header.num_readonly_signed_accounts,
+        writable_non_signers,
+        u8::try_from(keys).map_err(|_| too_large())?,
+    ];
+    for key in &message.account_keys {
+        data.extend_from_slice(key.as_ref());
+    }
+    data.push(u8::try_from(message.instructions.len()).map_err(|_| too_large())?);
+    for instruction in &message.instructions {
+        data.push(instruction.program_id_index);
+        data.push(u8::try_from(instruction.accounts.len()).map_err(|_| too_large())?);
+        data.extend_from_slice(&instruction.accounts);
+        let len = u16::try_from(instruction.data.len()).map_err(|_| too_large())?;
+        data.extend(len.to_le_bytes());
+        data.extend_from_slice(&instruction.data);
+    }
+    data.push(0); // no address table lookups
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::system_instruction;
+    use std::slice;
+
+    #[test]
+    fn transaction_message_of_a_vault_transfer() {
+        let multisig = Multisig {
+            address: Pubkey::new_unique(),
+            vault_index: 0,
+        };
+        let vault = multisig.vault();
+        let recipient = Pubkey::new_unique();
+        let transfer = system_instruction::transfer(&vault, &recipient, 5);
+        let data = transaction_message(&vault, slice::from_ref(&transfer)).unwrap();
+
+        // One signer, writable, one writable non-signer and three accounts in all
+        assert_eq!(data[..4], [1, 1, 1, 3]);
+        assert_eq!(data[4..36], vault.to_bytes());
+        assert_eq!(data[36..68], recipient.to_bytes());
+        assert_eq!(data[68..100], system_program::id().to_bytes());
+        // One instruction calling the system program with the vault and the recipient
+        assert_eq!(data[100..105], [1, 2, 2, 0, 1]);
+        let len = transfer.data.len();
+        assert_eq!(data[105..107], (len as u16).to_le_bytes());
+        assert_eq!(data[107..107 + len], transfer.data[..]);
+        // No address table lookups
+        assert_eq!(data[107 + len..], [0]);
+    }
+
+    #[test]
+    fn vault_is_derived_per_index() {
+        let address = Pubkey::new_unique();
+        let first = Multisig {
+            address,
+            vault_index: 0,
+        };
+        let second = Multisig {
+            address,
+            vault_index: 1,
+        };
+        assert_ne!(first.vault(), second.vault());
+        let (vault, _) = Pubkey::find_program_address(
+            &[b"multisig", address.as_ref(), b"vault", &[0]],
+            &PROGRAM_ID,
+        );
+        assert_eq!(first.vault(), vault);
+    }
+
+    #[test]
+    fn account_sizes_of_a_proposal() {
+        // A transfer compiles to 120 bytes, stored with 9 + 5 more for the u32 lengths
+        assert_eq!(vault_transaction_size(120, 1), 87 + 134);
+        assert_eq!(proposal_size(3), 58 + 3 * (4 + 96));
+    }
+
+    #[test]
+    fn instruction_data_starts_with_the_anchor_discriminator() {
+        let data = instruction_data("proposal_create", &[7, 0]);
+        let discriminator = &hash::hash(b"global:proposal_create").to_bytes()[..8];
+        assert_eq!(data[..8], *discriminator);
+        assert_eq!(data[8..], [7, 0]);
+    }
+}
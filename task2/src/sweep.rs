@@ -0,0 +1,62 @@
+use crate::{transfer_instructions, Transfer};
+use common::transaction::TransactionBuilder;
+use common::TaskResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use std::slice;
+
+/// Sets the amount of every transfer to all its source can send: the SOL balance minus the
+/// fee, and minus the rent-exempt minimum with `keep_rent_exempt`, or the whole token
+/// balance for token transfers. A multisig vault pays no fee, its member does.
+pub async fn set_amounts(
+    transfers: &mut [Transfer],
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+    keep_rent_exempt: bool,
+) -> TaskResult<()> {
+    let rent_exempt = if keep_rent_exempt {
+        client.get_minimum_balance_for_rent_exemption(0).await?
+    } else {
+        0
+    };
+    for transfer in transfers.iter_mut() {
+        let balance = balance(transfer, client).await?;
+        if transfer.mint.is_some() {
+            transfer.amount = balance;
+            continue;
+        }
+        let fee = source_fee(transfer, client, builder).await?;
+        transfer.amount = balance.saturating_sub(fee.saturating_add(rent_exempt));
+    }
+    Ok(())
+}
+
+/// The balance of the account a transfer sends from, in base units of the transferred
+/// token or in lamports.
+pub async fn balance(transfer: &Transfer, client: &RpcClient) -> TaskResult<u64> {
+    let source = transfer.source();
+    match transfer.mint {
+        Some(mint) => {
+            let token_account = mint.token_account(&source);
+            let balance = client.get_token_account_balance(&token_account).await?;
+            Ok(balance.amount.parse::<u64>().unwrap_or_default())
+        }
+        None => Ok(client.get_balance(&source).await?),
+    }
+}
+
+/// The fee the source of a SOL transfer pays for it, nothing when the source is a multisig
+/// vault whose member pays.
+pub async fn source_fee(
+    transfer: &Transfer,
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+) -> TaskResult<u64> {
+    let sender = transfer.sender.pubkey();
+    if transfer.source() != sender {
+        return Ok(0);
+    }
+    let instructions = transfer_instructions(slice::from_ref(transfer), client).await?;
+    builder
+        .fee(client, instructions, &sender, transfer.nonce.as_ref())
+        .await
+}
@@ -0,0 +1,199 @@
+use common::rpc::RetryConfig;
+use common::{TaskError, TaskResult};
+use futures::channel::mpsc;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::{Instant, Interval};
+use tonic::transport::channel::ClientTlsConfig;
+use tonic::Status;
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    CommitmentLevel as GeyserCommitmentLevel, SubscribeRequest, SubscribeRequestPing,
+    SubscribeUpdate,
+};
+
+type RequestSink = Pin<Box<dyn Sink<SubscribeRequest, Error = mpsc::SendError> + Send>>;
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+
+pub fn geyser_error(err: impl std::fmt::Display) -> TaskError {
+    TaskError::GeyserError(err.to_string())
+}
+
+/// Slot an update belongs to, for the updates that carry one.
+pub fn update_slot(update: &UpdateOneof) -> Option<u64> {
+    match update {
+        UpdateOneof::Account(account) => Some(account.slot),
+        UpdateOneof::Slot(slot) => Some(slot.slot),
+        UpdateOneof::Transaction(transaction) => Some(transaction.slot),
+        UpdateOneof::TransactionStatus(status) => Some(status.slot),
+        UpdateOneof::Block(block) => Some(block.slot),
+        UpdateOneof::BlockMeta(block_meta) => Some(block_meta.slot),
+        UpdateOneof::Entry(entry) => Some(entry.slot),
+        UpdateOneof::Ping(_) | UpdateOneof::Pong(_) => None,
+    }
+}
+
+// Geyser commitment level matching an RPC commitment
+fn commitment_filter(commitment: CommitmentConfig) -> i32 {
+    let level = match commitment.commitment {
+        CommitmentLevel::Processed => GeyserCommitmentLevel::Processed,
+        CommitmentLevel::Confirmed => GeyserCommitmentLevel::Confirmed,
+        CommitmentLevel::Finalized => GeyserCommitmentLevel::Finalized,
+    };
+    level as i32
+}
+
+// A request that only carries a ping, which leaves the subscription filters untouched
+fn ping_request() -> SubscribeRequest {
+    SubscribeRequest {
+        ping: Some(SubscribeRequestPing { id: 1 }),
+        ..SubscribeRequest::default()
+    }
+}
+
+// Completes on the next keepalive tick, never when keepalive is off
+async fn keepalive_tick(keepalive: &mut Option<Interval>) {
+    match keepalive {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Geyser subscription that survives stream failures: it reconnects with backoff and
+/// subscribes again from the last slot it saw, so no update is missed in between. It gives
+/// up after `retry.max_attempts` failures in a row.
+///
+/// Pings from the server are answered, and with a keepalive interval the client pings the
+/// server on its own, so that providers do not drop the stream as idle.
+pub struct Subscription {
+    url: String,
+    token: String,
+    retry: RetryConfig,
+    request: SubscribeRequest,
+    keepalive_interval: Option<Duration>,
+    keepalive: Option<Interval>,
+    last_slot: Option<u64>,
+    stream: Option<(RequestSink, UpdateStream)>,
+    reconnects: u64,
+    last_error: Option<String>,
+}
+
+impl Subscription {
+    pub fn new(
+        url: String,
+        token: String,
+        retry: RetryConfig,
+        mut request: SubscribeRequest,
+        commitment: CommitmentConfig,
+        keepalive_interval: Option<Duration>,
+    ) -> Self {
+        request.commitment = Some(commitment_filter(commitment));
+        Self {
+            url,
+            token,
+            retry,
+            request,
+            keepalive_interval,
+            keepalive: keepalive_interval
+                .map(|period| tokio::time::interval_at(Instant::now() + period, period)),
+            last_slot: None,
+            stream: None,
+            reconnects: 0,
+            last_error: None,
+        }
+    }
+
+    async fn connect(&mut self) -> TaskResult<(RequestSink, UpdateStream)> {
+        let tls_config = ClientTlsConfig::new().with_native_roots();
+        let mut builder = GeyserGrpcClient::build_from_shared(self.url.clone())
+            .map_err(geyser_error)?
+            .x_token(Some(self.token.clone()))
+            .map_err(geyser_error)?
+            .tls_config(tls_config)
+            .map_err(geyser_error)?;
+        if let Some(interval) = self.keepalive_interval {
+            builder = builder
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(true);
+        }
+        let mut client = builder.connect().await.map_err(geyser_error)?;
+
+        let mut request = self.request.clone();
+        request.from_slot = self.last_slot;
+        let (sink, stream) = client
+            .subscribe_with_request(Some(request))
+            .await
+            .map_err(geyser_error)?;
+        Ok((Box::pin(sink), Box::pin(stream)))
+    }
+
+    /// Number of times the stream failed and was reconnected so far.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Why the stream last failed, if it ever did.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// Waits for the next update, reconnecting as often as needed.
+    pub async fn next(&mut self) -> TaskResult<UpdateOneof> {
+        let mut failures = 0;
+        loop {
+            let error = match &mut self.stream {
+                None => match self.connect().await {
+                    Ok(stream) => {
+                        self.stream = Some(stream);
+                        continue;
+                    }
+                    Err(e) => e,
+                },
+                Some((sink, stream)) => {
+                    let update = tokio::select! {
+                        update = stream.next() => update,
+                        _ = keepalive_tick(&mut self.keepalive) => {
+                            match sink.send(ping_request()).await {
+                                Ok(()) => continue,
+                                Err(e) => Some(Err(Status::unavailable(e.to_string()))),
+                            }
+                        }
+                    };
+                    match update {
+                        Some(Ok(update)) => match update.update_oneof {
+                            Some(UpdateOneof::Ping(_)) => match sink.send(ping_request()).await {
+                                Ok(()) => continue,
+                                Err(e) => geyser_error(e),
+                            },
+                            Some(UpdateOneof::Pong(_)) | None => continue,
+                            Some(update) => {
+                                if let Some(slot) = update_slot(&update) {
+                                    self.last_slot =
+                                        Some(self.last_slot.map_or(slot, |last| last.max(slot)));
+                                }
+                                return Ok(update);
+                            }
+                        },
+                        Some(Err(status)) => geyser_error(status),
+                        None => geyser_error("stream closed by the server"),
+                    }
+                }
+            };
+
+            self.stream = None;
+            self.reconnects += 1;
+            self.last_error = Some(error.to_string());
+            failures += 1;
+            if failures >= self.retry.max_attempts {
+                return Err(error);
+            }
+            tracing::warn!("{error}, reconnecting");
+            tokio::time::sleep(self.retry.backoff(failures)).await;
+        }
+    }
+}
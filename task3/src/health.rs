@@ -0,0 +1,144 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::get, Json, Router};
+use common::{TaskError, TaskResult};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+
+/// What `GET /status` reports about a daemon.
+#[derive(Serialize, Clone, Default)]
+struct Status {
+    healthy: bool,
+    uptime_secs: u64,
+    /// Last slot seen on the Geyser stream
+    last_slot: Option<u64>,
+    seconds_since_last_update: Option<u64>,
+    /// When the last transfer was confirmed, RFC 3339
+    last_transfer_time: Option<String>,
+    transfers_succeeded: u64,
+    transfers_failed: u64,
+    rpc_errors: u64,
+    geyser_reconnects: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    last_slot: Option<u64>,
+    last_update: Option<Instant>,
+    last_transfer: Option<SystemTime>,
+    transfers_succeeded: u64,
+    transfers_failed: u64,
+    rpc_errors: u64,
+    geyser_reconnects: u64,
+}
+
+/// Liveness of a task3 daemon, served on `GET /healthz` and `GET /status`. The daemon is
+/// healthy while the Geyser stream keeps delivering updates, so that an orchestrator
+/// restarts it once the stream goes quiet.
+pub struct Health {
+    started: Instant,
+    /// Longest time without a Geyser update before the daemon is unhealthy
+    stale_after: Duration,
+    counters: Mutex<Counters>,
+}
+
+impl Health {
+    pub fn new(stale_after: Duration) -> Self {
+        Health {
+            started: Instant::now(),
+            stale_after,
+            counters: Mutex::new(Counters::default()),
+        }
+    }
+
+    /// Records an update of the Geyser stream, and the reconnects it took so far.
+    pub fn observe(&self, slot: Option<u64>, reconnects: u64) {
+        let mut counters = self.lock();
+        counters.last_update = Some(Instant::now());
+        if let Some(slot) = slot {
+            counters.last_slot = Some(counters.last_slot.map_or(slot, |last| last.max(slot)));
+        }
+        counters.geyser_reconnects = reconnects;
+    }
+
+    /// Records the outcome of a transfer, like `Metrics::record`.
+    pub fn record(&self, outcome: Result<bool, &TaskError>) {
+        let mut counters = self.lock();
+        match outcome {
+            Ok(true) => {
+                counters.transfers_succeeded += 1;
+                counters.last_transfer = Some(SystemTime::now());
+            }
+            Ok(false) => counters.transfers_failed += 1,
+            Err(e) => {
+                counters.transfers_failed += 1;
+                if matches!(e, TaskError::RpcError(_)) {
+                    counters.rpc_errors += 1;
+                }
+            }
+        }
+    }
+
+    fn status(&self) -> Status {
+        let counters = self.lock();
+        // Before the first update the daemon gets the same grace period from its start
+        let quiet = counters.last_update.unwrap_or(self.started).elapsed();
+        Status {
+            healthy: quiet <= self.stale_after,
+            uptime_secs: self.started.elapsed().as_secs(),
+            last_slot: counters.last_slot,
+            seconds_since_last_update: counters
+                .last_update
+                .map(|update| update.elapsed().as_secs()),
+            last_transfer_time: counters.last_transfer.map(|time| {
+                let seconds = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO);
+                common::output::format_unix_time(seconds.as_secs() as i64)
+            }),
+            transfers_succeeded: counters.transfers_succeeded,
+            transfers_failed: counters.transfers_failed,
+            rpc_errors: counters.rpc_errors,
+            geyser_reconnects: counters.geyser_reconnects,
+        }
+    }
+
+    // The counters stay usable even if a holder of the lock panicked
+    fn lock(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Serves `GET /healthz` and `GET /status` at `addr` in the background.
+    pub async fn serve(self: Arc<Self>, addr: &str) -> TaskResult<()> {
+        let listener = TcpListener::bind(addr).await.map_err(|e| {
+            TaskError::ConfigError(format!("cannot listen for health checks on {addr}: {e}"))
+        })?;
+        let app = Router::new()
+            .route("/healthz", get(healthz_handler))
+            .route("/status", get(status_handler))
+            .with_state(self);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("health server stopped: {e}");
+            }
+        });
+        Ok(())
+    }
+}
+
+async fn healthz_handler(State(health): State<Arc<Health>>) -> impl IntoResponse {
+    let status = health.status();
+    match (status.healthy, status.seconds_since_last_update) {
+        (true, _) => (StatusCode::OK, "ok".to_string()),
+        (false, Some(seconds)) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("no Geyser update for {seconds}s"),
+        ),
+        (false, None) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "no Geyser update yet".to_string(),
+        ),
+    }
+}
+
+async fn status_handler(State(health): State<Arc<Health>>) -> Json<Status> {
+    Json(health.status())
+}
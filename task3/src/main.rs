@@ -1,139 +1,707 @@
+use clap::Parser;
+use common::alerts::{Alert, AlertConfig, FailedTransfer, Notifier};
+use common::amount::Amount;
+use common::blockhash::BlockhashCache;
+use common::compute_budget::ComputeBudgetConfig;
+use common::dashboard::Dashboard;
+use common::explorer::{Explorer, ExplorerLinks};
+use common::keys::KeySource;
+use common::labels::Labels;
+use common::nonce::{DurableNonce, NonceConfig};
+use common::rate_limit::RateLimitConfig;
+use common::recipients::{RecipientListsConfig, RecipientPolicy};
+use common::rpc::{Network, RetryConfig, RpcEndpoints, RpcTimeouts};
+use common::telemetry::{LogArgs, LogFormat, TelemetryConfig};
+use common::transaction::{SendConfig, Simulation, TransactionBuilder, TransactionConfig};
+use common::{TaskError, TaskResult};
+use geyser::Subscription;
+use health::Health;
+use metrics::Metrics;
+use reload::{Hangups, Settings};
 use serde::{Deserialize, Serialize};
 use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::{
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+    instruction::Instruction,
     pubkey::Pubkey,
     signature::{Keypair, Signature, Signer},
-    system_transaction,
-    transaction::{self, Transaction},
+    system_instruction, transaction,
 };
-use std::{collections::HashMap, error::Error, fs, str::FromStr};
-use tokio_stream::StreamExt;
-use tonic::transport::channel::ClientTlsConfig;
-use yellowstone_grpc_client::GeyserGrpcClient;
-use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
-use yellowstone_grpc_proto::geyser::{SubscribeRequest, SubscribeRequestFilterBlocksMeta};
+use std::{
+    process::ExitCode,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tpu::{TpuConfig, TpuSender};
+use tracing::{debug, error, info, warn};
+use trigger::{Budget, MinBalance, Throttle, Trigger};
+
+const DEFAULT_AMOUNT: u64 = 1_000_000; // 0.001 SOL in lamports
+const DEFAULT_HEALTH_ADDR: &str = "127.0.0.1:9185";
+// Seconds without a Geyser update before a daemon reports itself unhealthy
+const DEFAULT_HEALTH_STALE_SECS: u64 = 60;
+
+mod geyser;
+mod health;
+mod metrics;
+mod monitor;
+mod reload;
+mod tpu;
+mod trigger;
+
+#[derive(Parser, Debug)]
+#[command(
+    about = "Sends a SOL transfer every time Geyser reports a new block or a change of a watched account",
+    long_about = "Sends a SOL transfer every time Geyser reports a new block or a change of a watched account.\n\nSend SIGHUP to reload the amount, memo, throttle and limits from the config without restarting the stream."
+)]
+struct Args {
+    /// Path to the config file, YAML, TOML or JSON by its extension
+    #[arg(long, default_value = "config.yaml")]
+    config: String,
+    /// Profile from the `profiles` of the config to lay over its top-level settings
+    #[arg(long)]
+    profile: Option<String>,
+    /// Amount to send per transfer, overrides `amount` from the config. Integers are
+    /// lamports, decimals like `0.001` or `0.001 SOL` are whole SOL
+    #[arg(long)]
+    amount: Option<Amount>,
+    /// Commitment level transfers are confirmed at, instead of `commitment` from the config
+    /// or `finalized`
+    #[arg(long)]
+    commitment: Option<CommitmentConfig>,
+    /// RPC endpoint to use instead of `rpc_url` and `rpc_urls` from the config
+    #[arg(long)]
+    rpc_url: Option<String>,
+    /// Public cluster to use instead of the endpoints from the config, `--rpc-url` taking
+    /// precedence
+    #[arg(long, value_enum)]
+    network: Option<Network>,
+    /// Simulate the transfer on every trigger instead of sending it
+    #[arg(long)]
+    dry_run: bool,
+    /// Only print the transactions touching the sender, the recipient and
+    /// `monitor_accounts`, without sending anything
+    #[arg(long)]
+    monitor: bool,
+    /// Show a live dashboard of the run in the terminal instead of logging
+    #[arg(long, conflicts_with = "monitor")]
+    dashboard: bool,
+    /// Run as a service: serve `/healthz` and `/status` on `health_addr` for systemd or
+    /// Kubernetes, and keep going after a failed transfer instead of stopping
+    #[arg(long, conflicts_with_all = ["monitor", "dashboard"])]
+    daemon: bool,
+    /// Explorer logged signatures and addresses link to, overrides `explorer` from the
+    /// config. JSON logs carry no links
+    #[arg(long, value_enum)]
+    explorer: Option<Explorer>,
+    #[command(flatten)]
+    log: LogArgs,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 struct YamlFile {
-    rpc_url: String,
+    #[serde(flatten)]
+    rpc: RpcEndpoints,
+    /// Commitment level of queries, confirmations and subscriptions
+    #[serde(default)]
+    commitment: Option<CommitmentLevel>,
+    #[serde(default)]
+    amount: Option<Amount>,
     geyser_url: String,
     geyser_token: String,
-    sender_private_key: Vec<u8>,
+    sender_private_key: KeySource,
     recepient_pyblic_key: String,
+    #[serde(flatten)]
+    compute_budget: ComputeBudgetConfig,
+    #[serde(flatten)]
+    transaction: TransactionConfig,
+    #[serde(default)]
+    retry: RetryConfig,
+    #[serde(flatten)]
+    timeouts: RpcTimeouts,
+    #[serde(default)]
+    send: SendConfig,
+    #[serde(flatten)]
+    telemetry: TelemetryConfig,
+    /// Client-side limit on RPC requests per second
+    #[serde(default)]
+    rate_limit: Option<RateLimitConfig>,
+    /// Durable nonce account used instead of a recent blockhash
+    #[serde(default)]
+    nonce: Option<NonceConfig>,
+    /// Send transfers straight to the leaders over QUIC instead of through the RPC node,
+    /// ignoring `send`
+    #[serde(default)]
+    tpu: Option<TpuConfig>,
+    /// Send the transfer when this account changes instead of on every block
+    #[serde(default)]
+    trigger_account: Option<String>,
+    /// Further accounts whose transactions `--monitor` prints
+    #[serde(default)]
+    monitor_accounts: Vec<String>,
+    /// Seconds between keepalive pings on the Geyser stream, no pings are sent when unset
+    #[serde(default)]
+    geyser_keepalive_secs: Option<u64>,
+    /// Only send a transfer on every Nth block (or account change)
+    #[serde(default)]
+    trigger_every_n_blocks: Option<u64>,
+    /// Minimum time between two transfers, e.g. `30s` or `5m`
+    #[serde(
+        default,
+        deserialize_with = "common::config::deserialize_optional_duration"
+    )]
+    min_trigger_interval: Option<Duration>,
+    /// Skip triggered transfers while the sender holds less than this
+    #[serde(default)]
+    min_sender_balance: Option<Amount>,
+    /// Stop after sending this many transfers
+    #[serde(default)]
+    max_transfers: Option<u64>,
+    /// Stop before the total amount sent would exceed this
+    #[serde(default)]
+    max_total_lamports: Option<Amount>,
+    /// Lamports the sender keeps, e.g. to pay future fees. Transfers that would take it
+    /// lower are reduced, or skipped when nothing is left
+    #[serde(default)]
+    keep_minimum_lamports: Option<u64>,
+    #[serde(flatten)]
+    recipient_lists: RecipientListsConfig,
+    /// Labels of the sender and the recipient, by address
+    #[serde(default)]
+    labels: Labels,
+    /// SPL Memo attached to every transfer
+    #[serde(default)]
+    memo: Option<String>,
+    /// Address to serve Prometheus metrics on at `/metrics`, e.g. `127.0.0.1:9184`
+    #[serde(default)]
+    metrics_addr: Option<String>,
+    /// Explorer logged signatures and addresses link to, Solscan by default
+    #[serde(default)]
+    explorer: Option<Explorer>,
+    /// Address `--daemon` serves `/healthz` and `/status` on, `127.0.0.1:9185` by default
+    #[serde(default)]
+    health_addr: Option<String>,
+    /// Seconds without a Geyser update after which `/healthz` reports the daemon
+    /// unhealthy, 60 by default
+    #[serde(default)]
+    health_stale_secs: Option<u64>,
+    /// Webhooks told about failed transfers, Geyser disconnects and large runs
+    #[serde(default)]
+    alerts: AlertConfig,
 }
 
 struct Transfer {
     amount: u64,
     sender_keypair: Keypair,
     recepient_public_key: Pubkey,
+    nonce: Option<DurableNonce>,
+    memo: Option<String>,
 }
 
 struct TransferResult {
     from: String,
     to: String,
-    signature: Signature,
+    signature: Option<Signature>,
     status: Option<transaction::Result<()>>,
+    /// Set instead of the signature in dry-run mode
+    simulation: Option<Simulation>,
 }
 
+/// How transfers are sent.
+struct SendOptions<'a> {
+    /// Simulate the transfers instead of sending them
+    dry_run: bool,
+    send: &'a SendConfig,
+    tpu: Option<&'a TpuSender>,
+    /// Longest wait for the confirmation of a transfer
+    confirmation_timeout: Option<Duration>,
+}
+
+// Function for building the transfer and memo instructions of a transfer of `amount`
+fn transfer_instructions(transfer: &Transfer, amount: u64) -> Vec<Instruction> {
+    let transfer_instruction = system_instruction::transfer(
+        &transfer.sender_keypair.pubkey(),
+        &transfer.recepient_public_key,
+        amount,
+    );
+    let mut instructions = vec![transfer_instruction];
+    if let Some(memo) = &transfer.memo {
+        instructions.push(spl_memo::build_memo(memo.as_bytes(), &[]));
+    }
+    instructions
+}
+
+// Function for working out how much of the transfer can be sent without taking the sender
+// below `floor` once the fee is paid. Returns it with the balance of the sender
+async fn floor_amount(
+    transfer: &Transfer,
+    client: &RpcClient,
+    builder: &TransactionBuilder,
+    floor: u64,
+) -> TaskResult<(u64, u64)> {
+    let payer = transfer.sender_keypair.pubkey();
+    let balance = client
+        .get_balance_with_commitment(&payer, client.commitment())
+        .await?
+        .value;
+    let instructions = transfer_instructions(transfer, transfer.amount);
+    let fee = builder
+        .fee(client, instructions, &payer, transfer.nonce.as_ref())
+        .await?;
+    let available = balance.saturating_sub(floor).saturating_sub(fee);
+    Ok((transfer.amount.min(available), balance))
+}
+
+#[tracing::instrument(skip_all, fields(amount = amount, to = %transfer.recepient_public_key))]
 async fn make_transfer(
     transfer: &Transfer,
+    amount: u64,
     client: &RpcClient,
-) -> Result<TransferResult, Box<dyn Error>> {
-    let latest_blockhash = client
-        .get_latest_blockhash()
-        .await
-        .expect("Failed to get latest blockhash");
-
-    let tx: Transaction = system_transaction::transfer(
-        &transfer.sender_keypair,
-        &transfer.recepient_public_key,
-        transfer.amount,
-        latest_blockhash,
-    );
+    builder: &TransactionBuilder,
+    options: &SendOptions<'_>,
+) -> TaskResult<TransferResult> {
+    let tx = builder
+        .build(
+            client,
+            transfer_instructions(transfer, amount),
+            &transfer.sender_keypair.pubkey(),
+            &[&transfer.sender_keypair],
+            transfer.nonce.as_ref(),
+        )
+        .await?;
+
+    if options.dry_run {
+        let simulation = common::transaction::simulate(client, &tx).await?;
+        return Ok(TransferResult {
+            from: transfer.sender_keypair.pubkey().to_string(),
+            to: transfer.recepient_public_key.to_string(),
+            signature: None,
+            status: Some(simulation.status()),
+            simulation: Some(simulation),
+        });
+    }
 
     // Send the transaction
-    let signature = client
-        .send_and_confirm_transaction(&tx)
-        .await
-        .expect("Failed to send transaction");
+    let timeout = options.confirmation_timeout;
+    let signature = match options.tpu {
+        Some(tpu) => tpu.send_and_confirm(client, &tx, timeout).await?,
+        None => common::transaction::send_and_confirm(client, &tx, options.send, timeout).await?,
+    };
 
     // Get transaction processing stats
-    let tx_status = client
-        .get_signature_status(&signature)
-        .await
-        .expect("Failed to get transaction status");
+    let tx_status = client.get_signature_status(&signature).await?;
 
     Ok(TransferResult {
         from: transfer.sender_keypair.pubkey().to_string(),
         to: transfer.recepient_public_key.to_string(),
-        signature: signature,
+        signature: Some(signature),
         status: tx_status,
+        simulation: None,
     })
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    let config_str = fs::read_to_string("config.yaml").expect("The config YAML file is missing");
-    let config: YamlFile =
-        serde_yaml::from_str::<YamlFile>(&config_str).expect("Incorrect YAML format");
-    let sol_client = RpcClient::new(config.rpc_url);
-    let amount: u64 = 1_000_000; // 0.001 SOL in lamports
-    let transfer = Transfer {
-        amount: amount,
-        sender_keypair: Keypair::from_bytes(&config.sender_private_key)?,
-        recepient_public_key: Pubkey::from_str(&config.recepient_pyblic_key)?,
+// Posts an alert in the background, so slow webhooks do not hold up the stream
+fn send_alert(notifier: &Arc<Notifier>, alert: Alert) {
+    let notifier = notifier.clone();
+    tokio::spawn(async move { notifier.notify(&alert).await });
+}
+
+// Applies reloaded settings to the state of the trigger loop, all of them or none when the
+// throttle rejects its new limits
+fn apply_settings(
+    settings: &Settings,
+    transfer: &mut Transfer,
+    throttle: &mut Throttle,
+    min_balance: &mut Option<MinBalance>,
+    budget: &mut Budget,
+) -> TaskResult<()> {
+    throttle.reconfigure(
+        settings.trigger_every_n_blocks,
+        settings.min_trigger_interval,
+    )?;
+    transfer.amount = settings.amount;
+    transfer.memo = settings.memo.clone();
+    budget.set_limits(settings.max_transfers, settings.max_total_lamports);
+    match (min_balance.as_mut(), settings.min_sender_balance) {
+        (Some(min_balance), Some(minimum)) => min_balance.set_minimum(minimum),
+        (Some(_), None) => *min_balance = None,
+        // The wallet is not part of the stream, it is only added at start
+        (None, Some(_)) => warn!("min_sender_balance set after start needs a restart"),
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+async fn run(args: Args) -> TaskResult<()> {
+    let config: YamlFile = common::config::load(&args.config, args.profile.as_deref())?;
+    let log = LogArgs {
+        silent: args.dashboard,
+        ..args.log
+    };
+    let _telemetry = common::telemetry::init(&log, &config.telemetry, "task3")?;
+    let sender_keypair = config
+        .sender_private_key
+        .keypair()
+        .map_err(|e| TaskError::KeyError(format!("sender private key: {e}")))?;
+    let recepient_public_key = Pubkey::from_str(&config.recepient_pyblic_key)
+        .map_err(|e| TaskError::KeyError(format!("recepient public key: {e}")))?;
+    RecipientPolicy::load(&config.recipient_lists)?.check(&recepient_public_key)?;
+    let labels = config.labels.clone();
+    // Links are for people, JSON logs are left without them
+    let explorer = match (
+        log.log_format,
+        config.rpc.urls(args.rpc_url.clone(), args.network),
+    ) {
+        (LogFormat::Text, Ok(urls)) => ExplorerLinks::new(
+            args.explorer.or(config.explorer).unwrap_or_default(),
+            &urls[0],
+        ),
+        _ => None,
     };
 
-    let tls_config = ClientTlsConfig::new().with_native_roots();
-    let mut client = GeyserGrpcClient::build_from_shared(config.geyser_url)?
-        .x_token(Some(config.geyser_token))?
-        .tls_config(tls_config)?
-        .connect()
-        .await?;
+    let keepalive = config.geyser_keepalive_secs.map(Duration::from_secs);
+    let commitment = common::config::commitment(
+        args.commitment,
+        config.commitment,
+        CommitmentLevel::Finalized,
+    );
+
+    if args.monitor {
+        let mut accounts = vec![sender_keypair.pubkey(), recepient_public_key];
+        for account in &config.monitor_accounts {
+            accounts.push(
+                Pubkey::from_str(account).map_err(|e| {
+                    TaskError::KeyError(format!("monitored account {account}: {e}"))
+                })?,
+            );
+        }
+        let mut subscription = Subscription::new(
+            config.geyser_url,
+            config.geyser_token,
+            config.retry.clone(),
+            monitor::request(&accounts),
+            commitment,
+            keepalive,
+        );
+        return monitor::watch(&mut subscription, explorer.as_ref()).await;
+    }
 
-    let mut blocks_meta: HashMap<String, SubscribeRequestFilterBlocksMeta> = HashMap::new();
-    blocks_meta.insert("client".to_owned(), SubscribeRequestFilterBlocksMeta {});
-    let request: SubscribeRequest = SubscribeRequest {
-        slots: HashMap::default(),
-        accounts: HashMap::default(),
-        transactions: HashMap::default(),
-        transactions_status: HashMap::default(),
-        entry: HashMap::default(),
-        blocks: HashMap::default(),
-        blocks_meta: blocks_meta,
-        commitment: None,
-        accounts_data_slice: Vec::default(),
-        ping: None,
-        from_slot: None,
+    if config.tpu.is_some() && config.send.jito.is_some() {
+        return Err(TaskError::ConfigError(
+            "tpu and send.jito cannot be used together".to_string(),
+        ));
+    }
+    let metrics = match &config.metrics_addr {
+        Some(addr) => {
+            let metrics = Arc::new(Metrics::new()?);
+            metrics.clone().serve(addr).await?;
+            Some(metrics)
+        }
+        None => None,
+    };
+    let rpc_urls = config.rpc.urls(args.rpc_url, args.network)?;
+    let sol_client = Arc::new(common::rpc::new_client(
+        rpc_urls,
+        config.rpc.fan_out,
+        commitment,
+        &config.retry,
+        config.rate_limit.as_ref(),
+        &config.timeouts,
+        metrics.as_ref().map(Metrics::rpc_error_hook),
+    )?);
+    let builder = TransactionBuilder::new(
+        &sol_client,
+        config.compute_budget.clone(),
+        &config.transaction,
+    )
+    .await?
+    .with_send_config(&config.send)?
+    .with_blockhash_cache(BlockhashCache::new(sol_client.clone()).await?);
+    let tpu = match &config.tpu {
+        Some(tpu) if !args.dry_run => Some(TpuSender::connect(sol_client.clone(), tpu).await?),
+        _ => None,
     };
-    let (_, mut stream) = client.subscribe_with_request(Some(request)).await?;
-
-    // Listen for updates
-    while let Some(update) = stream.next().await {
-        match update {
-            Ok(msg) => {
-                if let Some(UpdateOneof::BlockMeta(_)) = msg.update_oneof {
-                    println!("New block meta found");
-                    let result = make_transfer(&transfer, &sol_client).await?;
-
-                    println!("{} -> {}", result.from, result.to);
-                    println!("Signature {}", result.signature);
-                    match result.status {
-                        Some(status_result) => match status_result {
-                            Ok(()) => println!("Transaction status is OK"),
-                            Err(e) => println!("Trasaction status got error: {}", e),
-                        },
-                        None => println!("Transaction has None status."),
+    let mut settings = Settings::from_config(&config, args.amount.as_ref())?;
+    let mut transfer = Transfer {
+        amount: settings.amount,
+        sender_keypair,
+        recepient_public_key,
+        nonce: config
+            .nonce
+            .as_ref()
+            .map(DurableNonce::from_config)
+            .transpose()?,
+        memo: settings.memo.clone(),
+    };
+    let mut trigger = Trigger::new(config.trigger_account.as_deref(), &sol_client).await?;
+    let mut throttle = Throttle::new(
+        settings.trigger_every_n_blocks,
+        settings.min_trigger_interval,
+    )?;
+    let mut min_balance = match settings.min_sender_balance {
+        Some(minimum) => {
+            Some(MinBalance::new(transfer.sender_keypair.pubkey(), minimum, &sol_client).await?)
+        }
+        None => None,
+    };
+    let mut budget = Budget::new(settings.max_transfers, settings.max_total_lamports);
+    let health = match args.daemon {
+        true => {
+            let stale_after = config
+                .health_stale_secs
+                .unwrap_or(DEFAULT_HEALTH_STALE_SECS);
+            let health = Arc::new(Health::new(Duration::from_secs(stale_after)));
+            let addr = config.health_addr.as_deref().unwrap_or(DEFAULT_HEALTH_ADDR);
+            health.clone().serve(addr).await?;
+            info!(addr, "serving /healthz and /status");
+            Some(health)
+        }
+        false => None,
+    };
+    let notifier = Arc::new(Notifier::new(&config.alerts, "task3")?);
+    let mut large_run_alerted = false;
+    let mut request = trigger.request();
+    if let Some(min_balance) = &min_balance {
+        min_balance.subscribe(&mut request);
+    }
+    if let Some(metrics) = &metrics {
+        metrics.subscribe(&mut request);
+    }
+
+    let mut subscription = Subscription::new(
+        config.geyser_url,
+        config.geyser_token,
+        config.retry.clone(),
+        request,
+        commitment,
+        keepalive,
+    );
+
+    let dashboard = if args.dashboard {
+        Some(Dashboard::start("task3")?)
+    } else {
+        None
+    };
+    if let Some((dashboard, _)) = &dashboard {
+        let accounts = vec![transfer.sender_keypair.pubkey(), recepient_public_key];
+        dashboard.watch_balances(sol_client.clone(), accounts);
+    }
+
+    let options = SendOptions {
+        dry_run: args.dry_run,
+        send: &config.send,
+        tpu: tpu.as_ref(),
+        confirmation_timeout: config.timeouts.confirmation_timeout,
+    };
+
+    // Listen for updates until the budget is spent
+    let mut hangups = Hangups::listen();
+    while budget.allows(transfer.amount) {
+        let reconnects = subscription.reconnects();
+        let update = tokio::select! {
+            update = subscription.next() => match update {
+                Ok(update) => update,
+                Err(e) => {
+                    let error = e.to_string();
+                    notifier.notify(&Alert::GeyserDisconnected { error, reconnected: false }).await;
+                    return Err(e);
+                }
+            },
+            () = hangups.recv() => {
+                let updated = common::config::load(&args.config, args.profile.as_deref())
+                    .and_then(|config| Settings::from_config(&config, args.amount.as_ref()));
+                let applied = updated.and_then(|updated| {
+                    apply_settings(
+                        &updated,
+                        &mut transfer,
+                        &mut throttle,
+                        &mut min_balance,
+                        &mut budget,
+                    )?;
+                    Ok(updated)
+                });
+                match applied {
+                    Ok(updated) if updated == settings => info!("config reloaded, nothing changed"),
+                    Ok(updated) => {
+                        info!(?updated, "config reloaded");
+                        settings = updated;
                     }
-                    println!("--------------------------------------------------------------------------------------\n")
+                    Err(e) => error!(error = %e, "config not reloaded, keeping the previous settings"),
                 }
+                continue;
             }
-            Err(error) => {
-                println!("Error: {error:?}");
-                break;
+        };
+        if subscription.reconnects() > reconnects {
+            let error = subscription.last_error().unwrap_or_default().to_string();
+            send_alert(
+                &notifier,
+                Alert::GeyserDisconnected {
+                    error,
+                    reconnected: true,
+                },
+            );
+        }
+        if let Some(metrics) = &metrics {
+            metrics.observe(&update);
+        }
+        if let Some(health) = &health {
+            health.observe(geyser::update_slot(&update), subscription.reconnects());
+        }
+        if let (Some((dashboard, _)), Some(slot)) = (&dashboard, geyser::update_slot(&update)) {
+            dashboard.stream_update(slot, subscription.reconnects());
+        }
+        if let Some(min_balance) = &mut min_balance {
+            min_balance.observe(&update);
+        }
+        let Some(event) = trigger.check(&update) else {
+            continue;
+        };
+        if !throttle.allow() {
+            continue;
+        }
+        if let Some(reason) = min_balance.as_ref().and_then(MinBalance::check) {
+            info!(%event, %reason, "transfer skipped");
+            continue;
+        }
+        let amount = match settings.keep_minimum_lamports {
+            Some(floor) => {
+                let (amount, balance) =
+                    floor_amount(&transfer, &sol_client, &builder, floor).await?;
+                if amount == 0 {
+                    info!(%event, reason = "FloorProtected", balance, floor, "transfer skipped");
+                    continue;
+                }
+                if amount < transfer.amount {
+                    warn!(
+                        balance,
+                        floor,
+                        lowered = amount,
+                        "transfer lowered to keep the minimum balance"
+                    );
+                }
+                amount
+            }
+            None => transfer.amount,
+        };
+        info!(%event, "triggered");
+        let (from, to) = (
+            labels.display(&transfer.sender_keypair.pubkey().to_string()),
+            labels.display(&recepient_public_key.to_string()),
+        );
+        if let Some((dashboard, _)) = &dashboard {
+            dashboard.sending(&from, &to, amount);
+        }
+        let start_time = Instant::now();
+        let result = make_transfer(&transfer, amount, &sol_client, &builder, &options).await;
+        if let Some((dashboard, _)) = &dashboard {
+            match &result {
+                Ok(result) => match (&result.status, result.signature) {
+                    (Some(Ok(())), signature) => dashboard.confirmed(
+                        &from,
+                        &to,
+                        amount,
+                        &signature.map_or("simulated".to_string(), |s| s.to_string()),
+                        start_time.elapsed(),
+                    ),
+                    (Some(Err(e)), _) => dashboard.failed(&from, &to, &e.to_string()),
+                    (None, _) => dashboard.failed(&from, &to, "status unknown"),
+                },
+                Err(e) => dashboard.failed(&from, &to, &e.to_string()),
+            }
+        }
+        if let (Some(metrics), false) = (&metrics, args.dry_run) {
+            let outcome = result
+                .as_ref()
+                .map(|result| matches!(result.status, Some(Ok(()))));
+            metrics.record(amount, start_time.elapsed(), outcome);
+        }
+        if let (Some(health), false) = (&health, args.dry_run) {
+            let outcome = result
+                .as_ref()
+                .map(|result| matches!(result.status, Some(Ok(()))));
+            health.record(outcome);
+        }
+        if !args.dry_run {
+            let error = match &result {
+                Ok(result) => match &result.status {
+                    Some(Ok(())) => None,
+                    Some(Err(e)) => Some(e.to_string()),
+                    None => Some("status unknown".to_string()),
+                },
+                Err(e) => Some(e.to_string()),
+            };
+            if let Some(error) = error {
+                let failed = FailedTransfer {
+                    from: from.clone(),
+                    to: to.clone(),
+                    amount,
+                    error,
+                };
+                let alert = Alert::transfers_failed(vec![failed]);
+                match &result {
+                    // The run ends with the error, so the alert must go out before
+                    Err(_) if !args.daemon => notifier.notify(&alert).await,
+                    _ => send_alert(&notifier, alert),
+                }
+            }
+        }
+        let result = match result {
+            Ok(result) => result,
+            // A daemon reports the failure and waits for the next trigger
+            Err(e) if args.daemon => {
+                error!(from, to, error = %e, "transfer failed");
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        budget.record(amount, matches!(result.status, Some(Ok(()))));
+        if !large_run_alerted && !args.dry_run {
+            if let Some(alert) = notifier.large_run(budget.total_lamports) {
+                send_alert(&notifier, alert);
+                large_run_alerted = true;
+            }
+        }
+
+        let (from, to) = (labels.display(&result.from), labels.display(&result.to));
+        let signature = result.signature.map(|signature| signature.to_string());
+        let url = explorer
+            .as_ref()
+            .zip(signature.as_deref())
+            .map(|(links, signature)| links.transaction(signature));
+        if let Some(simulation) = &result.simulation {
+            info!(
+                from,
+                to,
+                compute_units = simulation.units_consumed,
+                "transfer simulated"
+            );
+            for log in &simulation.logs {
+                debug!("  {}", log);
             }
         }
+        match result.status {
+            Some(Ok(())) if result.simulation.is_some() => {}
+            Some(Ok(())) => info!(from, to, signature, url, "transfer confirmed"),
+            Some(Err(e)) => error!(from, to, signature, url, error = %e, "transaction failed"),
+            None => warn!(from, to, signature, url, "transaction status unknown"),
+        }
+        debug!(
+            from_url = explorer.as_ref().map(|links| links.account(&result.from)),
+            to_url = explorer.as_ref().map(|links| links.account(&result.to)),
+            "transfer accounts"
+        );
     }
 
+    info!(
+        transfers = budget.transfers,
+        total_lamports = budget.total_lamports,
+        "budget reached"
+    );
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    common::exit(run(Args::parse()).await)
+}
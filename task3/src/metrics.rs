@@ -0,0 +1,170 @@
+use axum::{extract::State, http::header, response::IntoResponse, routing::get, Router};
+use common::rpc::ErrorHook;
+use common::{TaskError, TaskResult};
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, Registry, TextEncoder};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpListener;
+use yellowstone_grpc_proto::geyser::{
+    subscribe_update::UpdateOneof, SubscribeRequest, SubscribeRequestFilterBlocksMeta,
+};
+
+/// Confirmation latency buckets in seconds, from a fast processed confirmation to a slow
+/// finalization
+const LATENCY_BUCKETS: [f64; 10] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 15.0, 30.0, 60.0, 120.0];
+
+/// Counters of a long-running task3, served in the Prometheus text format.
+pub struct Metrics {
+    registry: Registry,
+    pub transfers_attempted: IntCounter,
+    pub transfers_succeeded: IntCounter,
+    pub transfers_failed: IntCounter,
+    pub lamports_sent: IntCounter,
+    pub confirmation_seconds: Histogram,
+    pub rpc_errors: IntCounter,
+    geyser_slot: IntGauge,
+    geyser_lag_seconds: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> TaskResult<Self> {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str| -> TaskResult<IntCounter> {
+            let counter = IntCounter::new(name, help).map_err(metrics_error)?;
+            registry
+                .register(Box::new(counter.clone()))
+                .map_err(metrics_error)?;
+            Ok(counter)
+        };
+        let gauge = |name: &str, help: &str| -> TaskResult<IntGauge> {
+            let gauge = IntGauge::new(name, help).map_err(metrics_error)?;
+            registry
+                .register(Box::new(gauge.clone()))
+                .map_err(metrics_error)?;
+            Ok(gauge)
+        };
+        let confirmation_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "task3_confirmation_seconds",
+                "Time from sending a transfer to its confirmation",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+        )
+        .map_err(metrics_error)?;
+        registry
+            .register(Box::new(confirmation_seconds.clone()))
+            .map_err(metrics_error)?;
+
+        Ok(Self {
+            transfers_attempted: counter(
+                "task3_transfers_attempted_total",
+                "Transfers triggered and sent",
+            )?,
+            transfers_succeeded: counter(
+                "task3_transfers_succeeded_total",
+                "Transfers confirmed without error",
+            )?,
+            transfers_failed: counter(
+                "task3_transfers_failed_total",
+                "Transfers that failed to send or confirm",
+            )?,
+            lamports_sent: counter(
+                "task3_lamports_sent_total",
+                "Lamports moved by confirmed transfers",
+            )?,
+            rpc_errors: counter(
+                "task3_rpc_errors_total",
+                "Failed RPC requests, retried ones included",
+            )?,
+            geyser_slot: gauge("task3_geyser_slot", "Last slot seen on the Geyser stream")?,
+            geyser_lag_seconds: gauge(
+                "task3_geyser_lag_seconds",
+                "Delay between the production of the last block and its arrival on the Geyser stream",
+            )?,
+            confirmation_seconds,
+            registry,
+        })
+    }
+
+    /// A hook for the RPC client counting every failed request in `rpc_errors`.
+    pub fn rpc_error_hook(self: &Arc<Self>) -> ErrorHook {
+        let metrics = self.clone();
+        Arc::new(move |_| metrics.rpc_errors.inc())
+    }
+
+    /// Adds block metadata to the subscription, which the stream lag is measured on.
+    pub fn subscribe(&self, request: &mut SubscribeRequest) {
+        request
+            .blocks_meta
+            .insert("metrics".to_owned(), SubscribeRequestFilterBlocksMeta {});
+    }
+
+    /// Tracks the slot of a Geyser update, and the stream lag for block updates that carry
+    /// a block time.
+    pub fn observe(&self, update: &UpdateOneof) {
+        if let Some(slot) = crate::geyser::update_slot(update) {
+            if slot as i64 > self.geyser_slot.get() {
+                self.geyser_slot.set(slot as i64);
+            }
+        }
+        let UpdateOneof::BlockMeta(block_meta) = update else {
+            return;
+        };
+        if let Some(block_time) = &block_meta.block_time {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or(Duration::ZERO)
+                .as_secs() as i64;
+            self.geyser_lag_seconds
+                .set(now.saturating_sub(block_time.timestamp).max(0));
+        }
+    }
+
+    /// Records the outcome of a transfer of `lamports`, sent `elapsed` ago.
+    pub fn record(&self, lamports: u64, elapsed: Duration, outcome: Result<bool, &TaskError>) {
+        self.transfers_attempted.inc();
+        match outcome {
+            Ok(true) => {
+                self.transfers_succeeded.inc();
+                self.lamports_sent.inc_by(lamports);
+                self.confirmation_seconds.observe(elapsed.as_secs_f64());
+            }
+            Ok(false) | Err(_) => self.transfers_failed.inc(),
+        }
+    }
+
+    fn render(&self) -> TaskResult<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .map_err(metrics_error)?;
+        String::from_utf8(buffer).map_err(metrics_error)
+    }
+
+    /// Serves the metrics on `GET /metrics` at `addr` in the background.
+    pub async fn serve(self: Arc<Self>, addr: &str) -> TaskResult<()> {
+        let listener = TcpListener::bind(addr).await.map_err(|e| {
+            TaskError::ConfigError(format!("cannot listen for metrics on {addr}: {e}"))
+        })?;
+        let app = Router::new()
+            .route("/metrics", get(metrics_handler))
+            .with_state(self);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("metrics server stopped: {e}");
+            }
+        });
+        Ok(())
+    }
+}
+
+async fn metrics_handler(State(metrics): State<Arc<Metrics>>) -> impl IntoResponse {
+    match metrics.render() {
+        Ok(body) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(e) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+fn metrics_error(err: impl std::fmt::Display) -> TaskError {
+    TaskError::ConfigError(format!("metrics: {err}"))
+}
@@ -0,0 +1,84 @@
+use crate::geyser::Subscription;
+use common::explorer::ExplorerLinks;
+use common::TaskResult;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::collections::HashMap;
+use yellowstone_grpc_proto::convert_from::create_tx_error;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    SubscribeRequest, SubscribeRequestFilterTransactions, SubscribeUpdateTransaction,
+};
+
+/// Subscribes to the non-vote transactions touching any of `accounts`.
+pub fn request(accounts: &[Pubkey]) -> SubscribeRequest {
+    let mut transactions: HashMap<String, SubscribeRequestFilterTransactions> = HashMap::new();
+    transactions.insert(
+        "client".to_owned(),
+        SubscribeRequestFilterTransactions {
+            vote: Some(false),
+            failed: None,
+            signature: None,
+            account_include: accounts.iter().map(|account| account.to_string()).collect(),
+            account_exclude: Vec::default(),
+            account_required: Vec::default(),
+        },
+    );
+
+    SubscribeRequest {
+        slots: HashMap::default(),
+        accounts: HashMap::default(),
+        transactions,
+        transactions_status: HashMap::default(),
+        entry: HashMap::default(),
+        blocks: HashMap::default(),
+        blocks_meta: HashMap::default(),
+        commitment: None,
+        accounts_data_slice: Vec::default(),
+        ping: None,
+        from_slot: None,
+    }
+}
+
+fn print_transaction(update: &SubscribeUpdateTransaction, explorer: Option<&ExplorerLinks>) {
+    let Some(info) = &update.transaction else {
+        return;
+    };
+    let signature = Signature::try_from(info.signature.as_slice())
+        .map(|signature| signature.to_string())
+        .unwrap_or_else(|_| "<invalid signature>".to_string());
+    let status = match info
+        .meta
+        .as_ref()
+        .map(|meta| create_tx_error(meta.err.as_ref()))
+    {
+        Some(Ok(None)) => "OK".to_string(),
+        Some(Ok(Some(err))) => format!("error: {err}"),
+        Some(Err(err)) => format!("error: {err}"),
+        None => "unknown".to_string(),
+    };
+    match explorer {
+        Some(links) => println!(
+            "slot {} signature {} status {} {}",
+            update.slot,
+            signature,
+            status,
+            links.transaction(&signature)
+        ),
+        None => println!(
+            "slot {} signature {} status {}",
+            update.slot, signature, status
+        ),
+    }
+}
+
+/// Prints every non-vote transaction the subscription reports, with a link to `explorer`.
+pub async fn watch(
+    subscription: &mut Subscription,
+    explorer: Option<&ExplorerLinks>,
+) -> TaskResult<()> {
+    loop {
+        if let UpdateOneof::Transaction(transaction) = subscription.next().await? {
+            print_transaction(&transaction, explorer);
+        }
+    }
+}
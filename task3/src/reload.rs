@@ -0,0 +1,75 @@
+use crate::{YamlFile, DEFAULT_AMOUNT};
+use common::amount::Amount;
+use common::{TaskError, TaskResult};
+use std::time::Duration;
+
+/// Parameters of the trigger loop that can change while it runs: edit the config and send
+/// SIGHUP to the process. The Geyser stream, the keys and the trigger account need a
+/// restart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub amount: u64,
+    pub memo: Option<String>,
+    pub trigger_every_n_blocks: Option<u64>,
+    pub min_trigger_interval: Option<Duration>,
+    pub min_sender_balance: Option<u64>,
+    pub max_transfers: Option<u64>,
+    pub max_total_lamports: Option<u64>,
+    pub keep_minimum_lamports: Option<u64>,
+}
+
+impl Settings {
+    /// Reads the settings from the config, `amount` from the command line taking
+    /// precedence over the configured one.
+    pub fn from_config(config: &YamlFile, amount: Option<&Amount>) -> TaskResult<Self> {
+        let lamports = |amount: Option<&Amount>, name: &str| {
+            amount
+                .map(Amount::to_lamports)
+                .transpose()
+                .map_err(|e| TaskError::ConfigError(format!("{name}: {e}")))
+        };
+        Ok(Settings {
+            amount: lamports(amount.or(config.amount.as_ref()), "amount")?
+                .unwrap_or(DEFAULT_AMOUNT),
+            memo: config.memo.clone(),
+            trigger_every_n_blocks: config.trigger_every_n_blocks,
+            min_trigger_interval: config.min_trigger_interval,
+            min_sender_balance: lamports(config.min_sender_balance.as_ref(), "min_sender_balance")?,
+            max_transfers: config.max_transfers,
+            max_total_lamports: lamports(config.max_total_lamports.as_ref(), "max_total_lamports")?,
+            keep_minimum_lamports: config.keep_minimum_lamports,
+        })
+    }
+}
+
+/// Completes every time the process receives SIGHUP.
+pub struct Hangups {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+impl Hangups {
+    pub fn listen() -> Self {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+            let signal = signal(SignalKind::hangup())
+                .inspect_err(|e| tracing::warn!("cannot listen for SIGHUP: {e}"))
+                .ok();
+            Hangups { signal }
+        }
+        #[cfg(not(unix))]
+        Hangups {}
+    }
+
+    /// Waits for the next SIGHUP, forever where there is none.
+    pub async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = &mut self.signal {
+            if signal.recv().await.is_some() {
+                return;
+            }
+        }
+        std::future::pending().await
+    }
+}
@@ -0,0 +1,113 @@
+use common::transaction::{committed, signature_status};
+use common::{TaskError, TaskResult};
+use serde::{Deserialize, Serialize};
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::nonblocking::tpu_client::TpuClient;
+use solana_client::tpu_client::{TpuClientConfig, DEFAULT_FANOUT_SLOTS};
+use solana_quic_client::{QuicConfig, QuicConnectionManager, QuicPool};
+use solana_sdk::transaction::VersionedTransaction;
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info_span, warn, Instrument};
+
+/// Time between two sends of a transaction to the leaders while it has not landed
+const RESEND_INTERVAL: Duration = Duration::from_secs(2);
+
+fn default_fanout_slots() -> u64 {
+    DEFAULT_FANOUT_SLOTS
+}
+
+/// The `tpu` section of the config.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TpuConfig {
+    /// Pubsub endpoint the leader schedule is followed on, derived from the RPC URL when
+    /// unset
+    #[serde(default)]
+    pub ws_url: Option<String>,
+    /// How many upcoming leaders each transaction is sent to
+    #[serde(default = "default_fanout_slots")]
+    pub fanout_slots: u64,
+}
+
+/// Sends transactions straight to the TPU of the current and upcoming leaders over QUIC,
+/// skipping the `sendTransaction` hop through the RPC node. The RPC node is still used to
+/// find the leaders and to confirm.
+pub struct TpuSender {
+    client: TpuClient<QuicPool, QuicConnectionManager, QuicConfig>,
+}
+
+impl TpuSender {
+    pub async fn connect(rpc_client: Arc<RpcClient>, config: &TpuConfig) -> TaskResult<Self> {
+        let ws_url = match &config.ws_url {
+            Some(url) => url.clone(),
+            None => common::rpc::websocket_url(&rpc_client.url()),
+        };
+        let tpu_config = TpuClientConfig {
+            fanout_slots: config.fanout_slots,
+        };
+        let client = TpuClient::new("task3", rpc_client, &ws_url, tpu_config)
+            .await
+            .map_err(|e| tpu_error(e.to_string()))?;
+        Ok(Self { client })
+    }
+
+    /// Sends a signed transaction to the leaders until it reaches the commitment of
+    /// `client`, failing with [`TaskError::Expired`] once its blockhash expires before it has
+    /// landed, or after
+    /// `timeout` when one is given.
+    #[tracing::instrument(skip_all, fields(signature = %tx.signatures[0]))]
+    pub async fn send_and_confirm(
+        &self,
+        client: &RpcClient,
+        tx: &VersionedTransaction,
+        timeout: Option<Duration>,
+    ) -> TaskResult<Signature> {
+        let signature = tx.signatures[0];
+        let wire = bincode::serialize(tx).map_err(|e| TaskError::BuildError(e.to_string()))?;
+        self.client
+            .try_send_wire_transaction(wire.clone())
+            .instrument(info_span!("send"))
+            .await
+            .map_err(|e| tpu_error(e.to_string()))?;
+        let blockhash = common::transaction::expiry_blockhash(client, tx).await?;
+
+        let confirmed = async {
+            loop {
+                tokio::time::sleep(RESEND_INTERVAL).await;
+                match signature_status(client, &signature).await? {
+                    Some(status) if status.satisfies_commitment(client.commitment()) => {
+                        return committed(status, signature);
+                    }
+                    // Landed, so it can no longer expire and is not sent again
+                    Some(_) => continue,
+                    None => {}
+                }
+                if !client
+                    .is_blockhash_valid(&blockhash, CommitmentConfig::processed())
+                    .await?
+                    // It may have landed between the two checks
+                    && signature_status(client, &signature).await?.is_none()
+                {
+                    return Err(TaskError::Expired(signature));
+                }
+                // Leaders drop transactions they cannot schedule, nothing retries for us
+                if let Err(e) = self.client.try_send_wire_transaction(wire.clone()).await {
+                    warn!("cannot resend {signature} to the leaders: {e}");
+                }
+            }
+        }
+        .instrument(info_span!("confirm"));
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, confirmed)
+                .await
+                .unwrap_or(Err(TaskError::ConfirmationTimeout(signature))),
+            None => confirmed.await,
+        }
+    }
+}
+
+fn tpu_error(message: String) -> TaskError {
+    ClientError::from(ClientErrorKind::Custom(format!("TPU: {message}"))).into()
+}
@@ -0,0 +1,311 @@
+use common::{TaskError, TaskResult};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestFilterBlocksMeta,
+};
+
+/// What makes task3 send its transfer.
+pub enum Trigger {
+    /// Every new block. The last slot is kept so that blocks replayed after a reconnect
+    /// do not fire twice.
+    Block { last_slot: Option<u64> },
+    /// Every change of the lamports or data of an account. Geyser reports every write to
+    /// the account, so the last seen state is kept to ignore writes that change nothing.
+    Account {
+        pubkey: Pubkey,
+        state: Option<(u64, Vec<u8>)>,
+    },
+}
+
+impl Trigger {
+    /// Creates the trigger for `account`, or a block trigger when none is configured. The
+    /// current state of the account is fetched so that only later changes fire.
+    pub async fn new(account: Option<&str>, client: &RpcClient) -> TaskResult<Self> {
+        let Some(account) = account else {
+            return Ok(Self::Block { last_slot: None });
+        };
+        let pubkey = Pubkey::from_str(account)
+            .map_err(|e| TaskError::KeyError(format!("trigger account {account}: {e}")))?;
+        let state = client
+            .get_account_with_commitment(&pubkey, client.commitment())
+            .await?
+            .value
+            .map(|account| (account.lamports, account.data));
+
+        Ok(Self::Account { pubkey, state })
+    }
+
+    pub fn request(&self) -> SubscribeRequest {
+        let mut accounts: HashMap<String, SubscribeRequestFilterAccounts> = HashMap::new();
+        let mut blocks_meta: HashMap<String, SubscribeRequestFilterBlocksMeta> = HashMap::new();
+        match self {
+            Self::Block { .. } => {
+                blocks_meta.insert("client".to_owned(), SubscribeRequestFilterBlocksMeta {});
+            }
+            Self::Account { pubkey, .. } => {
+                accounts.insert(
+                    "client".to_owned(),
+                    SubscribeRequestFilterAccounts {
+                        account: vec![pubkey.to_string()],
+                        owner: Vec::default(),
+                        filters: Vec::default(),
+                        nonempty_txn_signature: None,
+                    },
+                );
+            }
+        }
+
+        SubscribeRequest {
+            slots: HashMap::default(),
+            accounts,
+            transactions: HashMap::default(),
+            transactions_status: HashMap::default(),
+            entry: HashMap::default(),
+            blocks: HashMap::default(),
+            blocks_meta,
+            commitment: None,
+            accounts_data_slice: Vec::default(),
+            ping: None,
+            from_slot: None,
+        }
+    }
+
+    /// Returns a description of the event when `update` should fire the transfer.
+    pub fn check(&mut self, update: &UpdateOneof) -> Option<String> {
+        match (self, update) {
+            (Self::Block { last_slot }, UpdateOneof::BlockMeta(block_meta)) => {
+                if last_slot.is_some_and(|last| block_meta.slot <= last) {
+                    return None;
+                }
+                *last_slot = Some(block_meta.slot);
+                Some("New block meta found".to_string())
+            }
+            (Self::Account { pubkey, state }, UpdateOneof::Account(update)) => {
+                let account = update
+                    .account
+                    .as_ref()
+                    .filter(|account| account.pubkey == pubkey.as_ref())?;
+                let current = (account.lamports, account.data.clone());
+                if state.as_ref() == Some(&current) {
+                    return None;
+                }
+                *state = Some(current);
+                Some(format!(
+                    "Account {} changed at slot {}, {} lamports",
+                    pubkey, update.slot, account.lamports
+                ))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Limits how often trigger events actually send a transfer: at least `every_n` events
+/// (blocks, for the block trigger) and `min_interval` must have passed since the last one.
+pub struct Throttle {
+    every_n: u64,
+    min_interval: Duration,
+    events: u64,
+    last_fired: Option<Instant>,
+}
+
+impl Throttle {
+    pub fn new(every_n: Option<u64>, min_interval: Option<Duration>) -> TaskResult<Self> {
+        if every_n == Some(0) {
+            return Err(TaskError::ConfigError(
+                "trigger_every_n_blocks must be at least 1".to_string(),
+            ));
+        }
+        Ok(Self {
+            every_n: every_n.unwrap_or(1),
+            min_interval: min_interval.unwrap_or_default(),
+            events: 0,
+            last_fired: None,
+        })
+    }
+
+    /// Changes the limits, keeping the events counted and the time of the last transfer.
+    pub fn reconfigure(
+        &mut self,
+        every_n: Option<u64>,
+        min_interval: Option<Duration>,
+    ) -> TaskResult<()> {
+        let updated = Self::new(every_n, min_interval)?;
+        self.every_n = updated.every_n;
+        self.min_interval = updated.min_interval;
+        Ok(())
+    }
+
+    /// Records a trigger event and returns whether it should send a transfer.
+    pub fn allow(&mut self) -> bool {
+        self.events += 1;
+        let interval_passed = self
+            .last_fired
+            .is_none_or(|last| last.elapsed() >= self.min_interval);
+        if self.events < self.every_n || !interval_passed {
+            return false;
+        }
+        self.events = 0;
+        self.last_fired = Some(Instant::now());
+        true
+    }
+}
+
+/// Balance a wallet must keep for transfers to be sent. The balance is fetched once and then
+/// kept up to date from the account updates of the subscription, so checking it is free.
+pub struct MinBalance {
+    pubkey: Pubkey,
+    minimum: u64,
+    balance: u64,
+}
+
+impl MinBalance {
+    pub async fn new(pubkey: Pubkey, minimum: u64, client: &RpcClient) -> TaskResult<Self> {
+        let balance = client
+            .get_balance_with_commitment(&pubkey, client.commitment())
+            .await?
+            .value;
+        Ok(Self {
+            pubkey,
+            minimum,
+            balance,
+        })
+    }
+
+    pub fn set_minimum(&mut self, minimum: u64) {
+        self.minimum = minimum;
+    }
+
+    /// Adds the wallet to the accounts the subscription reports.
+    pub fn subscribe(&self, request: &mut SubscribeRequest) {
+        request.accounts.insert(
+            "min_balance".to_owned(),
+            SubscribeRequestFilterAccounts {
+                account: vec![self.pubkey.to_string()],
+                owner: Vec::default(),
+                filters: Vec::default(),
+                nonempty_txn_signature: None,
+            },
+        );
+    }
+
+    pub fn observe(&mut self, update: &UpdateOneof) {
+        if let UpdateOneof::Account(update) = update {
+            if let Some(account) = &update.account {
+                if account.pubkey == self.pubkey.as_ref() {
+                    self.balance = account.lamports;
+                }
+            }
+        }
+    }
+
+    /// Returns why the transfer must be skipped when the balance is below the minimum.
+    pub fn check(&self) -> Option<String> {
+        (self.balance < self.minimum).then(|| {
+            format!(
+                "Sender balance {} lamports is below min_sender_balance {} lamports, skipping",
+                self.balance, self.minimum
+            )
+        })
+    }
+}
+
+/// Stop conditions of the run: it ends once `max_transfers` transfers were sent, or when the
+/// next transfer would take the total amount above `max_total_lamports`.
+#[derive(Default)]
+pub struct Budget {
+    max_transfers: Option<u64>,
+    max_total_lamports: Option<u64>,
+    pub transfers: u64,
+    pub total_lamports: u64,
+}
+
+impl Budget {
+    pub fn new(max_transfers: Option<u64>, max_total_lamports: Option<u64>) -> Self {
+        Self {
+            max_transfers,
+            max_total_lamports,
+            ..Self::default()
+        }
+    }
+
+    /// Changes the limits, keeping what was sent so far.
+    pub fn set_limits(&mut self, max_transfers: Option<u64>, max_total_lamports: Option<u64>) {
+        self.max_transfers = max_transfers;
+        self.max_total_lamports = max_total_lamports;
+    }
+
+    /// Whether one more transfer of `amount` lamports fits the budget.
+    pub fn allows(&self, amount: u64) -> bool {
+        self.max_transfers.is_none_or(|max| self.transfers < max)
+            && self
+                .max_total_lamports
+                .is_none_or(|max| self.total_lamports.saturating_add(amount) <= max)
+    }
+
+    /// Records a sent transfer, whose amount only counts when it succeeded.
+    pub fn record(&mut self, amount: u64, succeeded: bool) {
+        self.transfers += 1;
+        if succeeded {
+            self.total_lamports += amount;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttle_fires_every_n_events() {
+        let mut throttle = Throttle::new(Some(3), None).unwrap();
+        let fired: Vec<bool> = (0..7).map(|_| throttle.allow()).collect();
+        assert_eq!(fired, [false, false, true, false, false, true, false]);
+    }
+
+    #[test]
+    fn throttle_waits_for_the_interval() {
+        let mut throttle = Throttle::new(None, Some(Duration::from_secs(3600))).unwrap();
+        assert!(throttle.allow());
+        assert!(!throttle.allow());
+        assert!(!throttle.allow());
+
+        let mut throttle = Throttle::new(None, None).unwrap();
+        assert!((0..3).all(|_| throttle.allow()));
+    }
+
+    #[test]
+    fn throttle_rejects_zero_blocks() {
+        assert!(Throttle::new(Some(0), None).is_err());
+    }
+
+    #[test]
+    fn budget_limits_transfers() {
+        let mut budget = Budget::new(Some(2), None);
+        assert!(budget.allows(u64::MAX));
+        budget.record(10, true);
+        budget.record(10, false);
+        assert!(!budget.allows(1));
+    }
+
+    #[test]
+    fn budget_limits_the_total_amount() {
+        let mut budget = Budget::new(None, Some(100));
+        assert!(budget.allows(100));
+        assert!(!budget.allows(101));
+        budget.record(60, true);
+        assert!(budget.allows(40));
+        assert!(!budget.allows(41));
+        // Failed transfers do not use up the budget
+        budget.record(40, false);
+        assert!(budget.allows(40));
+        budget.record(40, true);
+        assert!(!budget.allows(1));
+        assert!(Budget::default().allows(u64::MAX));
+    }
+}